@@ -1,7 +1,9 @@
 use clap::Args;
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 use url::Url;
 
+use xlayer_flashblocks::quota::{LagPolicy, SubscriptionLimits};
+use xlayer_legacy_rpc::{auth::parse_header, pool::EndpointSelection, routes::RoutingPolicy};
 use xlayer_monitor::FullLinkMonitorArgs;
 
 /// X Layer specific configuration flags
@@ -32,6 +34,62 @@ pub struct XLayerArgs {
     )]
     pub flashblocks_subscription_max_addresses: usize,
 
+    /// Maximum number of flashblocks pubsub subscriptions per connection, 0 disables the limit
+    #[arg(
+        long = "xlayer.flashblocks-subscription-max-per-connection",
+        value_name = "COUNT",
+        default_value = "128"
+    )]
+    pub flashblocks_subscription_max_per_connection: usize,
+
+    /// Maximum number of flashblocks pubsub subscriptions in total, 0 disables the limit
+    #[arg(
+        long = "xlayer.flashblocks-subscription-max-total",
+        value_name = "COUNT",
+        default_value = "10000"
+    )]
+    pub flashblocks_subscription_max_total: usize,
+
+    /// Number of events queued per flashblocks subscription for slow clients
+    #[arg(
+        long = "xlayer.flashblocks-subscription-queue-size",
+        value_name = "COUNT",
+        default_value = "1024"
+    )]
+    pub flashblocks_subscription_queue_size: usize,
+
+    /// What to do once a subscription queue is full: drop-oldest or disconnect
+    #[arg(
+        long = "xlayer.flashblocks-subscription-lag-policy",
+        value_name = "POLICY",
+        default_value = "drop-oldest"
+    )]
+    pub flashblocks_subscription_lag_policy: LagPolicy,
+
+    /// Maximum number of blocks a logs subscription may replay from its fromBlock, 0 disables the limit
+    #[arg(
+        long = "xlayer.flashblocks-subscription-max-replay-blocks",
+        value_name = "COUNT",
+        default_value = "100000"
+    )]
+    pub flashblocks_subscription_max_replay_blocks: u64,
+
+    /// Maximum number of flashblocks subscriptions with state diffs or call traces in total, 0 disables the limit
+    #[arg(
+        long = "xlayer.flashblocks-subscription-max-execution",
+        value_name = "COUNT",
+        default_value = "100"
+    )]
+    pub flashblocks_subscription_max_execution: usize,
+
+    /// Number of recent heights buffered for flashblocks subscriptions resuming from a cursor
+    #[arg(
+        long = "xlayer.flashblocks-subscription-max-resume-blocks",
+        value_name = "COUNT",
+        default_value = "16"
+    )]
+    pub flashblocks_subscription_max_resume_blocks: usize,
+
     #[arg(
         long = "xlayer.sequencer-mode",
         help = "Enable sequencer mode for the node (default: false, i.e., RPC mode). This flag can be used by various business logic components to determine node behavior.",
@@ -45,9 +103,33 @@ impl XLayerArgs {
     pub fn validate(&self) -> Result<(), String> {
         self.legacy.validate()?;
         self.monitor.validate()?;
+        if self.enable_flashblocks_subscription && self.flashblocks_subscription_queue_size == 0 {
+            return Err("Flashblocks subscription queue size must be greater than zero".to_string());
+        }
+        if self.enable_flashblocks_subscription
+            && self.flashblocks_subscription_max_resume_blocks == 0
+        {
+            return Err(
+                "Flashblocks subscription max resume blocks must be greater than zero".to_string()
+            );
+        }
         Ok(())
     }
 
+    /// Limits of the flashblocks pubsub subscriptions
+    pub fn flashblocks_subscription_limits(&self) -> SubscriptionLimits {
+        SubscriptionLimits {
+            max_addresses: self.flashblocks_subscription_max_addresses,
+            max_per_connection: self.flashblocks_subscription_max_per_connection,
+            max_total: self.flashblocks_subscription_max_total,
+            queue_size: self.flashblocks_subscription_queue_size,
+            lag_policy: self.flashblocks_subscription_lag_policy,
+            max_replay_blocks: self.flashblocks_subscription_max_replay_blocks,
+            max_execution: self.flashblocks_subscription_max_execution,
+            max_resume_blocks: self.flashblocks_subscription_max_resume_blocks,
+        }
+    }
+
     /// Validate init command arguments for xlayer-mainnet and xlayer-testnet
     ///
     /// If --chain=xlayer-mainnet or --chain=xlayer-testnet is specified in init command,
@@ -89,9 +171,24 @@ impl XLayerArgs {
 /// X Layer legacy RPC arguments
 #[derive(Debug, Clone, Args, PartialEq, Eq, Default)]
 pub struct LegacyRpcArgs {
-    /// Legacy RPC endpoint URL for routing historical data
-    #[arg(long = "rpc.legacy-url", value_name = "URL")]
-    pub legacy_rpc_url: Option<String>,
+    /// Legacy RPC endpoint URLs for routing historical data (comma separated or repeated).
+    /// http(s):// URLs use HTTP, ws(s):// a WebSocket and ipc://<path> a Unix socket
+    #[arg(long = "rpc.legacy-url", value_name = "URL", value_delimiter = ',')]
+    pub legacy_rpc_urls: Vec<String>,
+
+    /// First block served by the local node, below which requests go to legacy.
+    /// Defaults to the genesis block number
+    #[arg(
+        long = "rpc.legacy-cutoff-block",
+        value_name = "BLOCK",
+        requires = "legacy_rpc_urls",
+        conflicts_with = "legacy_rpc_cutoff_auto"
+    )]
+    pub legacy_rpc_cutoff_block: Option<u64>,
+
+    /// Detect the cutoff at startup as the earliest block with local data and state
+    #[arg(long = "rpc.legacy-cutoff-auto", requires = "legacy_rpc_urls")]
+    pub legacy_rpc_cutoff_auto: bool,
 
     /// Timeout for legacy RPC requests
     #[arg(
@@ -99,18 +196,243 @@ pub struct LegacyRpcArgs {
         value_name = "DURATION",
         default_value = "30s",
         value_parser = humantime::parse_duration,
-        requires = "legacy_rpc_url"
+        requires = "legacy_rpc_urls"
     )]
     pub legacy_rpc_timeout: Duration,
+
+    /// Selection strategy across legacy endpoints: round-robin or latency
+    #[arg(
+        long = "rpc.legacy-selection",
+        value_name = "STRATEGY",
+        default_value = "round-robin",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_selection: EndpointSelection,
+
+    /// Interval between legacy endpoint health checks, 0s disables them
+    #[arg(
+        long = "rpc.legacy-health-check-interval",
+        value_name = "DURATION",
+        default_value = "10s",
+        value_parser = humantime::parse_duration,
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_health_check_interval: Duration,
+
+    /// Max size of the in-memory legacy response cache in MB, 0 disables caching
+    #[arg(
+        long = "rpc.legacy-cache-size-mb",
+        value_name = "MB",
+        default_value = "128",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_cache_size_mb: u64,
+
+    /// Directory persisting cached legacy responses across restarts
+    #[arg(long = "rpc.legacy-cache-dir", value_name = "PATH", requires = "legacy_rpc_urls")]
+    pub legacy_rpc_cache_dir: Option<PathBuf>,
+
+    /// Max size of the on-disk legacy response cache in MB, 0 disables the disk tier
+    #[arg(
+        long = "rpc.legacy-cache-disk-size-mb",
+        value_name = "MB",
+        default_value = "1024",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_cache_disk_size_mb: u64,
+
+    /// Block span of each chunk when eth_getLogs crosses the legacy cutoff, 0 disables chunking
+    #[arg(
+        long = "rpc.legacy-get-logs-chunk-size",
+        value_name = "BLOCKS",
+        default_value = "10000",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_get_logs_chunk_size: u64,
+
+    /// Max number of eth_getLogs chunks queried concurrently
+    #[arg(
+        long = "rpc.legacy-get-logs-concurrency",
+        value_name = "N",
+        default_value = "4",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_get_logs_concurrency: usize,
+
+    /// Max number of chunks an eth_getLogs crossing the legacy cutoff is split into, 0 is unlimited
+    #[arg(
+        long = "rpc.legacy-get-logs-max-chunks",
+        value_name = "N",
+        default_value = "10000",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_get_logs_max_chunks: usize,
+
+    /// Max number of logs returned when eth_getLogs crosses the legacy cutoff, 0 is unlimited
+    #[arg(
+        long = "rpc.legacy-get-logs-max-results",
+        value_name = "N",
+        default_value = "20000",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_get_logs_max_results: usize,
+
+    /// Static header sent to legacy endpoints in `Name: value` form (repeatable)
+    #[arg(
+        long = "rpc.legacy-header",
+        value_name = "HEADER",
+        env = "XLAYER_LEGACY_RPC_HEADER",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_headers: Vec<String>,
+
+    /// File holding a bearer token for legacy endpoints
+    #[arg(
+        long = "rpc.legacy-bearer-token-file",
+        value_name = "PATH",
+        env = "XLAYER_LEGACY_RPC_BEARER_TOKEN_FILE",
+        requires = "legacy_rpc_urls",
+        conflicts_with = "legacy_rpc_basic_auth"
+    )]
+    pub legacy_rpc_bearer_token_file: Option<PathBuf>,
+
+    /// Basic auth credentials for legacy endpoints in `user:password` form
+    #[arg(
+        long = "rpc.legacy-basic-auth",
+        value_name = "USER:PASSWORD",
+        env = "XLAYER_LEGACY_RPC_BASIC_AUTH",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_basic_auth: Option<String>,
+
+    /// PEM client certificate for mTLS with legacy endpoints, only supported
+    /// with http(s) endpoints
+    #[arg(
+        long = "rpc.legacy-tls-client-cert",
+        value_name = "PATH",
+        env = "XLAYER_LEGACY_RPC_TLS_CLIENT_CERT",
+        requires = "legacy_rpc_urls",
+        requires = "legacy_rpc_tls_client_key"
+    )]
+    pub legacy_rpc_tls_client_cert: Option<PathBuf>,
+
+    /// PEM private key of the mTLS client certificate
+    #[arg(
+        long = "rpc.legacy-tls-client-key",
+        value_name = "PATH",
+        env = "XLAYER_LEGACY_RPC_TLS_CLIENT_KEY",
+        requires = "legacy_rpc_tls_client_cert"
+    )]
+    pub legacy_rpc_tls_client_key: Option<PathBuf>,
+
+    /// Max outbound legacy calls per second, 0 is unlimited
+    #[arg(
+        long = "rpc.legacy-rate-limit",
+        value_name = "RPS",
+        default_value = "0",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_rate_limit: u32,
+
+    /// Burst capacity of the legacy rate limit, 0 uses the rate limit
+    #[arg(
+        long = "rpc.legacy-rate-limit-burst",
+        value_name = "N",
+        default_value = "0",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_rate_limit_burst: u32,
+
+    /// Retries of failed legacy calls for idempotent methods, 0 disables retrying
+    #[arg(
+        long = "rpc.legacy-max-retries",
+        value_name = "N",
+        default_value = "2",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_max_retries: u32,
+
+    /// Backoff before the first legacy retry, doubled on every further retry
+    #[arg(
+        long = "rpc.legacy-retry-backoff",
+        value_name = "DURATION",
+        default_value = "100ms",
+        value_parser = humantime::parse_duration,
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_retry_backoff: Duration,
+
+    /// Consecutive failures that open a legacy endpoint's circuit breaker, 0 disables it
+    #[arg(
+        long = "rpc.legacy-circuit-breaker-threshold",
+        value_name = "N",
+        default_value = "5",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_circuit_breaker_threshold: u32,
+
+    /// How long an open legacy circuit breaker fails fast before a trial request
+    #[arg(
+        long = "rpc.legacy-circuit-breaker-cooldown",
+        value_name = "DURATION",
+        default_value = "30s",
+        value_parser = humantime::parse_duration,
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_circuit_breaker_cooldown: Duration,
+
+    /// TOML or JSON file overriding the per-method legacy routing table
+    #[arg(long = "rpc.legacy-routing-policy", value_name = "PATH", requires = "legacy_rpc_urls")]
+    pub legacy_rpc_routing_policy: Option<PathBuf>,
+
+    /// Methods sent to both legacy and the local node to compare their responses
+    #[arg(
+        long = "rpc.legacy-shadow-methods",
+        value_name = "METHOD",
+        value_delimiter = ',',
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_shadow_methods: Vec<String>,
+
+    /// Blocks at or above the cutoff still shadowed, so the handover is compared too
+    #[arg(
+        long = "rpc.legacy-shadow-cutoff-window",
+        value_name = "BLOCKS",
+        default_value = "1000",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_shadow_cutoff_window: u64,
+
+    /// Max shadow comparisons in flight, further requests skip the comparison
+    #[arg(
+        long = "rpc.legacy-shadow-max-in-flight",
+        value_name = "COUNT",
+        default_value = "64",
+        requires = "legacy_rpc_urls"
+    )]
+    pub legacy_rpc_shadow_max_in_flight: usize,
 }
 
 impl LegacyRpcArgs {
     /// Validate legacy RPC configuration
     pub fn validate(&self) -> Result<(), String> {
-        if let Some(url_str) = &self.legacy_rpc_url {
+        if !self.legacy_rpc_urls.is_empty() {
             // Validate URL format
-            Url::parse(url_str)
-                .map_err(|e| format!("Invalid legacy RPC URL '{url_str}': {e:?}"))?;
+            for url_str in &self.legacy_rpc_urls {
+                let url = Url::parse(url_str)
+                    .map_err(|e| format!("Invalid legacy RPC URL '{url_str}': {e:?}"))?;
+
+                // The client identity is only presented over HTTP, a
+                // WebSocket or IPC endpoint would connect without it
+                if self.legacy_rpc_tls_client_cert.is_some()
+                    && !matches!(url.scheme(), "http" | "https")
+                {
+                    return Err(format!(
+                        "Legacy RPC TLS client cert is only supported with http(s) endpoints, got '{}'",
+                        url.scheme()
+                    ));
+                }
+            }
 
             // Validate timeout is reasonable (not zero and not excessively long)
             if self.legacy_rpc_timeout.is_zero() {
@@ -124,6 +446,27 @@ impl LegacyRpcArgs {
                     self.legacy_rpc_timeout
                 );
             }
+
+            for header in &self.legacy_rpc_headers {
+                parse_header(header)?;
+            }
+
+            let files = [
+                ("bearer token file", &self.legacy_rpc_bearer_token_file),
+                ("TLS client cert", &self.legacy_rpc_tls_client_cert),
+                ("TLS client key", &self.legacy_rpc_tls_client_key),
+            ];
+            for (name, path) in files {
+                if let Some(path) = path
+                    && !path.is_file()
+                {
+                    return Err(format!("Legacy RPC {name} '{}' does not exist", path.display()));
+                }
+            }
+
+            if let Some(path) = &self.legacy_rpc_routing_policy {
+                RoutingPolicy::from_file(path)?;
+            }
         }
 
         Ok(())
@@ -151,15 +494,16 @@ mod tests {
     #[test]
     fn test_legacy_rpc_disabled_by_default() {
         let args = LegacyRpcArgs::default();
-        assert!(args.legacy_rpc_url.is_none());
+        assert!(args.legacy_rpc_urls.is_empty());
         assert!(args.validate().is_ok());
     }
 
     #[test]
     fn test_legacy_rpc_valid_http_url() {
         let args = LegacyRpcArgs {
-            legacy_rpc_url: Some("http://localhost:8545".to_string()),
+            legacy_rpc_urls: vec!["http://localhost:8545".to_string()],
             legacy_rpc_timeout: Duration::from_secs(30),
+            ..Default::default()
         };
         assert!(args.validate().is_ok());
     }
@@ -167,8 +511,9 @@ mod tests {
     #[test]
     fn test_legacy_rpc_valid_https_url() {
         let args = LegacyRpcArgs {
-            legacy_rpc_url: Some("https://mainnet.infura.io/v3/YOUR-PROJECT-ID".to_string()),
+            legacy_rpc_urls: vec!["https://mainnet.infura.io/v3/YOUR-PROJECT-ID".to_string()],
             legacy_rpc_timeout: Duration::from_secs(30),
+            ..Default::default()
         };
         assert!(args.validate().is_ok());
     }
@@ -176,8 +521,9 @@ mod tests {
     #[test]
     fn test_legacy_rpc_valid_url_with_port() {
         let args = LegacyRpcArgs {
-            legacy_rpc_url: Some("http://192.168.1.100:8545".to_string()),
+            legacy_rpc_urls: vec!["http://192.168.1.100:8545".to_string()],
             legacy_rpc_timeout: Duration::from_secs(30),
+            ..Default::default()
         };
         assert!(args.validate().is_ok());
     }
@@ -185,8 +531,9 @@ mod tests {
     #[test]
     fn test_legacy_rpc_invalid_url_format() {
         let args = LegacyRpcArgs {
-            legacy_rpc_url: Some("not-a-valid-url".to_string()),
+            legacy_rpc_urls: vec!["not-a-valid-url".to_string()],
             legacy_rpc_timeout: Duration::from_secs(30),
+            ..Default::default()
         };
         let result = args.validate();
         assert!(result.is_err());
@@ -196,8 +543,9 @@ mod tests {
     #[test]
     fn test_legacy_rpc_empty_url() {
         let args = LegacyRpcArgs {
-            legacy_rpc_url: Some("".to_string()),
+            legacy_rpc_urls: vec!["".to_string()],
             legacy_rpc_timeout: Duration::from_secs(30),
+            ..Default::default()
         };
         let result = args.validate();
         assert!(result.is_err());
@@ -206,18 +554,34 @@ mod tests {
     #[test]
     fn test_legacy_rpc_invalid_scheme() {
         let args = LegacyRpcArgs {
-            legacy_rpc_url: Some("ftp://example.com".to_string()),
+            legacy_rpc_urls: vec!["ftp://example.com".to_string()],
             legacy_rpc_timeout: Duration::from_secs(30),
+            ..Default::default()
         };
         // This should pass validation (URL is valid, even if scheme is unusual)
         assert!(args.validate().is_ok());
     }
 
+    #[test]
+    fn test_legacy_rpc_ws_and_ipc_urls() {
+        let args = LegacyRpcArgs {
+            legacy_rpc_urls: vec![
+                "ws://localhost:8546".to_string(),
+                "wss://legacy.example.com".to_string(),
+                "ipc:///tmp/legacy.ipc".to_string(),
+            ],
+            legacy_rpc_timeout: Duration::from_secs(30),
+            ..Default::default()
+        };
+        assert!(args.validate().is_ok());
+    }
+
     #[test]
     fn test_legacy_rpc_zero_timeout() {
         let args = LegacyRpcArgs {
-            legacy_rpc_url: Some("http://localhost:8545".to_string()),
+            legacy_rpc_urls: vec!["http://localhost:8545".to_string()],
             legacy_rpc_timeout: Duration::from_secs(0),
+            ..Default::default()
         };
         let result = args.validate();
         assert!(result.is_err());
@@ -227,8 +591,9 @@ mod tests {
     #[test]
     fn test_legacy_rpc_reasonable_timeout() {
         let args = LegacyRpcArgs {
-            legacy_rpc_url: Some("http://localhost:8545".to_string()),
+            legacy_rpc_urls: vec!["http://localhost:8545".to_string()],
             legacy_rpc_timeout: Duration::from_secs(60),
+            ..Default::default()
         };
         assert!(args.validate().is_ok());
     }
@@ -244,7 +609,7 @@ mod tests {
         ])
         .args;
 
-        assert_eq!(args.legacy.legacy_rpc_url, Some("http://localhost:8545".to_string()));
+        assert_eq!(args.legacy.legacy_rpc_urls, vec!["http://localhost:8545".to_string()]);
         assert_eq!(args.legacy.legacy_rpc_timeout, Duration::from_secs(30));
         assert!(args.validate().is_ok());
     }
@@ -258,11 +623,301 @@ mod tests {
         ])
         .args;
 
-        assert_eq!(args.legacy.legacy_rpc_url, Some("http://localhost:8545".to_string()));
+        assert_eq!(args.legacy.legacy_rpc_urls, vec!["http://localhost:8545".to_string()]);
         assert_eq!(args.legacy.legacy_rpc_timeout, Duration::from_secs(30)); // default
         assert!(args.validate().is_ok());
     }
 
+    #[test]
+    fn test_legacy_rpc_parse_multiple_urls() {
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://node-a:8545,http://node-b:8545",
+            "--rpc.legacy-url",
+            "http://node-c:8545",
+            "--rpc.legacy-selection",
+            "latency",
+            "--rpc.legacy-health-check-interval",
+            "5s",
+        ])
+        .args;
+
+        assert_eq!(
+            args.legacy.legacy_rpc_urls,
+            vec![
+                "http://node-a:8545".to_string(),
+                "http://node-b:8545".to_string(),
+                "http://node-c:8545".to_string()
+            ]
+        );
+        assert_eq!(args.legacy.legacy_rpc_selection, EndpointSelection::Latency);
+        assert_eq!(args.legacy.legacy_rpc_health_check_interval, Duration::from_secs(5));
+        assert!(args.validate().is_ok());
+    }
+
+    #[test]
+    fn test_legacy_rpc_one_invalid_url_among_many() {
+        let args = LegacyRpcArgs {
+            legacy_rpc_urls: vec!["http://localhost:8545".to_string(), "invalid-url".to_string()],
+            legacy_rpc_timeout: Duration::from_secs(30),
+            ..Default::default()
+        };
+        let result = args.validate();
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("invalid-url"));
+    }
+
+    #[test]
+    fn test_legacy_rpc_parse_cache_options() {
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+        ])
+        .args;
+        assert_eq!(args.legacy.legacy_rpc_cache_size_mb, 128); // default
+        assert!(args.legacy.legacy_rpc_cache_dir.is_none());
+        assert_eq!(args.legacy.legacy_rpc_cache_disk_size_mb, 1024); // default
+
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+            "--rpc.legacy-cache-size-mb",
+            "0",
+            "--rpc.legacy-cache-dir",
+            "/data/legacy-cache",
+            "--rpc.legacy-cache-disk-size-mb",
+            "512",
+        ])
+        .args;
+        assert_eq!(args.legacy.legacy_rpc_cache_size_mb, 0);
+        assert_eq!(args.legacy.legacy_rpc_cache_dir, Some(PathBuf::from("/data/legacy-cache")));
+        assert_eq!(args.legacy.legacy_rpc_cache_disk_size_mb, 512);
+        assert!(args.validate().is_ok());
+    }
+
+    #[test]
+    fn test_legacy_rpc_parse_get_logs_options() {
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+            "--rpc.legacy-get-logs-chunk-size",
+            "2000",
+            "--rpc.legacy-get-logs-concurrency",
+            "8",
+            "--rpc.legacy-get-logs-max-chunks",
+            "100",
+            "--rpc.legacy-get-logs-max-results",
+            "5000",
+        ])
+        .args;
+        assert_eq!(args.legacy.legacy_rpc_get_logs_chunk_size, 2000);
+        assert_eq!(args.legacy.legacy_rpc_get_logs_concurrency, 8);
+        assert_eq!(args.legacy.legacy_rpc_get_logs_max_chunks, 100);
+        assert_eq!(args.legacy.legacy_rpc_get_logs_max_results, 5000);
+        assert!(args.validate().is_ok());
+    }
+
+    #[test]
+    fn test_legacy_rpc_parse_auth_options() {
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+            "--rpc.legacy-header",
+            "X-Api-Key: secret",
+            "--rpc.legacy-header",
+            "X-Tenant: xlayer",
+            "--rpc.legacy-basic-auth",
+            "user:pass",
+        ])
+        .args;
+        assert_eq!(
+            args.legacy.legacy_rpc_headers,
+            vec!["X-Api-Key: secret".to_string(), "X-Tenant: xlayer".to_string()]
+        );
+        assert_eq!(args.legacy.legacy_rpc_basic_auth, Some("user:pass".to_string()));
+        assert!(args.validate().is_ok());
+
+        let result = CommandParser::<XLayerArgs>::try_parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+            "--rpc.legacy-basic-auth",
+            "user:pass",
+            "--rpc.legacy-bearer-token-file",
+            "/run/secrets/token",
+        ]);
+        assert!(result.is_err());
+
+        let result = CommandParser::<XLayerArgs>::try_parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+            "--rpc.legacy-tls-client-cert",
+            "/etc/legacy/client.pem",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_legacy_rpc_parse_resilience_options() {
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+        ])
+        .args;
+        assert_eq!(args.legacy.legacy_rpc_rate_limit, 0); // default
+        assert_eq!(args.legacy.legacy_rpc_max_retries, 2); // default
+        assert_eq!(args.legacy.legacy_rpc_retry_backoff, Duration::from_millis(100)); // default
+        assert_eq!(args.legacy.legacy_rpc_circuit_breaker_threshold, 5); // default
+        assert_eq!(args.legacy.legacy_rpc_circuit_breaker_cooldown, Duration::from_secs(30)); // default
+
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+            "--rpc.legacy-rate-limit",
+            "100",
+            "--rpc.legacy-rate-limit-burst",
+            "200",
+            "--rpc.legacy-max-retries",
+            "0",
+            "--rpc.legacy-retry-backoff",
+            "250ms",
+            "--rpc.legacy-circuit-breaker-threshold",
+            "3",
+            "--rpc.legacy-circuit-breaker-cooldown",
+            "1m",
+        ])
+        .args;
+        assert_eq!(args.legacy.legacy_rpc_rate_limit, 100);
+        assert_eq!(args.legacy.legacy_rpc_rate_limit_burst, 200);
+        assert_eq!(args.legacy.legacy_rpc_max_retries, 0);
+        assert_eq!(args.legacy.legacy_rpc_retry_backoff, Duration::from_millis(250));
+        assert_eq!(args.legacy.legacy_rpc_circuit_breaker_threshold, 3);
+        assert_eq!(args.legacy.legacy_rpc_circuit_breaker_cooldown, Duration::from_secs(60));
+        assert!(args.validate().is_ok());
+    }
+
+    #[test]
+    fn test_legacy_rpc_parse_cutoff_options() {
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+        ])
+        .args;
+        assert_eq!(args.legacy.legacy_rpc_cutoff_block, None); // default
+        assert!(!args.legacy.legacy_rpc_cutoff_auto); // default
+
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+            "--rpc.legacy-cutoff-block",
+            "42000000",
+        ])
+        .args;
+        assert_eq!(args.legacy.legacy_rpc_cutoff_block, Some(42_000_000));
+
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+            "--rpc.legacy-cutoff-auto",
+        ])
+        .args;
+        assert!(args.legacy.legacy_rpc_cutoff_auto);
+
+        let result = CommandParser::<XLayerArgs>::try_parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+            "--rpc.legacy-cutoff-block",
+            "42000000",
+            "--rpc.legacy-cutoff-auto",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_legacy_rpc_parse_shadow_options() {
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+        ])
+        .args;
+        assert!(args.legacy.legacy_rpc_shadow_methods.is_empty()); // default
+        assert_eq!(args.legacy.legacy_rpc_shadow_cutoff_window, 1000); // default
+        assert_eq!(args.legacy.legacy_rpc_shadow_max_in_flight, 64); // default
+
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--rpc.legacy-url",
+            "http://localhost:8545",
+            "--rpc.legacy-shadow-methods",
+            "eth_getBlockByNumber,eth_getTransactionReceipt",
+            "--rpc.legacy-shadow-cutoff-window",
+            "0",
+            "--rpc.legacy-shadow-max-in-flight",
+            "8",
+        ])
+        .args;
+        assert_eq!(
+            args.legacy.legacy_rpc_shadow_methods,
+            vec!["eth_getBlockByNumber".to_string(), "eth_getTransactionReceipt".to_string()]
+        );
+        assert_eq!(args.legacy.legacy_rpc_shadow_cutoff_window, 0);
+        assert_eq!(args.legacy.legacy_rpc_shadow_max_in_flight, 8);
+    }
+
+    #[test]
+    fn test_legacy_rpc_invalid_routing_policy() {
+        let args = LegacyRpcArgs {
+            legacy_rpc_urls: vec!["http://localhost:8545".to_string()],
+            legacy_rpc_timeout: Duration::from_secs(30),
+            legacy_rpc_routing_policy: Some(PathBuf::from("/nonexistent/routing.toml")),
+            ..Default::default()
+        };
+        assert!(args.validate().unwrap_err().contains("routing policy"));
+    }
+
+    #[test]
+    fn test_legacy_rpc_invalid_auth_options() {
+        let args = LegacyRpcArgs {
+            legacy_rpc_urls: vec!["http://localhost:8545".to_string()],
+            legacy_rpc_timeout: Duration::from_secs(30),
+            legacy_rpc_headers: vec!["missing-separator".to_string()],
+            ..Default::default()
+        };
+        assert!(args.validate().unwrap_err().contains("Invalid header"));
+
+        let args = LegacyRpcArgs {
+            legacy_rpc_urls: vec!["http://localhost:8545".to_string()],
+            legacy_rpc_timeout: Duration::from_secs(30),
+            legacy_rpc_bearer_token_file: Some(PathBuf::from("/nonexistent/token")),
+            ..Default::default()
+        };
+        assert!(args.validate().unwrap_err().contains("bearer token file"));
+
+        let cert = std::env::temp_dir().join("xlayer-legacy-rpc-client.pem");
+        std::fs::write(&cert, "").unwrap();
+        let args = LegacyRpcArgs {
+            legacy_rpc_urls: vec!["wss://localhost:8546".to_string()],
+            legacy_rpc_timeout: Duration::from_secs(30),
+            legacy_rpc_tls_client_cert: Some(cert.clone()),
+            legacy_rpc_tls_client_key: Some(cert),
+            ..Default::default()
+        };
+        assert!(args.validate().unwrap_err().contains("only supported with http(s)"));
+    }
+
     #[test]
     fn test_xlayer_args_with_valid_legacy_config() {
         let args = CommandParser::<XLayerArgs>::parse_from([
@@ -278,23 +933,82 @@ mod tests {
         .args;
 
         assert!(args.enable_flashblocks_subscription);
-        assert!(args.legacy.legacy_rpc_url.is_some());
+        assert_eq!(args.legacy.legacy_rpc_urls.len(), 1);
         assert_eq!(args.legacy.legacy_rpc_timeout, Duration::from_secs(45));
         assert_eq!(args.flashblocks_subscription_max_addresses, 2000);
         assert!(args.validate().is_ok());
     }
 
+    #[test]
+    fn test_flashblocks_subscription_limits() {
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--xlayer.flashblocks-subscription",
+            "--xlayer.flashblocks-subscription-max-per-connection",
+            "8",
+            "--xlayer.flashblocks-subscription-max-total",
+            "0",
+            "--xlayer.flashblocks-subscription-queue-size",
+            "64",
+            "--xlayer.flashblocks-subscription-lag-policy",
+            "disconnect",
+            "--xlayer.flashblocks-subscription-max-replay-blocks",
+            "500",
+            "--xlayer.flashblocks-subscription-max-execution",
+            "10",
+            "--xlayer.flashblocks-subscription-max-resume-blocks",
+            "64",
+        ])
+        .args;
+
+        let limits = args.flashblocks_subscription_limits();
+        assert_eq!(limits.max_addresses, 1000);
+        assert_eq!(limits.max_per_connection, 8);
+        assert_eq!(limits.max_total, 0);
+        assert_eq!(limits.queue_size, 64);
+        assert_eq!(limits.lag_policy, LagPolicy::Disconnect);
+        assert_eq!(limits.max_replay_blocks, 500);
+        assert_eq!(limits.max_execution, 10);
+        assert_eq!(limits.max_resume_blocks, 64);
+        assert!(args.validate().is_ok());
+
+        let defaults = CommandParser::<XLayerArgs>::parse_from(["reth"]).args;
+        assert_eq!(defaults.flashblocks_subscription_limits(), SubscriptionLimits::default());
+    }
+
+    #[test]
+    fn test_flashblocks_subscription_invalid_limits() {
+        let result = CommandParser::<XLayerArgs>::try_parse_from([
+            "reth",
+            "--xlayer.flashblocks-subscription-lag-policy",
+            "block",
+        ]);
+        assert!(result.is_err());
+
+        let args = CommandParser::<XLayerArgs>::parse_from([
+            "reth",
+            "--xlayer.flashblocks-subscription",
+            "--xlayer.flashblocks-subscription-queue-size",
+            "0",
+        ])
+        .args;
+        let result = args.validate();
+        assert!(result.unwrap_err().contains("queue size"));
+    }
+
     #[test]
     fn test_xlayer_args_with_invalid_legacy_url() {
         let args = XLayerArgs {
             legacy: LegacyRpcArgs {
-                legacy_rpc_url: Some("invalid-url".to_string()),
+                legacy_rpc_urls: vec!["invalid-url".to_string()],
                 legacy_rpc_timeout: Duration::from_secs(30),
+                ..Default::default()
             },
             monitor: FullLinkMonitorArgs::default(),
             enable_flashblocks_subscription: false,
             flashblocks_subscription_max_addresses: 1000,
             sequencer_mode: false,
+            ..Default::default()
         };
 
         let result = args.validate();
@@ -0,0 +1,108 @@
+//! Historical logs for subscription replays.
+//!
+//! Blocks below the legacy cutoff are fetched from the legacy endpoints and
+//! the rest from the local `eth_getLogs` implementation.
+use std::marker::PhantomData;
+
+use alloy_json_rpc::RpcObject;
+use alloy_rpc_types_eth::{Filter, Log};
+use jsonrpsee::types::ErrorObject;
+use reth::providers::BlockNumReader;
+use reth_rpc_eth_api::EthFilterApiServer;
+use reth_rpc_server_types::result::internal_rpc_err;
+use xlayer_flashblocks::replay::LogsBackfill;
+use xlayer_legacy_rpc::layer::LegacyRpcRouterLayer;
+
+/// [`LogsBackfill`] serving legacy history through the legacy router.
+pub(crate) struct XLayerLogsBackfill<P, F, T> {
+    provider: P,
+    filter_api: F,
+    /// Set if legacy routing is enabled
+    legacy: Option<LegacyRpcRouterLayer>,
+    _tx: PhantomData<fn() -> T>,
+}
+
+impl<P, F, T> XLayerLogsBackfill<P, F, T> {
+    pub(crate) fn new(provider: P, filter_api: F, legacy: Option<LegacyRpcRouterLayer>) -> Self {
+        Self { provider, filter_api, legacy, _tx: PhantomData }
+    }
+}
+
+#[async_trait::async_trait]
+impl<P, F, T> LogsBackfill for XLayerLogsBackfill<P, F, T>
+where
+    P: BlockNumReader + Send + Sync + 'static,
+    F: EthFilterApiServer<T>,
+    T: RpcObject,
+{
+    async fn latest_block(&self) -> Result<u64, ErrorObject<'static>> {
+        self.provider.best_block_number().map_err(|e| internal_rpc_err(e.to_string()))
+    }
+
+    async fn logs(&self, filter: Filter) -> Result<Vec<Log>, ErrorObject<'static>> {
+        let (Some(from_block), Some(to_block)) = (filter.get_from_block(), filter.get_to_block())
+        else {
+            return self.filter_api.logs(filter).await;
+        };
+
+        let (legacy_range, local_range) =
+            split_at_cutoff(from_block, to_block, self.legacy.as_ref().map(|l| l.cutoff_block()));
+
+        let mut logs = Vec::new();
+        if let (Some(legacy), Some((legacy_from, legacy_to))) = (&self.legacy, legacy_range) {
+            let legacy_filter = filter.clone().from_block(legacy_from).to_block(legacy_to);
+            let legacy_filter = serde_json::to_value(&legacy_filter)
+                .map_err(|e| internal_rpc_err(e.to_string()))?;
+            let legacy_logs = legacy.legacy_logs(legacy_filter).await?;
+            logs = serde_json::from_value(serde_json::Value::Array(legacy_logs))
+                .map_err(|e| internal_rpc_err(format!("Invalid legacy logs: {e}")))?;
+        }
+
+        if let Some((local_from, local_to)) = local_range {
+            logs.extend(
+                self.filter_api.logs(filter.from_block(local_from).to_block(local_to)).await?,
+            );
+        }
+        Ok(logs)
+    }
+}
+
+/// Block range of a [`LogsBackfill`] query.
+type BlockRange = (u64, u64);
+
+/// Splits `[from_block, to_block]` at the legacy cutoff, into the range served
+/// by the legacy endpoints and the range served locally.
+fn split_at_cutoff(
+    from_block: u64,
+    to_block: u64,
+    cutoff_block: Option<u64>,
+) -> (Option<BlockRange>, Option<BlockRange>) {
+    let Some(cutoff_block) = cutoff_block.filter(|cutoff| from_block < *cutoff) else {
+        return (None, (from_block <= to_block).then_some((from_block, to_block)));
+    };
+    let legacy = (from_block, to_block.min(cutoff_block - 1));
+    let local = (cutoff_block <= to_block).then_some((cutoff_block, to_block));
+    (Some(legacy), local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_at_cutoff() {
+        // No legacy routing
+        assert_eq!(split_at_cutoff(10, 20, None), (None, Some((10, 20))));
+
+        // Entirely above the cutoff
+        assert_eq!(split_at_cutoff(100, 200, Some(100)), (None, Some((100, 200))));
+
+        // Entirely below the cutoff
+        assert_eq!(split_at_cutoff(10, 20, Some(100)), (Some((10, 20)), None));
+        assert_eq!(split_at_cutoff(10, 99, Some(100)), (Some((10, 99)), None));
+
+        // Across the cutoff, the cutoff block itself is served locally
+        assert_eq!(split_at_cutoff(10, 100, Some(100)), (Some((10, 99)), Some((100, 100))));
+        assert_eq!(split_at_cutoff(0, 200, Some(100)), (Some((0, 99)), Some((100, 200))));
+    }
+}
@@ -0,0 +1,92 @@
+//! Detection of the legacy cutoff from the local database.
+//!
+//! A node restored from a snapshot may hold blocks without their state, or
+//! no blocks at all below some height. The cutoff is the earliest block for
+//! which both the block and its historical state are available locally.
+use alloy_primitives::Address;
+use reth::providers::{
+    AccountReader, BlockNumReader, ProviderError, ProviderResult, StateProviderFactory,
+};
+
+/// Returns the earliest block with local data and state.
+pub(crate) fn detect_cutoff_block<P>(provider: &P) -> ProviderResult<u64>
+where
+    P: BlockNumReader + StateProviderFactory,
+{
+    let earliest = provider.earliest_block_number()?;
+    let best = provider.best_block_number()?;
+    find_cutoff_block(earliest, best, |block| has_state(provider, block))
+}
+
+/// Bisects `[earliest, best]` for the earliest block with state.
+fn find_cutoff_block(
+    earliest: u64,
+    best: u64,
+    mut has_state: impl FnMut(u64) -> ProviderResult<bool>,
+) -> ProviderResult<u64> {
+    // State pruning only ever removes history below some block, so
+    // availability is monotonic and can be bisected.
+    let (mut low, mut high) = (earliest, best.max(earliest));
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if has_state(mid)? {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    Ok(low)
+}
+
+/// Returns true if the state at `block` can be read, false if it was pruned.
+fn has_state<P: StateProviderFactory>(provider: &P, block: u64) -> ProviderResult<bool> {
+    is_available(
+        provider
+            .history_by_block_number(block)
+            .and_then(|state| state.basic_account(&Address::ZERO)),
+    )
+}
+
+/// Maps a state lookup to whether the state is available. Only pruned
+/// history counts as missing state, any other error is returned.
+fn is_available<T>(lookup: ProviderResult<T>) -> ProviderResult<bool> {
+    match lookup {
+        Ok(_) => Ok(true),
+        Err(ProviderError::StateAtBlockPruned(_)) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// State lookup of a database with history pruned below `pruned_below`.
+    fn pruned_history(pruned_below: u64) -> impl FnMut(u64) -> ProviderResult<bool> {
+        move |block| {
+            is_available(if block < pruned_below {
+                Err(ProviderError::StateAtBlockPruned(block))
+            } else {
+                Ok(())
+            })
+        }
+    }
+
+    #[test]
+    fn test_detect_cutoff_block_with_pruned_history() {
+        assert_eq!(find_cutoff_block(0, 1_000, pruned_history(600)).unwrap(), 600);
+        assert_eq!(find_cutoff_block(100, 1_000, pruned_history(0)).unwrap(), 100);
+        // History pruned up to the head
+        assert_eq!(find_cutoff_block(0, 1_000, pruned_history(2_000)).unwrap(), 1_000);
+        // Empty database
+        assert_eq!(find_cutoff_block(0, 0, pruned_history(0)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_detect_cutoff_block_propagates_errors() {
+        let result = find_cutoff_block(0, 1_000, |_| {
+            is_available(Err::<(), _>(ProviderError::BestBlockNotFound))
+        });
+        assert!(matches!(result, Err(ProviderError::BestBlockNotFound)));
+    }
+}
@@ -1,8 +1,11 @@
 #![allow(missing_docs, rustdoc::missing_crate_level_docs)]
 
 mod args;
+mod backfill;
+mod cutoff;
 mod payload;
 
+use backfill::XLayerLogsBackfill;
 use payload::XLayerPayloadServiceBuilder;
 
 use args::XLayerArgs;
@@ -20,13 +23,17 @@ use reth::{
 use reth_node_api::FullNodeComponents;
 use reth_optimism_cli::Cli;
 use reth_optimism_node::OpNode;
+use reth_rpc_eth_api::RpcTransaction;
 use reth_rpc_server_types::RethRpcModule;
 use xlayer_builder::args::OpRbuilderArgs;
 
 use xlayer_chainspec::XLayerChainSpecParser;
 use xlayer_flashblocks::handler::FlashblocksService;
 use xlayer_flashblocks::subscription::FlashblocksPubSub;
-use xlayer_legacy_rpc::{layer::LegacyRpcRouterLayer, LegacyRpcRouterConfig};
+use xlayer_legacy_rpc::{
+    auth::LegacyAuthConfig, layer::LegacyRpcRouterLayer, policy::RetryPolicy,
+    pool::CircuitBreakerConfig, routes::RoutingPolicy, shadow::ShadowConfig, LegacyRpcRouterConfig,
+};
 use xlayer_monitor::{start_monitor_handle, RpcMonitorLayer, XLayerMonitor};
 use xlayer_rpc::xlayer_ext::{XlayerRpcExt, XlayerRpcExtApiServer};
 
@@ -85,11 +92,55 @@ fn main() {
             // Clone xlayer_args early to avoid partial move issues
             let xlayer_args = args.xlayer_args.clone();
 
+            let routing = match &xlayer_args.legacy.legacy_rpc_routing_policy {
+                Some(path) => RoutingPolicy::from_file(path).map_err(|e| eyre::eyre!(e))?,
+                None => RoutingPolicy::default(),
+            };
+
             let legacy_config = LegacyRpcRouterConfig {
-                enabled: xlayer_args.legacy.legacy_rpc_url.is_some(),
-                legacy_endpoint: xlayer_args.legacy.legacy_rpc_url.unwrap_or_default(),
-                cutoff_block: genesis_block,
+                enabled: !xlayer_args.legacy.legacy_rpc_urls.is_empty(),
+                legacy_endpoints: xlayer_args.legacy.legacy_rpc_urls.clone(),
+                selection: xlayer_args.legacy.legacy_rpc_selection,
+                health_check_interval: xlayer_args.legacy.legacy_rpc_health_check_interval,
+                cutoff_block: xlayer_args.legacy.legacy_rpc_cutoff_block.unwrap_or(genesis_block),
                 timeout: xlayer_args.legacy.legacy_rpc_timeout,
+                cache_max_bytes: xlayer_args
+                    .legacy
+                    .legacy_rpc_cache_size_mb
+                    .saturating_mul(1024 * 1024),
+                cache_dir: xlayer_args.legacy.legacy_rpc_cache_dir.clone(),
+                cache_disk_max_bytes: xlayer_args
+                    .legacy
+                    .legacy_rpc_cache_disk_size_mb
+                    .saturating_mul(1024 * 1024),
+                get_logs_chunk_size: xlayer_args.legacy.legacy_rpc_get_logs_chunk_size,
+                get_logs_max_concurrency: xlayer_args.legacy.legacy_rpc_get_logs_concurrency,
+                get_logs_max_chunks: xlayer_args.legacy.legacy_rpc_get_logs_max_chunks,
+                get_logs_max_results: xlayer_args.legacy.legacy_rpc_get_logs_max_results,
+                auth: LegacyAuthConfig {
+                    headers: xlayer_args.legacy.legacy_rpc_headers.clone(),
+                    bearer_token_file: xlayer_args.legacy.legacy_rpc_bearer_token_file.clone(),
+                    basic_auth: xlayer_args.legacy.legacy_rpc_basic_auth.clone(),
+                    tls_client_cert: xlayer_args.legacy.legacy_rpc_tls_client_cert.clone(),
+                    tls_client_key: xlayer_args.legacy.legacy_rpc_tls_client_key.clone(),
+                },
+                rate_limit: xlayer_args.legacy.legacy_rpc_rate_limit,
+                rate_limit_burst: xlayer_args.legacy.legacy_rpc_rate_limit_burst,
+                retry: RetryPolicy {
+                    max_retries: xlayer_args.legacy.legacy_rpc_max_retries,
+                    initial_backoff: xlayer_args.legacy.legacy_rpc_retry_backoff,
+                },
+                circuit_breaker: CircuitBreakerConfig {
+                    threshold: xlayer_args.legacy.legacy_rpc_circuit_breaker_threshold,
+                    cooldown: xlayer_args.legacy.legacy_rpc_circuit_breaker_cooldown,
+                },
+                routing,
+                shadow: ShadowConfig {
+                    methods: xlayer_args.legacy.legacy_rpc_shadow_methods.iter().cloned().collect(),
+                    cutoff_window: xlayer_args.legacy.legacy_rpc_shadow_cutoff_window,
+                    max_in_flight: xlayer_args.legacy.legacy_rpc_shadow_max_in_flight,
+                },
+                max_response_size: builder.config().rpc.rpc_max_response_size_bytes() as usize,
             };
 
             // For X Layer full link monitor
@@ -99,9 +150,11 @@ fn main() {
                 xlayer_args.sequencer_mode,
             );
 
+            let legacy_layer = LegacyRpcRouterLayer::new(legacy_config);
+
             let add_ons = op_node.add_ons().with_rpc_middleware((
-                RpcMonitorLayer::new(monitor.clone()),    // Execute first
-                LegacyRpcRouterLayer::new(legacy_config), // Execute second
+                RpcMonitorLayer::new(monitor.clone()), // Execute first
+                legacy_layer.clone(),                  // Execute second
             ));
 
             // Create the X Layer payload service builder
@@ -119,6 +172,12 @@ fn main() {
                 .extend_rpc_modules(move |ctx| {
                     let new_op_eth_api = Arc::new(ctx.registry.eth_api().clone());
 
+                    // Resolve the legacy cutoff before the RPC server starts serving
+                    if legacy_layer.is_enabled() && xlayer_args.legacy.legacy_rpc_cutoff_auto {
+                        let cutoff_block = cutoff::detect_cutoff_block(ctx.node().provider())?;
+                        legacy_layer.set_cutoff_block(cutoff_block);
+                    }
+
                     // Initialize flashblocks RPC service if not in flashblocks sequencer mode
                     if !args.node_args.flashblocks.enabled {
                         if let Some(flashblock_rx) = new_op_eth_api.subscribe_received_flashblocks()
@@ -136,13 +195,21 @@ fn main() {
                             && let Some(pending_blocks_rx) = new_op_eth_api.pending_block_rx()
                         {
                             let eth_pubsub = ctx.registry.eth_handlers().pubsub.clone();
+                            let logs_backfill =
+                                XLayerLogsBackfill::<_, _, RpcTransaction<Optimism>>::new(
+                                    ctx.node().provider().clone(),
+                                    ctx.registry.eth_handlers().filter.clone(),
+                                    legacy_layer.is_enabled().then(|| legacy_layer.clone()),
+                                );
 
                             let flashblocks_pubsub = FlashblocksPubSub::new(
                                 eth_pubsub,
                                 pending_blocks_rx,
+                                Arc::new(ctx.node().provider().clone()),
                                 Box::new(ctx.node().task_executor().clone()),
-                                new_op_eth_api.converter().clone(),
-                                xlayer_args.flashblocks_subscription_max_addresses,
+                                (*new_op_eth_api).clone(),
+                                xlayer_args.flashblocks_subscription_limits(),
+                                Some(Arc::new(logs_backfill)),
                             );
                             ctx.modules.add_or_replace_if_module_configured(
                                 RethRpcModule::Eth,
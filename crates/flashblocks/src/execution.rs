@@ -0,0 +1,343 @@
+//! Re-execution of pending blocks for state diffs and call traces.
+//!
+//! The execution output of a pending block only holds the state changes of
+//! the whole block, so its transactions are re-executed on top of the parent
+//! state with a call tracer, recording the balance, nonce and storage changes
+//! each one makes, and its internal calls.
+//! Flashblocks only append transactions to a height, so the execution state is
+//! kept across the flashblocks of a height. A single task executes each pending
+//! block off the async runtime and shares the output with every subscription,
+//! which then picks the addresses and traces it asked for. Executed blocks are
+//! buffered for resumed subscriptions, and execution only runs while such
+//! subscriptions exist or for a few heights after the last one is gone, so
+//! a reconnecting subscription can resume.
+use alloy_consensus::{transaction::TxHashRef, BlockHeader as _};
+use alloy_primitives::{Address, TxHash, B256, U64};
+use alloy_rpc_types_trace::geth::{CallConfig, CallFrame};
+use eyre::WrapErr as _;
+use reth_evm::{execute::BlockExecutor as _, ConfigureEvm, Evm as _};
+use reth_optimism_flashblocks::{PendingBlockRx, PendingFlashBlock};
+use reth_primitives_traits::{NodePrimitives, Recovered, RecoveredBlock};
+use reth_revm::{database::StateProviderDatabase, State};
+use reth_storage_api::{StateProviderBox, StateProviderFactory};
+use reth_tracing::tracing::warn;
+use revm::{
+    context::result::ResultAndState,
+    state::{AccountInfo, EvmState},
+    Database, DatabaseCommit as _,
+};
+use revm_inspectors::tracing::{TracingInspector, TracingInspectorConfig};
+use std::{collections::HashSet, sync::Arc};
+use tokio::sync::watch;
+
+use crate::{
+    preconfirmed::block_tx_hashes,
+    pubsub::{AccountDiff, Delta, StateDiff},
+    resume::PendingBlockBuffer,
+};
+
+/// Heights still executed after the last subscription requiring execution is
+/// gone.
+const EXECUTION_KEEP_ALIVE_BLOCKS: u64 = 16;
+
+/// Output of a re-executed transaction.
+#[derive(Debug)]
+pub(crate) struct ExecutedTx {
+    tx_hash: TxHash,
+    /// Changes to every account touched by the transaction
+    state_diff: StateDiff,
+    /// Call trace
+    pub(crate) call_trace: CallFrame,
+    /// Targets of every call made by the transaction, including internal ones
+    pub(crate) call_targets: HashSet<Address>,
+}
+
+impl ExecutedTx {
+    /// Returns the changes made to the given addresses.
+    pub(crate) fn state_diff_of(&self, addresses: &HashSet<Address>) -> StateDiff {
+        self.state_diff
+            .iter()
+            .filter(|(address, _)| addresses.contains(*address))
+            .map(|(address, diff)| (*address, diff.clone()))
+            .collect()
+    }
+}
+
+/// Output of the executed transactions of a pending block, in block order.
+pub(crate) type ExecutedTxs = Arc<[Arc<ExecutedTx>]>;
+
+/// A pending block along with the output of its executed transactions. The
+/// output is empty if the execution failed, and the transactions are only
+/// streamed once a later flashblock of the height executes them.
+#[derive(Clone, Debug)]
+pub(crate) struct ExecutedPendingBlock<N: NodePrimitives> {
+    pub(crate) pending_block: PendingFlashBlock<N>,
+    pub(crate) executed: ExecutedTxs,
+}
+
+/// Pending blocks executed once for every subscription requiring execution.
+#[derive(Debug)]
+pub(crate) struct SharedExecution<N: NodePrimitives> {
+    /// Latest executed pending block, with a receiver per subscription
+    executed_tx: watch::Sender<Option<ExecutedPendingBlock<N>>>,
+    /// Recent executed pending blocks for resumed subscriptions
+    buffer: PendingBlockBuffer<ExecutedPendingBlock<N>>,
+}
+
+impl<N: NodePrimitives> SharedExecution<N> {
+    /// Creates the shared execution, buffering the latest `resume_blocks`
+    /// executed heights.
+    pub(crate) fn new(resume_blocks: usize) -> Self {
+        Self {
+            executed_tx: watch::Sender::new(None),
+            buffer: PendingBlockBuffer::new(resume_blocks),
+        }
+    }
+
+    /// Subscribes to the executed pending blocks, keeping execution running
+    /// until the receiver is dropped.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<Option<ExecutedPendingBlock<N>>> {
+        self.executed_tx.subscribe()
+    }
+
+    /// Returns the recent executed pending blocks.
+    pub(crate) const fn buffer(&self) -> &PendingBlockBuffer<ExecutedPendingBlock<N>> {
+        &self.buffer
+    }
+}
+
+/// Executes every pending block received while execution is needed, until
+/// the channel closes.
+pub(crate) async fn execute_pending_blocks<N, Evm, P>(
+    mut pending_block_rx: PendingBlockRx<N>,
+    evm_config: Evm,
+    provider: P,
+    shared: Arc<SharedExecution<N>>,
+) where
+    N: NodePrimitives,
+    Evm: ConfigureEvm<Primitives = N> + 'static,
+    P: StateProviderFactory + Clone + Send + Sync + 'static,
+{
+    let mut execution = None;
+    let mut executing = false;
+    // Last height seen with a subscription requiring execution
+    let mut last_subscribed = None;
+    loop {
+        let pending_block = pending_block_rx.borrow_and_update().clone();
+        if let Some(pending_block) = pending_block {
+            let block_number = pending_block.block().header().number();
+            if shared.executed_tx.receiver_count() > 0 {
+                last_subscribed = Some(block_number);
+            }
+            // Keep executing for a while, so a dropped subscription can
+            // resume without missing executed heights
+            let active = last_subscribed.is_some_and(|last: u64| {
+                block_number < last.saturating_add(EXECUTION_KEEP_ALIVE_BLOCKS)
+            });
+            if active {
+                let (exec, executed) =
+                    execute_off_runtime(execution, &evm_config, &provider, &pending_block).await;
+                execution = exec;
+                executing = true;
+                let executed = ExecutedPendingBlock { pending_block, executed };
+                shared.buffer.push(block_number, executed.clone());
+                shared.executed_tx.send_replace(Some(executed));
+            } else if std::mem::take(&mut executing) {
+                execution = None;
+                shared.buffer.clear();
+                shared.executed_tx.send_replace(None);
+            }
+        }
+        if pending_block_rx.changed().await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Executes the pending block on the blocking pool, returning the execution
+/// state to carry over and the output of the transactions executed so far.
+async fn execute_off_runtime<N, Evm, P>(
+    mut execution: Option<PendingExecution>,
+    evm_config: &Evm,
+    provider: &P,
+    pending_block: &PendingFlashBlock<N>,
+) -> (Option<PendingExecution>, ExecutedTxs)
+where
+    N: NodePrimitives,
+    Evm: ConfigureEvm<Primitives = N> + 'static,
+    P: StateProviderFactory + Clone + Send + Sync + 'static,
+{
+    let (evm_config, provider) = (evm_config.clone(), provider.clone());
+    let block = pending_block.block().clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let result = execute_pending_block(&mut execution, &evm_config, &provider, &block);
+        (execution, result)
+    })
+    .await;
+
+    match result {
+        Ok((Some(exec), Ok(()))) => {
+            let executed = exec.executed.iter().cloned().collect();
+            (Some(exec), executed)
+        }
+        Ok((_, Err(err))) => {
+            warn!(
+                target: "xlayer::flashblocks",
+                block_number = pending_block.block().header().number(),
+                error = ?err,
+                "Failed to execute pending block"
+            );
+            (None, Arc::new([]))
+        }
+        Ok((None, Ok(()))) => (None, Arc::new([])),
+        Err(err) => {
+            warn!(target: "xlayer::flashblocks", error = ?err, "Pending block execution panicked");
+            (None, Arc::new([]))
+        }
+    }
+}
+
+/// Execution state of the pending block of a height.
+struct PendingExecution {
+    block_number: u64,
+    parent_hash: B256,
+    db: State<StateProviderDatabase<StateProviderBox>>,
+    /// Executed transactions, in block order
+    executed: Vec<Arc<ExecutedTx>>,
+}
+
+impl PendingExecution {
+    /// Creates the execution state of the block on top of its parent state,
+    /// with the pre-execution changes applied.
+    fn new<N, Evm, P>(
+        evm_config: &Evm,
+        provider: &P,
+        block: &RecoveredBlock<N::Block>,
+    ) -> eyre::Result<Self>
+    where
+        N: NodePrimitives,
+        Evm: ConfigureEvm<Primitives = N>,
+        P: StateProviderFactory,
+    {
+        let header = block.header();
+        let state_provider = provider
+            .state_by_block_hash(header.parent_hash())
+            .wrap_err("failed to get state for parent hash")?;
+        let mut db =
+            State::builder().with_database(StateProviderDatabase::new(state_provider)).build();
+
+        evm_config
+            .executor_for_block(&mut db, block.sealed_block())
+            .wrap_err("failed to create block executor")?
+            .apply_pre_execution_changes()
+            .wrap_err("failed to apply pre-execution changes")?;
+
+        Ok(Self {
+            block_number: header.number(),
+            parent_hash: header.parent_hash(),
+            db,
+            executed: Vec::new(),
+        })
+    }
+
+    /// Returns `true` if the block extends the transactions executed so far.
+    fn is_extended_by<N: NodePrimitives>(&self, block: &RecoveredBlock<N::Block>) -> bool {
+        let header = block.header();
+        let tx_hashes = block_tx_hashes::<N>(block);
+        self.block_number == header.number()
+            && self.parent_hash == header.parent_hash()
+            && tx_hashes.len() >= self.executed.len()
+            && self
+                .executed
+                .iter()
+                .zip(&tx_hashes)
+                .all(|(executed, hash)| executed.tx_hash == *hash)
+    }
+}
+
+/// Executes the transactions of the pending block that weren't executed yet,
+/// starting over from the parent state unless the block extends the ones
+/// executed so far.
+fn execute_pending_block<N, Evm, P>(
+    execution: &mut Option<PendingExecution>,
+    evm_config: &Evm,
+    provider: &P,
+    block: &RecoveredBlock<N::Block>,
+) -> eyre::Result<()>
+where
+    N: NodePrimitives,
+    Evm: ConfigureEvm<Primitives = N>,
+    P: StateProviderFactory,
+{
+    let exec = match execution.take() {
+        Some(exec) if exec.is_extended_by::<N>(block) => exec,
+        _ => PendingExecution::new(evm_config, provider, block)?,
+    };
+    let exec = execution.insert(exec);
+
+    let evm_env = evm_config.evm_env(block.header()).wrap_err("failed to create evm env")?;
+    let inspector = TracingInspector::new(TracingInspectorConfig::default_geth());
+    let mut evm = evm_config.evm_with_env_and_inspector(&mut exec.db, evm_env, inspector);
+    for (sender, tx) in block.transactions_with_sender().skip(exec.executed.len()) {
+        evm.inspector_mut().fuse();
+        let ResultAndState { result, state } = evm
+            .transact(Recovered::new_unchecked(tx, *sender))
+            .wrap_err("failed to execute transaction")?;
+
+        let state_diff = state_diff(evm.db_mut(), &state)?;
+        evm.db_mut().commit(state);
+
+        let call_targets =
+            evm.inspector().traces().nodes().iter().map(|node| node.trace.address).collect();
+        let call_trace = evm
+            .inspector()
+            .geth_builder()
+            .geth_call_traces(CallConfig::default().with_log(), result.gas_used());
+
+        exec.executed.push(Arc::new(ExecutedTx {
+            tx_hash: *tx.tx_hash(),
+            state_diff,
+            call_trace,
+            call_targets,
+        }));
+    }
+
+    Ok(())
+}
+
+/// Returns the changes a transaction made to the accounts it touched, from
+/// its state changes and the state before it.
+fn state_diff<DB>(db: &mut DB, state: &EvmState) -> eyre::Result<StateDiff>
+where
+    DB: Database,
+    DB::Error: Send + Sync + 'static,
+{
+    let mut diff = StateDiff::new();
+    for (address, account) in state {
+        if !account.is_touched() {
+            continue;
+        }
+
+        let before = db.basic(*address).wrap_err("failed to load account")?.unwrap_or_default();
+        let after =
+            if account.is_selfdestructed() { AccountInfo::default() } else { account.info.clone() };
+        let account_diff = AccountDiff {
+            balance: Delta::changed(before.balance, after.balance),
+            nonce: Delta::changed(U64::from(before.nonce), U64::from(after.nonce)),
+            storage: account
+                .changed_storage_slots()
+                .map(|(slot, value)| {
+                    let delta = Delta {
+                        from: B256::from(value.original_value),
+                        to: B256::from(value.present_value),
+                    };
+                    (B256::from(*slot), delta)
+                })
+                .collect(),
+        };
+        if !account_diff.is_empty() {
+            diff.insert(*address, account_diff);
+        }
+    }
+    Ok(diff)
+}
@@ -1,3 +1,9 @@
+mod execution;
 pub mod handler;
+pub mod pending_logs;
+mod preconfirmed;
 pub mod pubsub;
+pub mod quota;
+pub mod replay;
+mod resume;
 pub mod subscription;
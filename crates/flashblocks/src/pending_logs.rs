@@ -0,0 +1,240 @@
+//! Pending logs subscription fed by flashblocks.
+//!
+//! `eth_subscribe("pendingLogs", filter)` emits the matching logs of every
+//! pending flashblock as it arrives, tagged with the flashblock index, rather
+//! than waiting for the canonical block like `eth_subscribe("logs")`. Each
+//! transaction's logs are emitted once, when the transaction is first seen.
+//!
+//! Once the canonical block of a height lands, pre-confirmed logs whose
+//! transaction is not at the same position in that block are emitted again
+//! with `removed: true`, so clients can roll back what they applied.
+use alloy_consensus::{transaction::TxHashRef, BlockHeader as _, TxReceipt as _};
+use alloy_primitives::TxHash;
+use alloy_rpc_types_eth::{pubsub::Params as AlloyParams, Log};
+use futures::StreamExt;
+use jsonrpsee::types::ErrorObject;
+use reth_optimism_flashblocks::PendingFlashBlock;
+use reth_primitives_traits::{NodePrimitives, RecoveredBlock};
+use reth_provider::CanonStateNotification;
+use reth_rpc_server_types::result::invalid_params_rpc_err;
+use reth_tracing::tracing::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use tokio_stream::Stream;
+
+use crate::{
+    preconfirmed::{block_tx_hashes, Preconfirmed},
+    pubsub::{FlashblockParams, FlashblocksFilter, LogFilter},
+    quota::SubscriptionQueue,
+};
+
+/// Log pre-confirmed by a flashblock.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingLog {
+    #[serde(flatten)]
+    pub log: Log,
+    /// Index of the flashblock the log was first seen in
+    pub flashblock_index: u64,
+}
+
+/// Returns the log filter of `pendingLogs` params, which may be an
+/// `eth_getLogs` filter object or nothing to match every log. The block range
+/// of the filter is ignored.
+pub(crate) fn pending_logs_filter(
+    params: Option<&FlashblockParams>,
+) -> Result<LogFilter, ErrorObject<'static>> {
+    match params {
+        Some(FlashblockParams::Standard(AlloyParams::Logs(filter))) => Ok((&**filter).into()),
+        Some(FlashblockParams::Standard(AlloyParams::None)) | None => Ok(LogFilter::default()),
+        // An empty object also parses as a default flashblocks filter
+        Some(FlashblockParams::FlashblocksFilter(filter))
+            if *filter == FlashblocksFilter::default() =>
+        {
+            Ok(LogFilter::default())
+        }
+        _ => Err(invalid_params_rpc_err("invalid params for pending logs")),
+    }
+}
+
+/// Logs emitted per pending height, kept until the canonical block lands.
+#[derive(Debug, Default)]
+struct PreconfirmedLogs {
+    /// Transactions already seen per height
+    seen: BTreeMap<u64, HashSet<TxHash>>,
+    /// Logs emitted per height
+    logs: Preconfirmed<PendingLog>,
+}
+
+impl PreconfirmedLogs {
+    /// Returns the logs of the transactions first seen in this flashblock.
+    fn on_pending_block<N: NodePrimitives>(
+        &mut self,
+        pending_block: &PendingFlashBlock<N>,
+        filter: &LogFilter,
+    ) -> Vec<PendingLog> {
+        let block = pending_block.block();
+        let sealed_block = block.sealed_block();
+        let header = sealed_block.header();
+        let block_number = header.number();
+        if self.logs.is_stale(block_number) {
+            return Vec::new();
+        }
+
+        let seen = self.seen.entry(block_number).or_default();
+        let mut new_logs = Vec::new();
+        let mut log_index = 0u64;
+        for (idx, ((_, tx), receipt)) in
+            block.transactions_with_sender().zip(pending_block.receipts.iter()).enumerate()
+        {
+            let tx_hash = *tx.tx_hash();
+            let first_seen = seen.insert(tx_hash);
+            for log in receipt.logs() {
+                if first_seen && filter.matches(log) {
+                    new_logs.push(PendingLog {
+                        log: Log {
+                            inner: log.clone(),
+                            block_hash: Some(sealed_block.hash()),
+                            block_number: Some(block_number),
+                            block_timestamp: Some(header.timestamp()),
+                            transaction_hash: Some(tx_hash),
+                            transaction_index: Some(idx as u64),
+                            log_index: Some(log_index),
+                            removed: false,
+                        },
+                        flashblock_index: pending_block.last_flashblock_index,
+                    });
+                }
+                log_index += 1;
+            }
+        }
+
+        self.logs.extend(block_number, new_logs.iter().cloned());
+        new_logs
+    }
+
+    /// Returns the logs emitted for the height of the canonical block whose
+    /// transaction is missing from it, marked as removed.
+    fn on_canonical_block<N: NodePrimitives>(
+        &mut self,
+        block: &RecoveredBlock<N::Block>,
+    ) -> Vec<PendingLog> {
+        let block_number = block.header().number();
+        self.seen = self.seen.split_off(&(block_number + 1));
+        let logs = self.logs.take_canonical(block_number);
+
+        let canonical = block_tx_hashes::<N>(block);
+        logs.into_iter()
+            .filter(|pending_log| {
+                let idx = pending_log.log.transaction_index.unwrap_or_default() as usize;
+                canonical.get(idx) != pending_log.log.transaction_hash.as_ref()
+            })
+            .map(|mut pending_log| {
+                pending_log.log.removed = true;
+                pending_log
+            })
+            .collect()
+    }
+}
+
+/// Pipes pending logs to the subscription queue, with removal notices once
+/// canonical blocks diverge from what was pre-confirmed.
+pub(crate) async fn pipe_pending_logs<N, P, C>(
+    queue: &SubscriptionQueue,
+    filter: LogFilter,
+    pending: P,
+    canonical: C,
+) -> Result<(), ErrorObject<'static>>
+where
+    N: NodePrimitives,
+    P: Stream<Item = PendingFlashBlock<N>>,
+    C: Stream<Item = CanonStateNotification<N>>,
+{
+    let mut pending = std::pin::pin!(pending);
+    let mut canonical = std::pin::pin!(canonical);
+    let mut preconfirmed = PreconfirmedLogs::default();
+
+    loop {
+        let logs = tokio::select! {
+            _ = queue.closed() => break Ok(()),
+            maybe_block = pending.next() => {
+                let Some(pending_block) = maybe_block else {
+                    break Ok(());
+                };
+                preconfirmed.on_pending_block(&pending_block, &filter)
+            }
+            maybe_notification = canonical.next() => {
+                let Some(notification) = maybe_notification else {
+                    break Ok(());
+                };
+                let mut removed = Vec::new();
+                for block in notification.committed().blocks_iter() {
+                    removed.extend(preconfirmed.on_canonical_block::<N>(block));
+                }
+                if !removed.is_empty() {
+                    debug!(
+                        target: "xlayer::flashblocks",
+                        removed = removed.len(),
+                        "pre-confirmed logs missing from canonical block"
+                    );
+                }
+                removed
+            }
+        };
+
+        for log in &logs {
+            if log.removed {
+                queue.push_notice(log)?;
+            } else {
+                queue.push(log)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{Address, LogData, B256};
+    use alloy_rpc_types_eth::Filter;
+
+    fn log(address: Address, topics: Vec<B256>) -> alloy_primitives::Log {
+        alloy_primitives::Log { address, data: LogData::new_unchecked(topics, Default::default()) }
+    }
+
+    #[test]
+    fn test_pending_logs_filter_matches_like_get_logs() {
+        let (contract, other) = (Address::with_last_byte(1), Address::with_last_byte(2));
+        let (transfer, approval) = (B256::with_last_byte(1), B256::with_last_byte(2));
+        let filter = Filter::new().address(contract).event_signature(vec![transfer, approval]);
+        let params = FlashblockParams::Standard(AlloyParams::Logs(Box::new(filter)));
+
+        let filter = pending_logs_filter(Some(&params)).unwrap();
+        assert!(filter.matches(&log(contract, vec![transfer])));
+        assert!(filter.matches(&log(contract, vec![approval, B256::ZERO])));
+        assert!(!filter.matches(&log(other, vec![transfer])));
+        assert!(!filter.matches(&log(contract, vec![B256::ZERO])));
+        assert!(!filter.matches(&log(contract, vec![])));
+
+        let filter = pending_logs_filter(None).unwrap();
+        assert!(filter.matches(&log(other, vec![])));
+    }
+
+    #[test]
+    fn test_pending_logs_filter_limits() {
+        let addresses: Vec<_> = (0..3).map(Address::with_last_byte).collect();
+        let params = FlashblockParams::Standard(AlloyParams::Logs(Box::new(
+            Filter::new().address(addresses),
+        )));
+
+        let filter = pending_logs_filter(Some(&params)).unwrap();
+        assert!(filter.validate(3).is_ok());
+        assert!(filter.validate(2).is_err());
+
+        let params = FlashblockParams::FlashblocksFilter(FlashblocksFilter {
+            header_info: true,
+            ..Default::default()
+        });
+        assert!(pending_logs_filter(Some(&params)).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! Tracking of what was streamed for pending blocks.
+//!
+//! Items streamed for a pending block are kept by height until the canonical
+//! block of that height lands, when they are handed back to be checked
+//! against it. Pending blocks at or below the canonical head are stale.
+use alloy_consensus::transaction::TxHashRef;
+use alloy_primitives::TxHash;
+use reth_primitives_traits::{NodePrimitives, RecoveredBlock};
+use std::collections::BTreeMap;
+
+/// Items streamed per pending height, until the canonical block lands.
+#[derive(Debug)]
+pub(crate) struct Preconfirmed<T> {
+    items: BTreeMap<u64, Vec<T>>,
+    /// Latest canonical height
+    canonical_head: Option<u64>,
+}
+
+impl<T> Default for Preconfirmed<T> {
+    fn default() -> Self {
+        Self { items: BTreeMap::new(), canonical_head: None }
+    }
+}
+
+impl<T> Preconfirmed<T> {
+    /// Returns `true` if the height already has a canonical block.
+    pub(crate) fn is_stale(&self, block_number: u64) -> bool {
+        self.canonical_head.is_some_and(|head| block_number <= head)
+    }
+
+    /// Records items streamed for a pending height.
+    pub(crate) fn extend(&mut self, block_number: u64, items: impl IntoIterator<Item = T>) {
+        self.items.entry(block_number).or_default().extend(items);
+    }
+
+    /// Marks a height canonical, returning its items and dropping every
+    /// height up to it.
+    pub(crate) fn take_canonical(&mut self, block_number: u64) -> Vec<T> {
+        self.canonical_head = Some(block_number);
+        let pending = self.items.split_off(&(block_number + 1));
+        std::mem::replace(&mut self.items, pending).remove(&block_number).unwrap_or_default()
+    }
+}
+
+/// How a streamed transaction differs from the canonical block of its height.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Divergence {
+    /// Missing from the canonical block
+    Dropped,
+    /// At another index in the canonical block
+    Moved(u64),
+}
+
+/// Compares the transactions streamed for a height, with their index in the
+/// pending block, to the transactions of the canonical block.
+pub(crate) fn diverged_txs(
+    streamed: Vec<(TxHash, u64)>,
+    canonical: &[TxHash],
+) -> Vec<(TxHash, Divergence)> {
+    streamed
+        .into_iter()
+        .filter_map(|(tx_hash, idx)| match canonical.iter().position(|hash| *hash == tx_hash) {
+            None => Some((tx_hash, Divergence::Dropped)),
+            Some(pos) if pos as u64 != idx => Some((tx_hash, Divergence::Moved(pos as u64))),
+            Some(_) => None,
+        })
+        .collect()
+}
+
+/// Returns the transaction hashes of a block, in block order.
+pub(crate) fn block_tx_hashes<N: NodePrimitives>(block: &RecoveredBlock<N::Block>) -> Vec<TxHash> {
+    block.transactions_with_sender().map(|(_, tx)| *tx.tx_hash()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preconfirmed_take_canonical() {
+        let mut preconfirmed = Preconfirmed::default();
+        preconfirmed.extend(10, [1, 2]);
+        preconfirmed.extend(11, [3]);
+        preconfirmed.extend(12, [4]);
+        preconfirmed.extend(10, [5]);
+        assert!(!preconfirmed.is_stale(10));
+
+        assert_eq!(preconfirmed.take_canonical(11), vec![3]);
+        // Lower heights are dropped, higher ones kept
+        assert!(preconfirmed.is_stale(10));
+        assert!(preconfirmed.is_stale(11));
+        assert!(!preconfirmed.is_stale(12));
+        assert!(preconfirmed.take_canonical(10).is_empty());
+        assert_eq!(preconfirmed.take_canonical(12), vec![4]);
+    }
+
+    #[test]
+    fn test_preconfirmed_take_canonical_without_items() {
+        let mut preconfirmed = Preconfirmed::<u64>::default();
+        assert!(preconfirmed.take_canonical(5).is_empty());
+        assert!(preconfirmed.is_stale(5));
+        assert!(!preconfirmed.is_stale(6));
+    }
+
+    #[test]
+    fn test_diverged_txs() {
+        let [a, b, c, d] = [1, 2, 3, 4].map(TxHash::with_last_byte);
+        let streamed = vec![(a, 0), (b, 1), (c, 2)];
+        let canonical = [a, c, d, b];
+
+        assert_eq!(
+            diverged_txs(streamed, &canonical),
+            vec![(b, Divergence::Moved(3)), (c, Divergence::Moved(1))]
+        );
+        assert_eq!(diverged_txs(vec![(d, 0)], &[a]), vec![(d, Divergence::Dropped)]);
+        assert!(diverged_txs(vec![(a, 0)], &[a, b]).is_empty());
+    }
+}
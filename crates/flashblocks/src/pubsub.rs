@@ -1,14 +1,19 @@
-use alloy_primitives::{Address, TxHash};
+use alloy_primitives::{Address, TxHash, B256, U256, U64};
 use alloy_rpc_types_eth::{
     pubsub::{Params as AlloyParams, SubscriptionKind as AlloySubscriptionKind},
-    Header,
+    Filter, Header, Log, ValueOrArray,
 };
+use alloy_rpc_types_trace::geth::CallFrame;
 use jsonrpsee::types::ErrorObject;
 use reth_rpc_server_types::result::invalid_params_rpc_err;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
 const FLASHBLOCKS: &str = "flashblocks";
+const PENDING_LOGS: &str = "pendingLogs";
+
+/// Max number of topic positions in a log filter.
+const MAX_TOPICS: usize = 4;
 
 /// Subscription kind inclusive of flashblocks.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
@@ -20,23 +25,37 @@ pub enum FlashblockSubscriptionKind {
         serialize_with = "serialize_flashblocks"
     )]
     Flashblocks,
+    /// Logs of pending flashblocks.
+    #[serde(
+        deserialize_with = "deserialize_pending_logs",
+        serialize_with = "serialize_pending_logs"
+    )]
+    PendingLogs,
     /// Standard Ethereum subscription.
     Standard(AlloySubscriptionKind),
 }
 
-/// Helper to deserialize the unit variant from the string "flashblocks".
-fn deserialize_flashblocks<'de, D>(deserializer: D) -> Result<(), D::Error>
+/// Helper to deserialize a unit variant from the string `expected`.
+fn deserialize_unit<'de, D>(deserializer: D, expected: &str) -> Result<(), D::Error>
 where
     D: serde::Deserializer<'de>,
 {
     let s = <&str>::deserialize(deserializer)?;
-    if s == FLASHBLOCKS {
+    if s == expected {
         Ok(())
     } else {
-        Err(serde::de::Error::custom(format!("expected '{FLASHBLOCKS}', got '{s}'")))
+        Err(serde::de::Error::custom(format!("expected '{expected}', got '{s}'")))
     }
 }
 
+/// Helper to deserialize the unit variant from the string "flashblocks".
+fn deserialize_flashblocks<'de, D>(deserializer: D) -> Result<(), D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserialize_unit(deserializer, FLASHBLOCKS)
+}
+
 /// Helper to serialize the unit variant as the string "flashblocks".
 fn serialize_flashblocks<S>(serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -45,6 +64,22 @@ where
     serializer.serialize_str(FLASHBLOCKS)
 }
 
+/// Helper to deserialize the unit variant from the string "pendingLogs".
+fn deserialize_pending_logs<'de, D>(deserializer: D) -> Result<(), D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserialize_unit(deserializer, PENDING_LOGS)
+}
+
+/// Helper to serialize the unit variant as the string "pendingLogs".
+fn serialize_pending_logs<S>(serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(PENDING_LOGS)
+}
+
 /// Extended params that wraps Alloy's `Params` and adds flashblocks specific variants.
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(untagged)]
@@ -58,11 +93,21 @@ pub enum FlashblockParams {
 impl FlashblockParams {
     /// Validates the flashblock params.
     pub fn validate(&self, max_subscribed_addresses: usize) -> Result<(), ErrorObject<'static>> {
-        if let FlashblockParams::FlashblocksFilter(filter) = self
-            && filter.sub_tx_filter.subscribe_addresses.len() > max_subscribed_addresses
-        {
+        let FlashblockParams::FlashblocksFilter(filter) = self else {
+            return Ok(());
+        };
+        if filter.sub_tx_filter.subscribe_addresses.len() > max_subscribed_addresses {
             return Err(invalid_params_rpc_err("too many subscribe addresses"));
         }
+        if let Some(log_filter) = &filter.log_filter {
+            log_filter.validate(max_subscribed_addresses)?;
+        }
+        if filter.log_info && filter.log_filter.is_none() {
+            return Err(invalid_params_rpc_err("logInfo requires a logFilter"));
+        }
+        if filter.sub_tx_filter.state_diff && !filter.sub_tx_filter.has_address_filter() {
+            return Err(invalid_params_rpc_err("stateDiff requires subscribeAddresses"));
+        }
         Ok(())
     }
 }
@@ -76,6 +121,16 @@ pub struct FlashblocksFilter {
 
     /// Tx criterias to subscribe to new transactions in the stream.
     pub sub_tx_filter: SubTxFilter,
+
+    /// Log criteria, only transactions emitting a matching log are streamed.
+    pub log_filter: Option<LogFilter>,
+
+    /// Flag to stream the logs matching `log_filter` as they land.
+    pub log_info: bool,
+
+    /// Cursor of the last event received, the recent events after it are
+    /// replayed before the live feed.
+    pub resume_from: Option<FlashblockCursor>,
 }
 
 impl FlashblocksFilter {
@@ -100,6 +155,15 @@ pub struct SubTxFilter {
 
     /// Flag to include transaction receipts.
     pub tx_receipt: bool,
+
+    /// Flag to include the state changes of the subscribed addresses. The
+    /// pending transactions are re-executed for it.
+    pub state_diff: bool,
+
+    /// Flag to include the call trace of the transaction, and to match the
+    /// subscribe addresses against its internal calls. The pending
+    /// transactions are re-executed for it.
+    pub call_trace: bool,
 }
 
 impl SubTxFilter {
@@ -107,9 +171,94 @@ impl SubTxFilter {
     pub fn has_address_filter(&self) -> bool {
         !self.subscribe_addresses.is_empty()
     }
+
+    /// Returns `true` if the pending transactions must be re-executed.
+    pub fn requires_execution(&self) -> bool {
+        self.state_diff || self.call_trace
+    }
+}
+
+/// Log criteria with the `eth_getLogs` filter semantics, without a block range.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields, default)]
+pub struct LogFilter {
+    /// Contracts emitting the log, any of them matches. Unset matches any contract.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<ValueOrArray<Address>>,
+
+    /// Topics by position, any of the topics at a position matches. `null` or an
+    /// empty array at a position matches any topic.
+    pub topics: Vec<Option<ValueOrArray<B256>>>,
+}
+
+impl LogFilter {
+    /// Returns the contract addresses of the filter.
+    pub fn addresses(&self) -> &[Address] {
+        match &self.address {
+            None => &[],
+            Some(ValueOrArray::Value(address)) => std::slice::from_ref(address),
+            Some(ValueOrArray::Array(addresses)) => addresses,
+        }
+    }
+
+    /// Checks the filter against the address limit and the topic positions.
+    pub fn validate(&self, max_addresses: usize) -> Result<(), ErrorObject<'static>> {
+        if self.addresses().len() > max_addresses {
+            return Err(invalid_params_rpc_err("too many log filter addresses"));
+        }
+        if self.topics.len() > MAX_TOPICS {
+            return Err(invalid_params_rpc_err("too many log filter topics"));
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the log matches the address and every topic position.
+    pub fn matches(&self, log: &alloy_primitives::Log) -> bool {
+        let addresses = self.addresses();
+        if !addresses.is_empty() && !addresses.contains(&log.address) {
+            return false;
+        }
+
+        let log_topics = log.topics();
+        self.topics.iter().enumerate().all(|(idx, topic)| {
+            let topics = match topic {
+                None => return true,
+                Some(ValueOrArray::Value(topic)) => std::slice::from_ref(topic),
+                Some(ValueOrArray::Array(topics)) => topics.as_slice(),
+            };
+            topics.is_empty() || log_topics.get(idx).is_some_and(|t| topics.contains(t))
+        })
+    }
 }
 
-/// Streaming flashblock event which is either a header or transaction message
+impl From<&Filter> for LogFilter {
+    /// Takes the address and topics of an `eth_getLogs` filter, ignoring its
+    /// block range.
+    fn from(filter: &Filter) -> Self {
+        Self {
+            address: filter.address.to_value_or_array(),
+            topics: filter.topics.iter().map(|topic| topic.to_value_or_array()).collect(),
+        }
+    }
+}
+
+/// Position of an event in the flashblocks stream, ordered by block number,
+/// flashblock index, then transaction index.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize,
+)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct FlashblockCursor {
+    pub block_number: u64,
+    pub flashblock_index: u64,
+    /// Index of the transaction in the block, unset for a header. A logs
+    /// event has the index of the last transaction of its flashblock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_index: Option<u64>,
+}
+
+/// Streaming flashblock event which is either a header, transaction or logs
+/// message, or a divergence notice once the canonical block of a height lands
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum FlashblockStreamEvent<H, Tx, R> {
@@ -117,14 +266,41 @@ pub enum FlashblockStreamEvent<H, Tx, R> {
     Header {
         #[serde(skip_serializing)]
         block_number: u64,
+        cursor: FlashblockCursor,
         header: Header<H>,
     },
     /// Individual transaction event
     Transaction {
         #[serde(skip_serializing)]
         block_number: u64,
+        cursor: FlashblockCursor,
         transaction: EnrichedTransaction<Tx, R>,
     },
+    /// Logs matching the log filter, from the transactions of a flashblock
+    Logs {
+        #[serde(skip_serializing)]
+        block_number: u64,
+        cursor: FlashblockCursor,
+        logs: Vec<Log>,
+    },
+    /// Streamed transaction missing from the canonical block of its height
+    Dropped {
+        #[serde(rename = "blockNumber")]
+        block_number: u64,
+        #[serde(rename = "txHash")]
+        tx_hash: TxHash,
+    },
+    /// Streamed transaction included in the canonical block at another
+    /// position, so its streamed receipt and logs are stale
+    Reorged {
+        #[serde(rename = "blockNumber")]
+        block_number: u64,
+        #[serde(rename = "txHash")]
+        tx_hash: TxHash,
+        /// Index of the transaction in the canonical block
+        #[serde(rename = "transactionIndex")]
+        transaction_index: u64,
+    },
 }
 
 impl<H, Tx, R> FlashblockStreamEvent<H, Tx, R> {
@@ -133,6 +309,28 @@ impl<H, Tx, R> FlashblockStreamEvent<H, Tx, R> {
         match self {
             FlashblockStreamEvent::Header { block_number, .. } => *block_number,
             FlashblockStreamEvent::Transaction { block_number, .. } => *block_number,
+            FlashblockStreamEvent::Logs { block_number, .. } => *block_number,
+            FlashblockStreamEvent::Dropped { block_number, .. } => *block_number,
+            FlashblockStreamEvent::Reorged { block_number, .. } => *block_number,
+        }
+    }
+
+    /// Returns `true` for the notices of streamed transactions diverging
+    /// from the canonical chain
+    pub fn is_divergence(&self) -> bool {
+        matches!(
+            self,
+            FlashblockStreamEvent::Dropped { .. } | FlashblockStreamEvent::Reorged { .. }
+        )
+    }
+
+    /// Get the cursor of this event, unset for divergence notices
+    pub fn cursor(&self) -> Option<FlashblockCursor> {
+        match self {
+            FlashblockStreamEvent::Header { cursor, .. }
+            | FlashblockStreamEvent::Transaction { cursor, .. }
+            | FlashblockStreamEvent::Logs { cursor, .. } => Some(*cursor),
+            FlashblockStreamEvent::Dropped { .. } | FlashblockStreamEvent::Reorged { .. } => None,
         }
     }
 }
@@ -151,4 +349,50 @@ pub struct EnrichedTransaction<Tx, R> {
     /// Transaction receipt (if `tx_receipt` is true in filter criteria).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub receipt: Option<R>,
+
+    /// State changes of the subscribed addresses (if `state_diff` is true in
+    /// filter criteria).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_diff: Option<StateDiff>,
+
+    /// Call trace (if `call_trace` is true in filter criteria).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_trace: Option<CallFrame>,
+}
+
+/// State changes of the subscribed addresses made by a transaction.
+pub type StateDiff = BTreeMap<Address, AccountDiff>;
+
+/// State changes of an account made by a transaction.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDiff {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<Delta<U256>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<Delta<U64>>,
+    /// Changed storage slots
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<B256, Delta<B256>>,
+}
+
+impl AccountDiff {
+    /// Returns `true` if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.balance.is_none() && self.nonce.is_none() && self.storage.is_empty()
+    }
+}
+
+/// Value before and after a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delta<T> {
+    pub from: T,
+    pub to: T,
+}
+
+impl<T: PartialEq> Delta<T> {
+    /// Returns the delta if the value changed.
+    pub fn changed(from: T, to: T) -> Option<Self> {
+        (from != to).then_some(Self { from, to })
+    }
 }
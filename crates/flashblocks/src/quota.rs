@@ -0,0 +1,526 @@
+//! Quotas and backpressure of flashblocks subscriptions.
+//!
+//! Subscriptions are counted per connection and across all connections, and
+//! rejected once either limit is reached. Subscriptions asking for state diffs
+//! or call traces have a separate, lower limit, as they keep pending blocks
+//! being executed.
+//!
+//! Events of every subscription but `syncing` go through a bounded queue per
+//! subscription, so a slow client never holds back the stream it is fed from.
+//! Once the queue is full, the [`LagPolicy`] either drops the oldest queued
+//! event or closes the subscription. Divergence and removal notices are never
+//! dropped: the subscription is closed instead if one cannot be queued.
+//! Replayed history is queued as room frees up rather than dropped.
+use futures::StreamExt;
+use jsonrpsee::{
+    server::SubscriptionMessage,
+    types::{error::TOO_MANY_SUBSCRIPTIONS_CODE, ErrorObject},
+    ConnectionId, SubscriptionSink,
+};
+use parking_lot::Mutex;
+use reth_metrics::{
+    metrics::{Counter, Gauge},
+    Metrics,
+};
+use reth_tracing::tracing::debug;
+use serde::Serialize;
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    fmt,
+    future::Future,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::Notify;
+use tokio_stream::Stream;
+
+use crate::subscription::SubscriptionSerializeError;
+
+/// What to do with a subscription whose send queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Drop the oldest queued event to make room for the new one.
+    #[default]
+    DropOldest,
+    /// Close the subscription.
+    Disconnect,
+}
+
+impl FromStr for LagPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop-oldest" => Ok(Self::DropOldest),
+            "disconnect" => Ok(Self::Disconnect),
+            _ => Err(format!(
+                "Unknown flashblocks subscription lag policy '{s}', expected 'drop-oldest' or 'disconnect'"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LagPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DropOldest => f.write_str("drop-oldest"),
+            Self::Disconnect => f.write_str("disconnect"),
+        }
+    }
+}
+
+/// Limits of the flashblocks subscriptions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriptionLimits {
+    /// Addresses a single flashblocks subscription may subscribe to
+    pub max_addresses: usize,
+    /// Subscriptions per connection, 0 disables the limit
+    pub max_per_connection: usize,
+    /// Subscriptions across all connections, 0 disables the limit
+    pub max_total: usize,
+    /// Events queued per subscription before the lag policy applies
+    pub queue_size: usize,
+    /// What to do once a send queue is full
+    pub lag_policy: LagPolicy,
+    /// Blocks a logs subscription may replay from its `fromBlock`, 0 disables
+    /// the limit
+    pub max_replay_blocks: u64,
+    /// Subscriptions requiring execution across all connections, 0 disables
+    /// the limit
+    pub max_execution: usize,
+    /// Recent heights buffered for flashblocks subscriptions resuming from a
+    /// cursor
+    pub max_resume_blocks: usize,
+}
+
+impl Default for SubscriptionLimits {
+    fn default() -> Self {
+        Self {
+            max_addresses: 1000,
+            max_per_connection: 128,
+            max_total: 10_000,
+            queue_size: 1024,
+            lag_policy: LagPolicy::default(),
+            max_replay_blocks: 100_000,
+            max_execution: 100,
+            max_resume_blocks: 16,
+        }
+    }
+}
+
+/// Flashblocks subscription metrics
+#[derive(Metrics, Clone)]
+#[metrics(scope = "xlayer_flashblocks.subscriptions")]
+pub(crate) struct SubscriptionMetrics {
+    /// Number of active subscriptions
+    active: Gauge,
+    /// Number of subscriptions rejected by a quota
+    rejected: Counter,
+    /// Number of events dropped from full send queues
+    dropped_events: Counter,
+    /// Number of subscriptions closed for lagging behind
+    lagged: Counter,
+}
+
+/// Active subscription counts, checked against the limits.
+pub(crate) struct SubscriptionQuota {
+    limits: SubscriptionLimits,
+    counts: Mutex<QuotaCounts>,
+    metrics: SubscriptionMetrics,
+}
+
+#[derive(Debug, Default)]
+struct QuotaCounts {
+    total: usize,
+    per_connection: HashMap<ConnectionId, usize>,
+    execution: usize,
+}
+
+impl SubscriptionQuota {
+    pub(crate) fn new(limits: SubscriptionLimits) -> Self {
+        Self {
+            limits,
+            counts: Mutex::new(QuotaCounts::default()),
+            metrics: SubscriptionMetrics::default(),
+        }
+    }
+
+    /// Counts a new subscription of the connection, unless a limit is reached.
+    /// The subscription is counted until the permit is dropped.
+    pub(crate) fn try_acquire(
+        self: &Arc<Self>,
+        connection_id: ConnectionId,
+        requires_execution: bool,
+    ) -> Result<QuotaPermit, ErrorObject<'static>> {
+        let mut counts = self.counts.lock();
+        let per_connection = counts.per_connection.get(&connection_id).copied().unwrap_or_default();
+        let reason = if is_reached(self.limits.max_total, counts.total) {
+            Some("too many flashblocks subscriptions")
+        } else if is_reached(self.limits.max_per_connection, per_connection) {
+            Some("too many subscriptions on the connection")
+        } else if requires_execution && is_reached(self.limits.max_execution, counts.execution) {
+            Some("too many subscriptions with state diffs or call traces")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            self.metrics.rejected.increment(1);
+            return Err(ErrorObject::owned(TOO_MANY_SUBSCRIPTIONS_CODE, reason, None::<()>));
+        }
+
+        counts.total += 1;
+        *counts.per_connection.entry(connection_id).or_default() += 1;
+        counts.execution += usize::from(requires_execution);
+        self.metrics.active.set(counts.total as f64);
+        Ok(QuotaPermit { quota: self.clone(), connection_id, requires_execution })
+    }
+
+    /// Returns a bounded send queue for an accepted subscription.
+    pub(crate) fn queue(&self, sink: SubscriptionSink) -> SubscriptionQueue {
+        SubscriptionQueue {
+            sink,
+            messages: Mutex::new(LagQueue::new(self.limits.queue_size, self.limits.lag_policy)),
+            notify: Notify::new(),
+            room: Notify::new(),
+            done: AtomicBool::new(false),
+            metrics: self.metrics.clone(),
+        }
+    }
+
+    fn release(&self, connection_id: ConnectionId, requires_execution: bool) {
+        let mut counts = self.counts.lock();
+        counts.total -= 1;
+        counts.execution -= usize::from(requires_execution);
+        if let Entry::Occupied(mut entry) = counts.per_connection.entry(connection_id) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+            }
+        }
+        self.metrics.active.set(counts.total as f64);
+    }
+}
+
+/// Returns `true` if `count` reached `limit`, 0 being no limit.
+const fn is_reached(limit: usize, count: usize) -> bool {
+    limit != 0 && count >= limit
+}
+
+/// A subscription counted by the quota.
+pub(crate) struct QuotaPermit {
+    quota: Arc<SubscriptionQuota>,
+    connection_id: ConnectionId,
+    requires_execution: bool,
+}
+
+impl Drop for QuotaPermit {
+    fn drop(&mut self) {
+        self.quota.release(self.connection_id, self.requires_execution);
+    }
+}
+
+/// The subscription lagged behind and must be closed.
+#[derive(Debug, PartialEq, Eq)]
+struct Lagged;
+
+/// Events queued for a subscription, bounded by the lag policy.
+#[derive(Debug)]
+struct LagQueue<T> {
+    /// Queued events, each with whether it is a notice that is never dropped
+    items: VecDeque<(T, bool)>,
+    capacity: usize,
+    lag_policy: LagPolicy,
+}
+
+impl<T> LagQueue<T> {
+    fn new(capacity: usize, lag_policy: LagPolicy) -> Self {
+        Self { items: VecDeque::new(), capacity, lag_policy }
+    }
+
+    /// Queues an event, applying the lag policy if the queue is full.
+    /// Returns `true` if an older event was dropped to make room, and fails
+    /// if no event can be dropped.
+    fn push(&mut self, item: T, notice: bool) -> Result<bool, Lagged> {
+        if self.has_room() {
+            self.items.push_back((item, notice));
+            return Ok(false);
+        }
+
+        let droppable = match self.lag_policy {
+            LagPolicy::DropOldest => self.items.iter().position(|(_, notice)| !notice),
+            LagPolicy::Disconnect => None,
+        };
+        let idx = droppable.ok_or(Lagged)?;
+        self.items.remove(idx);
+        self.items.push_back((item, notice));
+        Ok(true)
+    }
+
+    const fn has_room(&self) -> bool {
+        self.items.len() < self.capacity
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.items.pop_front().map(|(item, _)| item)
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn clear(&mut self) {
+        self.items.clear();
+    }
+}
+
+/// Bounded send queue of a subscription.
+pub(crate) struct SubscriptionQueue {
+    sink: SubscriptionSink,
+    messages: Mutex<LagQueue<SubscriptionMessage>>,
+    /// Wakes the sender on new messages
+    notify: Notify,
+    /// Wakes a waiting [`Self::push_wait`] once a message is sent
+    room: Notify,
+    /// Set once nothing more is queued
+    done: AtomicBool,
+    metrics: SubscriptionMetrics,
+}
+
+impl SubscriptionQueue {
+    /// Completes once the subscription is closed.
+    pub(crate) async fn closed(&self) {
+        self.sink.closed().await
+    }
+
+    /// Queues an event, applying the lag policy if the queue is full. Fails
+    /// if the subscription lagged behind and must be closed.
+    pub(crate) fn push<T: Serialize + ?Sized>(&self, item: &T) -> Result<(), ErrorObject<'static>> {
+        self.enqueue(self.message(item)?, false)
+    }
+
+    /// Queues a divergence or removal notice, which is never dropped. Fails
+    /// if it cannot be queued, as the subscription must then be closed.
+    pub(crate) fn push_notice<T: Serialize + ?Sized>(
+        &self,
+        item: &T,
+    ) -> Result<(), ErrorObject<'static>> {
+        self.enqueue(self.message(item)?, true)
+    }
+
+    /// Queues an event once there is room, for events produced faster than
+    /// they can be sent, like replayed history. Returns `false` if the
+    /// subscription was closed meanwhile.
+    pub(crate) async fn push_wait<T: Serialize + ?Sized>(
+        &self,
+        item: &T,
+    ) -> Result<bool, ErrorObject<'static>> {
+        let msg = self.message(item)?;
+        loop {
+            {
+                let mut messages = self.messages.lock();
+                if messages.has_room() {
+                    let _ = messages.push(msg, false);
+                    break;
+                }
+            }
+            tokio::select! {
+                _ = self.room.notified() => {}
+                _ = self.closed() => return Ok(false),
+            }
+        }
+
+        self.notify.notify_one();
+        Ok(true)
+    }
+
+    /// Queues the items of `stream` until it ends or the subscription closes.
+    /// Items `is_notice` returns `true` for are queued as notices.
+    pub(crate) async fn pipe<T: Serialize>(
+        &self,
+        stream: impl Stream<Item = T>,
+        is_notice: impl Fn(&T) -> bool,
+    ) -> Result<(), ErrorObject<'static>> {
+        let mut stream = std::pin::pin!(stream);
+        loop {
+            tokio::select! {
+                _ = self.closed() => break Ok(()),
+                maybe_item = stream.next() => {
+                    let Some(item) = maybe_item else {
+                        break Ok(());
+                    };
+                    if is_notice(&item) {
+                        self.push_notice(&item)?;
+                    } else {
+                        self.push(&item)?;
+                    }
+                }
+            }
+        }
+    }
+
+    fn message<T: Serialize + ?Sized>(
+        &self,
+        item: &T,
+    ) -> Result<SubscriptionMessage, ErrorObject<'static>> {
+        SubscriptionMessage::new(self.sink.method_name(), self.sink.subscription_id(), item)
+            .map_err(|err| SubscriptionSerializeError::new(err).into())
+    }
+
+    fn enqueue(&self, msg: SubscriptionMessage, notice: bool) -> Result<(), ErrorObject<'static>> {
+        let mut messages = self.messages.lock();
+        match messages.push(msg, notice) {
+            Ok(dropped) => {
+                if dropped {
+                    self.metrics.dropped_events.increment(1);
+                }
+            }
+            Err(Lagged) => {
+                self.metrics.dropped_events.increment(messages.len() as u64 + 1);
+                self.metrics.lagged.increment(1);
+                messages.clear();
+                debug!(
+                    target: "xlayer::flashblocks",
+                    subscription_id = ?self.sink.subscription_id(),
+                    notice,
+                    "subscription lagged behind, closing"
+                );
+                return Err(ErrorObject::owned(
+                    TOO_MANY_SUBSCRIPTIONS_CODE,
+                    "subscription lagged behind",
+                    None::<()>,
+                ));
+            }
+        }
+        drop(messages);
+
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    /// Runs `pipe`, which queues the events, while sending the queued events
+    /// to the sink. Whatever is still queued once `pipe` ends is sent too.
+    pub(crate) async fn run(
+        &self,
+        pipe: impl Future<Output = Result<(), ErrorObject<'static>>>,
+    ) -> Result<(), ErrorObject<'static>> {
+        let pipe = async {
+            let result = pipe.await;
+            self.done.store(true, Ordering::Release);
+            self.notify.notify_one();
+            result
+        };
+        let (result, ()) = tokio::join!(pipe, self.send_queued());
+        result
+    }
+
+    /// Sends queued events until the sink closes, or nothing more is queued.
+    async fn send_queued(&self) {
+        loop {
+            match self.pop() {
+                Some(msg) => {
+                    if self.sink.send(msg).await.is_err() {
+                        return;
+                    }
+                }
+                None if self.done.load(Ordering::Acquire) => return,
+                None => self.notify.notified().await,
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<SubscriptionMessage> {
+        let msg = self.messages.lock().pop();
+        if msg.is_some() {
+            self.room.notify_one();
+        }
+        msg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(queue: &mut LagQueue<u32>) -> Vec<u32> {
+        std::iter::from_fn(|| queue.pop()).collect()
+    }
+
+    #[test]
+    fn test_lag_queue_drops_oldest() {
+        let mut queue = LagQueue::new(2, LagPolicy::DropOldest);
+        assert_eq!(queue.push(1, false), Ok(false));
+        assert_eq!(queue.push(2, false), Ok(false));
+        assert_eq!(queue.push(3, false), Ok(true));
+        assert_eq!(drain(&mut queue), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_lag_queue_keeps_notices() {
+        let mut queue = LagQueue::new(3, LagPolicy::DropOldest);
+        queue.push(1, true).unwrap();
+        queue.push(2, false).unwrap();
+        queue.push(3, true).unwrap();
+        // The oldest event that isn't a notice makes room
+        assert_eq!(queue.push(4, true), Ok(true));
+        assert_eq!(drain(&mut queue), vec![1, 3, 4]);
+
+        // Full of notices, nothing can be dropped
+        let mut queue = LagQueue::new(2, LagPolicy::DropOldest);
+        queue.push(1, true).unwrap();
+        queue.push(2, true).unwrap();
+        assert_eq!(queue.push(3, false), Err(Lagged));
+        assert_eq!(queue.push(3, true), Err(Lagged));
+    }
+
+    #[test]
+    fn test_lag_queue_disconnects() {
+        let mut queue = LagQueue::new(2, LagPolicy::Disconnect);
+        queue.push(1, false).unwrap();
+        queue.push(2, false).unwrap();
+        assert_eq!(queue.push(3, false), Err(Lagged));
+        assert_eq!(queue.push(3, true), Err(Lagged));
+
+        queue.pop();
+        assert_eq!(queue.push(3, true), Ok(false));
+    }
+
+    #[test]
+    fn test_quota_releases_permits() {
+        let limits =
+            SubscriptionLimits { max_per_connection: 2, max_total: 3, ..Default::default() };
+        let quota = Arc::new(SubscriptionQuota::new(limits));
+
+        let first = quota.try_acquire(ConnectionId(1), false).unwrap();
+        let second = quota.try_acquire(ConnectionId(1), false).unwrap();
+        assert!(quota.try_acquire(ConnectionId(1), false).is_err());
+
+        let other = quota.try_acquire(ConnectionId(2), false).unwrap();
+        assert!(quota.try_acquire(ConnectionId(3), false).is_err());
+
+        drop(first);
+        assert_eq!(quota.counts.lock().per_connection.get(&ConnectionId(1)), Some(&1));
+        let third = quota.try_acquire(ConnectionId(1), false).unwrap();
+
+        drop((second, third, other));
+        let counts = quota.counts.lock();
+        assert_eq!(counts.total, 0);
+        assert!(counts.per_connection.is_empty());
+    }
+
+    #[test]
+    fn test_quota_limits_execution_subscriptions() {
+        let limits = SubscriptionLimits { max_execution: 1, ..Default::default() };
+        let quota = Arc::new(SubscriptionQuota::new(limits));
+
+        let permit = quota.try_acquire(ConnectionId(1), true).unwrap();
+        assert!(quota.try_acquire(ConnectionId(2), true).is_err());
+        // Subscriptions without execution aren't held back
+        let _plain = quota.try_acquire(ConnectionId(2), false).unwrap();
+
+        drop(permit);
+        assert!(quota.try_acquire(ConnectionId(2), true).is_ok());
+    }
+}
@@ -0,0 +1,267 @@
+//! Historical replay for `eth_subscribe("logs")`.
+//!
+//! A logs subscription with a `fromBlock` first replays the matching logs
+//! from that block up to the current head, then switches to the live feed.
+//! The live feed is subscribed to before the replay starts, but it is not
+//! polled while replaying, so notifications lagging out of its channel during
+//! a long replay would be lost. Instead, the head is read again once the
+//! replay reaches it, and the blocks added meanwhile are replayed as well
+//! until the replay catches up. Live logs at or below the replayed head are
+//! skipped, so no block is missed or sent twice across the switch. Removed
+//! logs from reorgs are always forwarded.
+//!
+//! A numeric `toBlock` ends the replay, and live logs past it are skipped.
+//! Replays spanning more blocks than allowed are rejected up front. Replayed
+//! logs wait for room in the send queue, while live ones follow its lag policy.
+//!
+//! The history itself comes from a [`LogsBackfill`], which lets the node
+//! serve blocks below the legacy cutoff from the legacy endpoints.
+use alloy_rpc_types_eth::{BlockNumberOrTag, Filter, Log};
+use futures::StreamExt;
+use jsonrpsee::types::ErrorObject;
+use reth_rpc_server_types::result::invalid_params_rpc_err;
+use reth_tracing::tracing::debug;
+use std::sync::Arc;
+use tokio_stream::Stream;
+
+use crate::quota::SubscriptionQueue;
+
+/// Block span of each backfill query, bounding the logs held in memory.
+const REPLAY_CHUNK_SIZE: u64 = 10_000;
+
+/// Source of historical logs for subscription replays.
+#[async_trait::async_trait]
+pub trait LogsBackfill: Send + Sync {
+    /// Returns the latest block that history can be replayed up to.
+    async fn latest_block(&self) -> Result<u64, ErrorObject<'static>>;
+
+    /// Returns the logs matching `filter`, whose range is always a block
+    /// number range.
+    async fn logs(&self, filter: Filter) -> Result<Vec<Log>, ErrorObject<'static>>;
+}
+
+/// Returns the block to replay from, if the filter asks for a replay.
+pub fn replay_from_block(filter: &Filter) -> Option<u64> {
+    match filter.block_option.get_from_block()? {
+        BlockNumberOrTag::Number(number) => Some(*number),
+        BlockNumberOrTag::Earliest => Some(0),
+        _ => None,
+    }
+}
+
+/// Returns the numeric `toBlock` of the filter, if any.
+fn replay_to_block(filter: &Filter) -> Option<u64> {
+    match filter.block_option.get_to_block()? {
+        BlockNumberOrTag::Number(number) => Some(*number),
+        BlockNumberOrTag::Earliest => Some(0),
+        _ => None,
+    }
+}
+
+/// Checks that the replay of `filter` from `from_block` spans at most
+/// `max_blocks` blocks, 0 being no limit.
+pub(crate) async fn check_replay_span(
+    filter: &Filter,
+    from_block: u64,
+    max_blocks: u64,
+    backfill: &dyn LogsBackfill,
+) -> Result<(), ErrorObject<'static>> {
+    if max_blocks == 0 {
+        return Ok(());
+    }
+    let head = backfill.latest_block().await?;
+    let end = replay_to_block(filter).map_or(head, |to_block| to_block.min(head));
+    if end.saturating_sub(from_block) >= max_blocks {
+        return Err(invalid_params_rpc_err(format!(
+            "fromBlock is too far behind, at most {max_blocks} blocks can be replayed"
+        )));
+    }
+    Ok(())
+}
+
+/// Progress of a replay, chunk by chunk up to a head that is read again
+/// until the replay catches up.
+struct Replay {
+    filter: Filter,
+    /// Next block to replay
+    next_block: u64,
+    /// Last block of the range, if bounded
+    to_block: Option<u64>,
+    /// Head the replay is currently catching up with
+    target: Option<u64>,
+}
+
+impl Replay {
+    fn new(filter: Filter, from_block: u64) -> Self {
+        let to_block = replay_to_block(&filter);
+        Self { filter, next_block: from_block, to_block, target: None }
+    }
+
+    /// Returns the logs of the next chunk, or `None` once caught up with the
+    /// head.
+    async fn next_chunk(
+        &mut self,
+        backfill: &dyn LogsBackfill,
+    ) -> Result<Option<Vec<Log>>, ErrorObject<'static>> {
+        if self.target.is_none_or(|target| self.next_block > target) {
+            let head = backfill.latest_block().await?;
+            let target = self.to_block.map_or(head, |to_block| to_block.min(head));
+            if self.next_block > target {
+                return Ok(None);
+            }
+            debug!(
+                target: "xlayer::flashblocks",
+                from_block = self.next_block,
+                head = target,
+                "replaying subscription logs"
+            );
+            self.target = Some(target);
+        }
+
+        let target = self.target.unwrap_or_default();
+        let end = self.next_block.saturating_add(REPLAY_CHUNK_SIZE - 1).min(target);
+        let logs =
+            backfill.logs(self.filter.clone().from_block(self.next_block).to_block(end)).await?;
+        self.next_block = end + 1;
+        Ok(Some(logs))
+    }
+
+    /// Returns `true` if a live log was already replayed, or is past the
+    /// range of the filter.
+    fn skips(&self, log: &Log) -> bool {
+        let Some(number) = log.block_number else {
+            return false;
+        };
+        let replayed = !log.removed && number < self.next_block;
+        replayed || self.to_block.is_some_and(|to_block| number > to_block)
+    }
+}
+
+/// Replays the logs from `from_block` to the head, then pipes the live feed.
+pub(crate) async fn pipe_logs_with_replay<St>(
+    queue: &SubscriptionQueue,
+    filter: Filter,
+    from_block: u64,
+    live: St,
+    backfill: Arc<dyn LogsBackfill>,
+) -> Result<(), ErrorObject<'static>>
+where
+    St: Stream<Item = Log>,
+{
+    let mut replay = Replay::new(filter, from_block);
+    while let Some(logs) = replay.next_chunk(backfill.as_ref()).await? {
+        for log in logs {
+            if !queue.push_wait(&log).await? {
+                return Ok(());
+            }
+        }
+    }
+
+    let live = live.filter(|log| std::future::ready(!replay.skips(log)));
+    queue.pipe(live, |log| log.removed).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    /// Backfill with one log per block, whose head advances on every read.
+    struct MockBackfill {
+        head: Mutex<u64>,
+        head_step: u64,
+        queries: Mutex<Vec<(u64, u64)>>,
+    }
+
+    impl MockBackfill {
+        fn new(head: u64, head_step: u64) -> Self {
+            Self { head: Mutex::new(head), head_step, queries: Mutex::new(Vec::new()) }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl LogsBackfill for MockBackfill {
+        async fn latest_block(&self) -> Result<u64, ErrorObject<'static>> {
+            let mut head = self.head.lock();
+            let latest = *head;
+            *head += self.head_step;
+            Ok(latest)
+        }
+
+        async fn logs(&self, filter: Filter) -> Result<Vec<Log>, ErrorObject<'static>> {
+            let from = filter.get_from_block().unwrap();
+            let to = filter.get_to_block().unwrap();
+            self.queries.lock().push((from, to));
+            Ok((from..=to).map(|number| log(number, false)).collect())
+        }
+    }
+
+    fn log(block_number: u64, removed: bool) -> Log {
+        Log { block_number: Some(block_number), removed, ..Default::default() }
+    }
+
+    async fn replayed_blocks(replay: &mut Replay, backfill: &MockBackfill) -> Vec<u64> {
+        let mut blocks = Vec::new();
+        while let Some(logs) = replay.next_chunk(backfill).await.unwrap() {
+            blocks.extend(logs.into_iter().map(|log| log.block_number.unwrap()));
+        }
+        blocks
+    }
+
+    #[tokio::test]
+    async fn test_replay_catches_up_with_head() {
+        // The head moves 5 blocks every time it is read
+        let backfill = MockBackfill::new(20_005, 5);
+        let mut replay = Replay::new(Filter::new(), 5);
+
+        let blocks = replayed_blocks(&mut replay, &backfill).await;
+        // Heads read: 20_005, 20_010, then caught up at 20_015
+        assert_eq!(blocks, (5..=20_010).collect::<Vec<_>>());
+        assert_eq!(
+            *backfill.queries.lock(),
+            vec![(5, 10_004), (10_005, 20_004), (20_005, 20_005), (20_006, 20_010)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_respects_to_block() {
+        let backfill = MockBackfill::new(100, 10);
+        let mut replay = Replay::new(Filter::new().to_block(50u64), 40);
+
+        let blocks = replayed_blocks(&mut replay, &backfill).await;
+        assert_eq!(blocks, (40..=50).collect::<Vec<_>>());
+
+        // Live logs past toBlock are skipped
+        assert!(replay.skips(&log(51, false)));
+        assert!(replay.skips(&log(51, true)));
+    }
+
+    #[tokio::test]
+    async fn test_replay_span_is_capped() {
+        let backfill = MockBackfill::new(1_000, 0);
+        let filter = Filter::new();
+
+        assert!(check_replay_span(&filter, 901, 100, &backfill).await.is_ok());
+        assert!(check_replay_span(&filter, 900, 100, &backfill).await.is_err());
+        assert!(check_replay_span(&filter, 0, 0, &backfill).await.is_ok());
+
+        // A bounded range only counts up to its toBlock
+        let bounded = Filter::new().to_block(50u64);
+        assert!(check_replay_span(&bounded, 0, 100, &backfill).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_replay_handover_skips_replayed_logs() {
+        let backfill = MockBackfill::new(10, 0);
+        let mut replay = Replay::new(Filter::new(), 0);
+        assert_eq!(replayed_blocks(&mut replay, &backfill).await, (0..=10).collect::<Vec<_>>());
+
+        let live = [log(9, false), log(10, false), log(10, true), log(11, false)];
+        let forwarded: Vec<_> = live
+            .iter()
+            .filter(|log| !replay.skips(log))
+            .map(|log| (log.block_number.unwrap(), log.removed))
+            .collect();
+        assert_eq!(forwarded, vec![(10, true), (11, false)]);
+    }
+}
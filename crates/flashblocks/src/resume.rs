@@ -0,0 +1,150 @@
+//! Resumable flashblocks subscriptions.
+//!
+//! Every flashblocks event carries a [`FlashblockCursor`]. A subscription with
+//! a `resumeFrom` cursor first replays the events after it from a ring buffer
+//! of recent pending blocks, then switches to the live feed. A pending block
+//! holds every flashblock of its height so far, so only the latest one per
+//! height is buffered. Divergence notices carry no cursor and are not replayed.
+use alloy_consensus::BlockHeader as _;
+use parking_lot::Mutex;
+use reth_optimism_flashblocks::{PendingBlockRx, PendingFlashBlock};
+use reth_primitives_traits::NodePrimitives;
+use std::{collections::VecDeque, sync::Arc};
+
+use crate::pubsub::FlashblockCursor;
+
+/// Ring buffer of the latest pending block of recent heights.
+#[derive(Debug)]
+pub(crate) struct PendingBlockBuffer<T> {
+    /// Blocks by height, oldest first
+    blocks: Mutex<VecDeque<(u64, T)>>,
+    /// Number of heights kept
+    capacity: usize,
+}
+
+impl<T> PendingBlockBuffer<T> {
+    /// Creates a buffer keeping the latest `capacity` heights.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { blocks: Mutex::new(VecDeque::with_capacity(capacity)), capacity }
+    }
+}
+
+impl<T: Clone> PendingBlockBuffer<T> {
+    /// Buffers a pending block, replacing the previous one of its height.
+    pub(crate) fn push(&self, block_number: u64, pending_block: T) {
+        let mut blocks = self.blocks.lock();
+        if blocks.back().is_some_and(|(last, _)| *last == block_number) {
+            blocks.pop_back();
+        }
+        blocks.push_back((block_number, pending_block));
+        while blocks.len() > self.capacity {
+            blocks.pop_front();
+        }
+    }
+
+    /// Returns `true` if the events after the cursor are still buffered.
+    /// Nothing is covered before the first pending block is buffered.
+    pub(crate) fn covers(&self, cursor: &FlashblockCursor) -> bool {
+        self.blocks.lock().front().is_some_and(|(first, _)| *first <= cursor.block_number)
+    }
+
+    /// Drops every buffered pending block.
+    pub(crate) fn clear(&self) {
+        self.blocks.lock().clear();
+    }
+
+    /// Returns the buffered pending blocks, oldest first.
+    pub(crate) fn snapshot(&self) -> Vec<T> {
+        self.blocks.lock().iter().map(|(_, pending_block)| pending_block.clone()).collect()
+    }
+}
+
+/// Returns `true` if the transaction at `tx_index` of the block was streamed
+/// at or before the cursor.
+pub(crate) fn is_tx_covered(cursor: &FlashblockCursor, block_number: u64, tx_index: u64) -> bool {
+    block_number < cursor.block_number
+        || (block_number == cursor.block_number
+            && cursor.tx_index.is_some_and(|covered| tx_index <= covered))
+}
+
+/// Returns `true` if an event was already sent, its cursor being at or
+/// before `sent`. Events without a cursor are never considered sent.
+pub(crate) fn is_sent(sent: &FlashblockCursor, event_cursor: Option<FlashblockCursor>) -> bool {
+    event_cursor.is_some_and(|event_cursor| event_cursor <= *sent)
+}
+
+/// Buffers every pending block received until the channel closes.
+pub(crate) async fn buffer_pending_blocks<N: NodePrimitives>(
+    mut pending_block_rx: PendingBlockRx<N>,
+    buffer: Arc<PendingBlockBuffer<PendingFlashBlock<N>>>,
+) {
+    loop {
+        let pending_block = pending_block_rx.borrow_and_update().clone();
+        if let Some(pending_block) = pending_block {
+            buffer.push(pending_block.block().header().number(), pending_block);
+        }
+        if pending_block_rx.changed().await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(block_number: u64, flashblock_index: u64, tx_index: Option<u64>) -> FlashblockCursor {
+        FlashblockCursor { block_number, flashblock_index, tx_index }
+    }
+
+    #[test]
+    fn test_pending_block_buffer_keeps_latest_per_height() {
+        let buffer = PendingBlockBuffer::new(4);
+        buffer.push(10, "10/0");
+        buffer.push(10, "10/1");
+        buffer.push(11, "11/0");
+        assert_eq!(buffer.snapshot(), vec!["10/1", "11/0"]);
+
+        for block_number in 12..16 {
+            buffer.push(block_number, "later");
+        }
+        let snapshot = buffer.snapshot();
+        assert_eq!(snapshot.len(), 4);
+        assert!(!snapshot.contains(&"11/0"));
+    }
+
+    #[test]
+    fn test_pending_block_buffer_covers() {
+        let buffer = PendingBlockBuffer::new(16);
+        // Nothing is buffered yet
+        assert!(!buffer.covers(&cursor(10, 0, None)));
+
+        buffer.push(10, ());
+        buffer.push(11, ());
+        assert!(buffer.covers(&cursor(10, 0, Some(3))));
+        assert!(buffer.covers(&cursor(12, 0, None)));
+        assert!(!buffer.covers(&cursor(9, 5, None)));
+    }
+
+    #[test]
+    fn test_is_tx_covered() {
+        let header = cursor(10, 1, None);
+        assert!(is_tx_covered(&header, 9, 100));
+        assert!(!is_tx_covered(&header, 10, 0));
+
+        let tx = cursor(10, 1, Some(2));
+        assert!(is_tx_covered(&tx, 10, 2));
+        assert!(!is_tx_covered(&tx, 10, 3));
+        assert!(!is_tx_covered(&tx, 11, 0));
+    }
+
+    #[test]
+    fn test_is_sent() {
+        let sent = cursor(10, 1, Some(2));
+        assert!(is_sent(&sent, Some(cursor(10, 1, None))));
+        assert!(is_sent(&sent, Some(sent)));
+        assert!(!is_sent(&sent, Some(cursor(10, 2, None))));
+        assert!(!is_sent(&sent, Some(cursor(10, 1, Some(3)))));
+        assert!(!is_sent(&sent, None));
+    }
+}
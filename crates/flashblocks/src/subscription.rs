@@ -1,30 +1,39 @@
-use crate::pubsub::{
-    EnrichedTransaction, FlashblockParams, FlashblockStreamEvent, FlashblockSubscriptionKind,
-    FlashblocksFilter,
+use crate::{
+    execution::{execute_pending_blocks, ExecutedTx, ExecutedTxs, SharedExecution},
+    pending_logs::{pending_logs_filter, pipe_pending_logs},
+    preconfirmed::{block_tx_hashes, diverged_txs, Divergence, Preconfirmed},
+    pubsub::{
+        EnrichedTransaction, FlashblockCursor, FlashblockParams, FlashblockStreamEvent,
+        FlashblockSubscriptionKind, FlashblocksFilter,
+    },
+    quota::{SubscriptionLimits, SubscriptionQuota},
+    replay::{check_replay_span, pipe_logs_with_replay, replay_from_block, LogsBackfill},
+    resume::{buffer_pending_blocks, is_sent, is_tx_covered, PendingBlockBuffer},
 };
 use alloy_consensus::{transaction::TxHashRef, BlockHeader as _, Transaction as _, TxReceipt as _};
 use alloy_json_rpc::RpcObject;
 use alloy_primitives::{Address, TxHash, U256};
-use alloy_rpc_types_eth::{Header, TransactionInfo};
-use futures::StreamExt;
-use jsonrpsee::{
-    proc_macros::rpc, server::SubscriptionMessage, types::ErrorObject, PendingSubscriptionSink,
-    SubscriptionSink,
+use alloy_rpc_types_eth::{
+    pubsub::{Params as AlloyParams, SubscriptionKind as AlloySubscriptionKind},
+    Filter, Header, Log, TransactionInfo,
 };
+use futures::StreamExt;
+use jsonrpsee::{proc_macros::rpc, types::ErrorObject, PendingSubscriptionSink, SubscriptionSink};
 use moka::policy::EvictionPolicy;
 use moka::sync::Cache;
 use reth_optimism_flashblocks::{PendingBlockRx, PendingFlashBlock};
 use reth_primitives_traits::{
     NodePrimitives, Recovered, RecoveredBlock, SealedBlock, TransactionMeta,
 };
+use reth_provider::{CanonStateNotification, CanonStateSubscriptions};
 use reth_rpc::eth::pubsub::EthPubSub;
 use reth_rpc_convert::{transaction::ConvertReceiptInput, RpcConvert};
 use reth_rpc_eth_api::{EthApiTypes, RpcNodeCore, RpcReceipt, RpcTransaction};
 use reth_rpc_eth_types::utils::calculate_gas_used_and_next_log_index;
 use reth_rpc_server_types::result::{internal_rpc_err, invalid_params_rpc_err};
-use reth_storage_api::BlockNumReader;
+use reth_storage_api::{BlockNumReader, StateProviderFactory};
 use reth_tasks::TaskSpawner;
-use reth_tracing::tracing::{trace, warn};
+use reth_tracing::tracing::{debug, trace, warn};
 use std::{collections::HashSet, future::ready, sync::Arc};
 use tokio_stream::{wrappers::WatchStream, Stream};
 
@@ -41,6 +50,17 @@ type EnrichedTxItem<C> = EnrichedTransaction<
     RpcReceipt<<C as RpcConvert>::Network>,
 >;
 
+/// Transactions streamed per pending height, with their index in the block
+type StreamedTxs = Preconfirmed<(TxHash, u64)>;
+
+/// Update fed to the flashblocks stream
+enum ChainUpdate<N: NodePrimitives> {
+    /// A pending block, with its executed transactions if the subscription
+    /// requires execution
+    Pending(PendingFlashBlock<N>, Option<ExecutedTxs>),
+    Canonical(CanonStateNotification<N>),
+}
+
 /// Context for enriching transactions and receipts from a block
 struct EnrichmentContext<'a, N: NodePrimitives, C> {
     tx: &'a N::SignedTx,
@@ -79,24 +99,59 @@ pub struct FlashblocksPubSub<Eth: EthApiTypes, N: NodePrimitives> {
 impl<Eth: EthApiTypes, N: NodePrimitives> FlashblocksPubSub<Eth, N>
 where
     Eth: RpcNodeCore<Primitives = N> + 'static,
-    Eth::Provider: BlockNumReader,
+    Eth::Provider: BlockNumReader + StateProviderFactory,
     Eth::RpcConvert: RpcConvert<Primitives = N> + Clone,
 {
     /// Creates a new, shareable instance.
     ///
-    /// Subscription tasks are spawned via [`tokio::task::spawn`]
+    /// Background tasks are spawned with `subscription_task_spawner`.
+    ///
+    /// Logs subscriptions with a `fromBlock` are replayed from `logs_backfill`
+    /// before the live feed. Without it, `fromBlock` is ignored.
+    ///
+    /// Canonical blocks from `canon_state` are compared with the pending
+    /// blocks streamed for their height.
+    ///
+    /// Recent pending blocks are buffered by a spawned task, for flashblocks
+    /// subscriptions resuming from a cursor.
+    ///
+    /// `eth_api` converts RPC transactions, and re-executes pending blocks in
+    /// a spawned task shared by the subscriptions asking for state diffs or
+    /// call traces.
+    ///
+    /// Subscriptions are rejected beyond the `limits`, and all but `syncing`
+    /// ones are sent through a bounded queue per subscription.
     pub fn new(
         eth_pubsub: EthPubSub<Eth>,
         pending_block_rx: PendingBlockRx<N>,
+        canon_state: Arc<dyn CanonStateSubscriptions<Primitives = N>>,
         subscription_task_spawner: Box<dyn TaskSpawner>,
-        tx_converter: Eth::RpcConvert,
-        max_subscribed_addresses: usize,
+        eth_api: Eth,
+        limits: SubscriptionLimits,
+        logs_backfill: Option<Arc<dyn LogsBackfill>>,
     ) -> Self {
+        let resume_buffer = Arc::new(PendingBlockBuffer::new(limits.max_resume_blocks));
+        subscription_task_spawner.spawn(Box::pin(buffer_pending_blocks(
+            pending_block_rx.clone(),
+            resume_buffer.clone(),
+        )));
+        let execution = Arc::new(SharedExecution::new(limits.max_resume_blocks));
+        subscription_task_spawner.spawn(Box::pin(execute_pending_blocks(
+            pending_block_rx.clone(),
+            eth_api.evm_config().clone(),
+            eth_api.provider().clone(),
+            execution.clone(),
+        )));
+
         let inner = FlashblocksPubSubInner {
             pending_block_rx,
-            subscription_task_spawner,
-            tx_converter,
-            max_subscribed_addresses,
+            canon_state,
+            resume_buffer,
+            execution,
+            tx_converter: eth_api.converter().clone(),
+            limits,
+            quota: Arc::new(SubscriptionQuota::new(limits)),
+            logs_backfill,
         };
         Self { eth_pubsub, inner: Arc::new(inner) }
     }
@@ -132,7 +187,17 @@ where
                 };
 
                 let fb_stream = self.new_flashblocks_stream(filter);
-                pipe_from_flashblocks_stream::<N, Eth, _>(accepted_sink, fb_stream).await
+                let queue = self.inner.quota.queue(accepted_sink);
+                queue.run(queue.pipe(fb_stream, |event| event.is_divergence())).await
+            }
+            FlashblockSubscriptionKind::PendingLogs => {
+                let filter = pending_logs_filter(params.as_ref())?;
+
+                let pending =
+                    WatchStream::new(self.inner.pending_block_rx.clone()).filter_map(ready);
+                let canonical = self.inner.canon_state.canonical_state_stream();
+                let queue = self.inner.quota.queue(accepted_sink);
+                queue.run(pipe_pending_logs(&queue, filter, pending, canonical)).await
             }
             FlashblockSubscriptionKind::Standard(alloy_kind) => {
                 let standard_params = match params {
@@ -144,7 +209,66 @@ where
                         ))
                     }
                 };
-                self.eth_pubsub.handle_accepted(accepted_sink, alloy_kind, standard_params).await
+
+                // Syncing status changes are rare, the other kinds go through
+                // the send queue
+                match alloy_kind {
+                    AlloySubscriptionKind::NewHeads => {
+                        let queue = self.inner.quota.queue(accepted_sink);
+                        queue.run(queue.pipe(self.eth_pubsub.new_headers_stream(), |_| false)).await
+                    }
+                    AlloySubscriptionKind::Logs => {
+                        let filter = match standard_params {
+                            Some(AlloyParams::Logs(filter)) => *filter,
+                            Some(AlloyParams::Bool(_)) => {
+                                return Err(invalid_params_rpc_err("Invalid params for logs"));
+                            }
+                            _ => Filter::default(),
+                        };
+                        let live = self.eth_pubsub.log_stream(filter.clone());
+                        let queue = self.inner.quota.queue(accepted_sink);
+                        match (replay_from_block(&filter), self.inner.logs_backfill.clone()) {
+                            (Some(from_block), Some(backfill)) => {
+                                queue
+                                    .run(pipe_logs_with_replay(
+                                        &queue, filter, from_block, live, backfill,
+                                    ))
+                                    .await
+                            }
+                            _ => queue.run(queue.pipe(live, |log: &Log| log.removed)).await,
+                        }
+                    }
+                    AlloySubscriptionKind::NewPendingTransactions => {
+                        let queue = self.inner.quota.queue(accepted_sink);
+                        if let Some(AlloyParams::Bool(true)) = standard_params {
+                            let tx_converter = self.inner.tx_converter.clone();
+                            let txs = self.eth_pubsub.full_pending_transaction_stream().filter_map(
+                                move |event| {
+                                    let tx = tx_converter
+                                        .fill_pending(event.transaction.to_consensus())
+                                        .inspect_err(|err| {
+                                            warn!(
+                                                target: "xlayer::flashblocks",
+                                                error = ?err,
+                                                "Failed to convert pending transaction"
+                                            );
+                                        })
+                                        .ok();
+                                    ready(tx)
+                                },
+                            );
+                            queue.run(queue.pipe(txs, |_| false)).await
+                        } else {
+                            let hashes = self.eth_pubsub.pending_transaction_hashes_stream();
+                            queue.run(queue.pipe(hashes, |_| false)).await
+                        }
+                    }
+                    AlloySubscriptionKind::Syncing => {
+                        self.eth_pubsub
+                            .handle_accepted(accepted_sink, alloy_kind, standard_params)
+                            .await
+                    }
+                }
             }
         }
     }
@@ -155,7 +279,7 @@ impl<Eth: EthApiTypes, N: NodePrimitives>
     FlashblocksPubSubApiServer<RpcTransaction<Eth::NetworkTypes>> for FlashblocksPubSub<Eth, N>
 where
     Eth: RpcNodeCore<Primitives = N> + 'static,
-    Eth::Provider: BlockNumReader,
+    Eth::Provider: BlockNumReader + StateProviderFactory,
     Eth::RpcConvert: RpcConvert<Primitives = N> + Clone,
 {
     async fn subscribe(
@@ -174,17 +298,66 @@ where
                 return Ok(());
             };
 
-            if let Err(err) = params.validate(self.inner.max_subscribed_addresses) {
+            if let Err(err) = params.validate(self.inner.limits.max_addresses) {
                 pending.reject(err).await;
                 return Ok(());
             }
+
+            if let FlashblockParams::FlashblocksFilter(filter) = params
+                && let Some(cursor) = &filter.resume_from
+                && !self.inner.covers(filter, cursor)
+            {
+                pending
+                    .reject(invalid_params_rpc_err("resumeFrom cursor is no longer buffered"))
+                    .await;
+                return Ok(());
+            }
+        }
+
+        if kind == FlashblockSubscriptionKind::PendingLogs
+            && let Err(err) = pending_logs_filter(params.as_ref())
+                .and_then(|filter| filter.validate(self.inner.limits.max_addresses))
+        {
+            pending.reject(err).await;
+            return Ok(());
+        }
+
+        if let FlashblockSubscriptionKind::Standard(AlloySubscriptionKind::Logs) = kind
+            && let Some(FlashblockParams::Standard(AlloyParams::Logs(filter))) = &params
+            && let Some(from_block) = replay_from_block(filter)
+            && let Some(backfill) = &self.inner.logs_backfill
+            && let Err(err) = check_replay_span(
+                filter,
+                from_block,
+                self.inner.limits.max_replay_blocks,
+                backfill.as_ref(),
+            )
+            .await
+        {
+            pending.reject(err).await;
+            return Ok(());
         }
 
+        let requires_execution = kind == FlashblockSubscriptionKind::Flashblocks
+            && matches!(
+                &params,
+                Some(FlashblockParams::FlashblocksFilter(filter))
+                    if filter.sub_tx_filter.requires_execution()
+            );
+        let permit = match self.inner.quota.try_acquire(pending.connection_id(), requires_execution)
+        {
+            Ok(permit) => permit,
+            Err(err) => {
+                pending.reject(err).await;
+                return Ok(());
+            }
+        };
+
         let sink = pending.accept().await?;
-        let pubsub = self.clone();
-        self.inner.subscription_task_spawner.spawn(Box::pin(async move {
-            let _ = pubsub.handle_accepted(sink, kind, params).await;
-        }));
+        let _permit = permit;
+        // An error closes the subscription with a notice to the client, like
+        // a subscription lagging behind
+        self.handle_accepted(sink, kind, params).await?;
 
         Ok(())
     }
@@ -194,19 +367,39 @@ where
 pub struct FlashblocksPubSubInner<Eth: EthApiTypes, N: NodePrimitives> {
     /// Pending block receiver from flashblocks, if available
     pub(crate) pending_block_rx: PendingBlockRx<N>,
-    /// The type that's used to spawn subscription tasks.
-    pub(crate) subscription_task_spawner: Box<dyn TaskSpawner>,
+    /// Canonical state notifications, to check what was pre-confirmed.
+    pub(crate) canon_state: Arc<dyn CanonStateSubscriptions<Primitives = N>>,
+    /// Recent pending blocks, replayed for resumed subscriptions.
+    pub(crate) resume_buffer: Arc<PendingBlockBuffer<PendingFlashBlock<N>>>,
+    /// Pending blocks executed for subscriptions requiring execution.
+    pub(crate) execution: Arc<SharedExecution<N>>,
     /// RPC transaction converter.
     pub(crate) tx_converter: Eth::RpcConvert,
-    /// Maximum number of subscribed addresses.
-    pub(crate) max_subscribed_addresses: usize,
+    /// Subscription limits.
+    pub(crate) limits: SubscriptionLimits,
+    /// Active subscriptions, checked against the limits.
+    pub(crate) quota: Arc<SubscriptionQuota>,
+    /// Source of historical logs for logs subscriptions with a `fromBlock`.
+    pub(crate) logs_backfill: Option<Arc<dyn LogsBackfill>>,
 }
 
 impl<Eth: EthApiTypes, N: NodePrimitives> FlashblocksPubSubInner<Eth, N>
 where
     Eth: RpcNodeCore<Primitives = N> + 'static,
+    Eth::Provider: StateProviderFactory,
     Eth::RpcConvert: RpcConvert<Primitives = N> + Clone,
 {
+    /// Returns `true` if the events after the cursor are still buffered for
+    /// the filter. Subscriptions requiring execution resume from the executed
+    /// pending blocks.
+    fn covers(&self, filter: &FlashblocksFilter, cursor: &FlashblockCursor) -> bool {
+        if filter.sub_tx_filter.requires_execution() {
+            self.execution.buffer().covers(cursor)
+        } else {
+            self.resume_buffer.covers(cursor)
+        }
+    }
+
     fn new_flashblocks_stream(
         &self,
         filter: FlashblocksFilter,
@@ -216,19 +409,131 @@ where
             .max_capacity(MAX_TXHASH_CACHE_SIZE)
             .eviction_policy(EvictionPolicy::lru())
             .build();
+        let mut streamed = StreamedTxs::default();
 
-        WatchStream::new(self.pending_block_rx.clone())
-            .filter_map(move |pending_block_opt| {
-                ready(pending_block_opt.map(|pending_block| {
-                    futures::stream::iter(Self::flashblock_to_stream_events(
-                        &pending_block,
-                        &filter,
-                        &tx_converter,
-                        &txhash_cache,
-                    ))
-                }))
+        let replayed = match filter.resume_from {
+            Some(cursor) => {
+                self.replay_events(&cursor, &filter, &tx_converter, &txhash_cache, &mut streamed)
+            }
+            None => Vec::new(),
+        };
+        // The live feed starts with the current pending block, which was
+        // already replayed
+        let sent = filter.resume_from.map(|cursor| {
+            replayed.iter().filter_map(|event| event.cursor()).fold(cursor, std::cmp::max)
+        });
+
+        let pending = if filter.sub_tx_filter.requires_execution() {
+            WatchStream::new(self.execution.subscribe())
+                .filter_map(|executed_opt| {
+                    ready(executed_opt.map(|executed| {
+                        ChainUpdate::Pending(executed.pending_block, Some(executed.executed))
+                    }))
+                })
+                .left_stream()
+        } else {
+            WatchStream::new(self.pending_block_rx.clone())
+                .filter_map(|pending_block_opt| {
+                    ready(
+                        pending_block_opt
+                            .map(|pending_block| ChainUpdate::Pending(pending_block, None)),
+                    )
+                })
+                .right_stream()
+        };
+        let canonical = self.canon_state.canonical_state_stream().map(ChainUpdate::Canonical);
+
+        let live = futures::stream::select(pending, canonical)
+            .map(move |update| {
+                let events = match update {
+                    ChainUpdate::Pending(pending_block, executed) => {
+                        Self::flashblock_to_stream_events(
+                            &pending_block,
+                            &filter,
+                            &tx_converter,
+                            &txhash_cache,
+                            &mut streamed,
+                            executed.as_deref(),
+                        )
+                    }
+                    ChainUpdate::Canonical(notification) => {
+                        let mut events = Vec::new();
+                        for block in notification.committed().blocks_iter() {
+                            events.extend(Self::divergence_events(
+                                block,
+                                &txhash_cache,
+                                &mut streamed,
+                            ));
+                        }
+                        events
+                    }
+                };
+                futures::stream::iter(events)
             })
             .flatten()
+            .filter(move |event| ready(sent.is_none_or(|sent| !is_sent(&sent, event.cursor()))));
+
+        futures::stream::iter(replayed).chain(live)
+    }
+
+    /// Returns the events after the cursor from the buffered pending blocks,
+    /// or the executed ones if the subscription requires execution.
+    /// Transactions at or before the cursor are marked as processed, so the
+    /// live feed doesn't send them again.
+    fn replay_events(
+        &self,
+        cursor: &FlashblockCursor,
+        filter: &FlashblocksFilter,
+        tx_converter: &Eth::RpcConvert,
+        txhash_cache: &Cache<TxHash, ()>,
+        streamed: &mut StreamedTxs,
+    ) -> Vec<FlashblockItem<N, Eth::RpcConvert>> {
+        let buffered: Vec<_> = if filter.sub_tx_filter.requires_execution() {
+            self.execution
+                .buffer()
+                .snapshot()
+                .into_iter()
+                .map(|executed| (executed.pending_block, Some(executed.executed)))
+                .collect()
+        } else {
+            self.resume_buffer
+                .snapshot()
+                .into_iter()
+                .map(|pending_block| (pending_block, None))
+                .collect()
+        };
+
+        let mut events = Vec::new();
+        for (pending_block, executed) in buffered {
+            let block = pending_block.block();
+            let block_number = block.header().number();
+            for (idx, (_, tx)) in block.transactions_with_sender().enumerate() {
+                if is_tx_covered(cursor, block_number, idx as u64) {
+                    txhash_cache.insert(*tx.tx_hash(), ());
+                }
+            }
+
+            events.extend(
+                Self::flashblock_to_stream_events(
+                    &pending_block,
+                    filter,
+                    tx_converter,
+                    txhash_cache,
+                    streamed,
+                    executed.as_deref(),
+                )
+                .into_iter()
+                .filter(|event| !is_sent(cursor, event.cursor())),
+            );
+        }
+
+        debug!(
+            target: "xlayer::flashblocks",
+            ?cursor,
+            replayed = events.len(),
+            "replaying flashblocks subscription"
+        );
+        events
     }
 
     /// Convert a flashblock into a stream of events (header + transaction messages)
@@ -237,18 +542,30 @@ where
         filter: &FlashblocksFilter,
         tx_converter: &Eth::RpcConvert,
         txhash_cache: &Cache<TxHash, ()>,
+        streamed: &mut StreamedTxs,
+        executed: Option<&[Arc<ExecutedTx>]>,
     ) -> Vec<FlashblockItem<N, Eth::RpcConvert>> {
         let block = pending_block.block();
         let receipts = pending_block.receipts.as_ref();
         let sealed_block = block.sealed_block();
         let block_number = sealed_block.header().number();
+        let flashblock_index = pending_block.last_flashblock_index;
+        let cursor = |tx_index| FlashblockCursor { block_number, flashblock_index, tx_index };
 
         let mut events = Vec::new();
+        if streamed.is_stale(block_number) {
+            trace!(target: "xlayer::flashblocks", block_number, "skipping stale pending block");
+            return events;
+        }
 
         if filter.header_info {
             match extract_header_from_pending_block(pending_block) {
                 Ok(header) => {
-                    events.push(FlashblockStreamEvent::Header { block_number, header });
+                    events.push(FlashblockStreamEvent::Header {
+                        block_number,
+                        cursor: cursor(None),
+                        header,
+                    });
                 }
                 Err(e) => {
                     warn!(target: "xlayer::flashblocks", error = ?e, "Failed to extract header");
@@ -256,22 +573,82 @@ where
             }
         }
 
-        events.extend(
-            Self::collect_transactions(
-                block,
-                filter,
-                receipts,
-                tx_converter,
-                sealed_block,
-                txhash_cache,
-            )
-            .into_iter()
-            .map(|transaction| FlashblockStreamEvent::Transaction { block_number, transaction }),
-        );
+        let mut logs = Vec::new();
+        let mut txs = Vec::new();
+        for (idx, transaction, tx_logs) in Self::collect_transactions(
+            block,
+            filter,
+            receipts,
+            tx_converter,
+            sealed_block,
+            txhash_cache,
+            executed,
+        ) {
+            let tx_index = idx as u64;
+            txs.push((transaction.tx_hash, tx_index));
+            events.push(FlashblockStreamEvent::Transaction {
+                block_number,
+                cursor: cursor(Some(tx_index)),
+                transaction,
+            });
+            logs.extend(tx_logs);
+        }
+
+        if !logs.is_empty() {
+            // After the transactions, so the cursors stay monotonic
+            events.push(FlashblockStreamEvent::Logs {
+                block_number,
+                cursor: cursor(txs.last().map(|(_, tx_index)| *tx_index)),
+                logs,
+            });
+        }
+        streamed.extend(block_number, txs);
 
         events
     }
 
+    /// Compares a canonical block with the transactions streamed for its
+    /// height, returning an event for each one missing or moved. Diverged
+    /// transactions are forgotten, so they are streamed again if they land in
+    /// a later pending block.
+    fn divergence_events(
+        block: &RecoveredBlock<N::Block>,
+        txhash_cache: &Cache<TxHash, ()>,
+        streamed: &mut StreamedTxs,
+    ) -> Vec<FlashblockItem<N, Eth::RpcConvert>> {
+        let block_number = block.header().number();
+        let txs = streamed.take_canonical(block_number);
+        if txs.is_empty() {
+            return Vec::new();
+        }
+
+        let canonical = block_tx_hashes::<N>(block);
+        let events: Vec<_> = diverged_txs(txs, &canonical)
+            .into_iter()
+            .map(|(tx_hash, divergence)| {
+                txhash_cache.invalidate(&tx_hash);
+                match divergence {
+                    Divergence::Dropped => FlashblockStreamEvent::Dropped { block_number, tx_hash },
+                    Divergence::Moved(transaction_index) => {
+                        FlashblockStreamEvent::Reorged { block_number, tx_hash, transaction_index }
+                    }
+                }
+            })
+            .collect();
+
+        if !events.is_empty() {
+            debug!(
+                target: "xlayer::flashblocks",
+                block_number,
+                diverged = events.len(),
+                "streamed transactions diverge from canonical block"
+            );
+        }
+        events
+    }
+
+    /// Collects the new transactions matching the filter, along with their
+    /// index in the block and matching logs if `log_info` is set.
     fn collect_transactions(
         block: &RecoveredBlock<N::Block>,
         filter: &FlashblocksFilter,
@@ -279,7 +656,8 @@ where
         tx_converter: &Eth::RpcConvert,
         sealed_block: &SealedBlock<N::Block>,
         txhash_cache: &Cache<TxHash, ()>,
-    ) -> Vec<EnrichedTxItem<Eth::RpcConvert>> {
+        executed: Option<&[Arc<ExecutedTx>]>,
+    ) -> Vec<(usize, EnrichedTxItem<Eth::RpcConvert>, Vec<Log>)> {
         block
             .transactions_with_sender()
             .enumerate()
@@ -293,18 +671,42 @@ where
                     warn!(target: "xlayer::flashblocks", "failed to collect transaction idx: {idx}, missing receipt");
                     return None;
                 };
+                let executed_tx = executed.and_then(|executed| executed.get(idx));
+                // Left for a later flashblock if the execution failed, rather
+                // than sent without its state diff and internal calls
+                if executed.is_some() && executed_tx.is_none() {
+                    trace!(target: "xlayer::flashblocks", "skipping transaction idx: {idx}, not executed");
+                    return None;
+                }
 
                 if filter.requires_address_filtering() {
                     let matches_filter = Self::is_address_in_transaction(
                         *sender,
                         tx,
                         Some(receipt),
+                        executed_tx.map(|executed_tx| &executed_tx.call_targets),
                         &filter.sub_tx_filter.subscribe_addresses,
                     );
                     if !matches_filter {
                         return None;
                     }
                 }
+
+                let matching_logs: Vec<_> = match &filter.log_filter {
+                    Some(log_filter) => {
+                        let logs: Vec<_> = receipt
+                            .logs()
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, log)| log_filter.matches(log))
+                            .collect();
+                        if logs.is_empty() {
+                            return None;
+                        }
+                        logs
+                    }
+                    None => Vec::new(),
+                };
                 txhash_cache.insert(tx_hash, ());
 
                 let ctx = EnrichmentContext {
@@ -318,8 +720,55 @@ where
 
                 let tx_data = Self::enrich_transaction_data(filter, &ctx);
                 let tx_receipt = Self::enrich_receipt(filter, receipt, receipts, &ctx);
+                let logs = if filter.log_info {
+                    Self::rpc_logs(matching_logs, receipts, &ctx)
+                } else {
+                    Vec::new()
+                };
+
+                let sub_tx_filter = &filter.sub_tx_filter;
+                let state_diff = executed_tx
+                    .filter(|_| sub_tx_filter.state_diff)
+                    .map(|executed_tx| executed_tx.state_diff_of(&sub_tx_filter.subscribe_addresses));
+                let call_trace = executed_tx
+                    .filter(|_| sub_tx_filter.call_trace)
+                    .map(|executed_tx| executed_tx.call_trace.clone());
 
-                Some(EnrichedTransaction { tx_hash, tx_data, receipt: tx_receipt })
+                Some((
+                    idx,
+                    EnrichedTransaction {
+                        tx_hash,
+                        tx_data,
+                        receipt: tx_receipt,
+                        state_diff,
+                        call_trace,
+                    },
+                    logs,
+                ))
+            })
+            .collect()
+    }
+
+    /// Converts logs of a transaction, given with their index in its receipt,
+    /// into RPC logs of the pending block.
+    fn rpc_logs(
+        logs: Vec<(usize, &alloy_primitives::Log)>,
+        receipts: &[N::Receipt],
+        ctx: &EnrichmentContext<'_, N, Eth::RpcConvert>,
+    ) -> Vec<Log> {
+        let (_, first_log_index) = calculate_gas_used_and_next_log_index(ctx.idx as u64, receipts);
+        let header = ctx.sealed_block.header();
+
+        logs.into_iter()
+            .map(|(log_idx, log)| Log {
+                inner: log.clone(),
+                block_hash: Some(ctx.sealed_block.hash()),
+                block_number: Some(header.number()),
+                block_timestamp: Some(header.timestamp()),
+                transaction_hash: Some(ctx.tx_hash),
+                transaction_index: Some(ctx.idx as u64),
+                log_index: Some(first_log_index + log_idx as u64),
+                removed: false,
             })
             .collect()
     }
@@ -393,6 +842,7 @@ where
         sender: Address,
         tx: &N::SignedTx,
         receipt: Option<&N::Receipt>,
+        call_targets: Option<&HashSet<Address>>,
         addresses: &HashSet<Address>,
     ) -> bool {
         // Check sender
@@ -416,6 +866,13 @@ where
             }
         }
 
+        // Check internal call targets, if the transaction was re-executed
+        if let Some(call_targets) = call_targets
+            && !call_targets.is_disjoint(addresses)
+        {
+            return true;
+        }
+
         false
     }
 }
@@ -425,7 +882,7 @@ where
 pub struct SubscriptionSerializeError(serde_json::Error);
 
 impl SubscriptionSerializeError {
-    const fn new(err: serde_json::Error) -> Self {
+    pub(crate) const fn new(err: serde_json::Error) -> Self {
         Self(err)
     }
 }
@@ -442,44 +899,6 @@ impl From<SubscriptionSerializeError> for ErrorObject<'static> {
     }
 }
 
-/// Pipes all stream items to the subscription sink.
-async fn pipe_from_flashblocks_stream<N, Eth, FbSt>(
-    sink: SubscriptionSink,
-    mut fb_stream: FbSt,
-) -> Result<(), ErrorObject<'static>>
-where
-    N: NodePrimitives,
-    N::BlockHeader: alloy_consensus::BlockHeader,
-    Eth: EthApiTypes + RpcNodeCore<Primitives = N> + 'static,
-    Eth::RpcConvert: RpcConvert<Primitives = N> + Clone,
-    FbSt: Stream<Item = FlashblockItem<N, Eth::RpcConvert>> + Unpin,
-{
-    loop {
-        tokio::select! {
-            _ = sink.closed() => {
-                // connection dropped
-                break Ok(())
-            },
-            maybe_fb_item = fb_stream.next() => {
-                let Some(item) = maybe_fb_item else {
-                    // stream ended
-                    break Ok(());
-                };
-
-                let msg = SubscriptionMessage::new(
-                    sink.method_name(),
-                    sink.subscription_id(),
-                    &item
-                ).map_err(SubscriptionSerializeError::new)?;
-
-                if sink.send(msg).await.is_err() {
-                    break Ok(());
-                }
-            }
-        }
-    }
-}
-
 /// Extract `Header` from `PendingFlashBlock`
 fn extract_header_from_pending_block<N: NodePrimitives>(
     pending_block: &PendingFlashBlock<N>,
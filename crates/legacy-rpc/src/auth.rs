@@ -0,0 +1,132 @@
+//! Authentication for the legacy endpoints.
+//!
+//! Everything here is baked into the shared [`reqwest::Client`], so it
+//! applies to routed requests and health probes alike.
+use std::path::PathBuf;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION},
+    ClientBuilder, Identity,
+};
+
+/// Credentials and headers sent to the legacy endpoints
+#[derive(Clone, Debug, Default)]
+pub struct LegacyAuthConfig {
+    /// Static headers in `Name: value` form
+    pub headers: Vec<String>,
+    /// File holding a bearer token, read once at startup
+    pub bearer_token_file: Option<PathBuf>,
+    /// Basic auth credentials in `user:password` form
+    pub basic_auth: Option<String>,
+    /// PEM client certificate for mTLS
+    pub tls_client_cert: Option<PathBuf>,
+    /// PEM private key of the client certificate
+    pub tls_client_key: Option<PathBuf>,
+}
+
+impl LegacyAuthConfig {
+    /// Applies headers, credentials and the client identity to `builder`.
+    pub fn apply(&self, builder: ClientBuilder) -> Result<ClientBuilder, String> {
+        let mut builder = builder.default_headers(self.default_headers()?);
+
+        if let (Some(cert), Some(key)) = (&self.tls_client_cert, &self.tls_client_key) {
+            let mut pem = std::fs::read(cert)
+                .map_err(|e| format!("Failed to read TLS client cert {}: {e}", cert.display()))?;
+            pem.extend(
+                std::fs::read(key)
+                    .map_err(|e| format!("Failed to read TLS client key {}: {e}", key.display()))?,
+            );
+            let identity = Identity::from_pem(&pem)
+                .map_err(|e| format!("Invalid TLS client identity: {e}"))?;
+            builder = builder.use_rustls_tls().identity(identity);
+        }
+
+        Ok(builder)
+    }
+
+    /// Builds the headers sent with every legacy request.
+    pub fn default_headers(&self) -> Result<HeaderMap, String> {
+        let mut headers = HeaderMap::new();
+
+        for header in &self.headers {
+            let (name, value) = parse_header(header)?;
+            headers.append(name, value);
+        }
+
+        if let Some(path) = &self.bearer_token_file {
+            let token = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read bearer token file {}: {e}", path.display()))?;
+            headers.insert(AUTHORIZATION, sensitive_value(&format!("Bearer {}", token.trim()))?);
+        } else if let Some(credentials) = &self.basic_auth {
+            let encoded = STANDARD.encode(credentials);
+            headers.insert(AUTHORIZATION, sensitive_value(&format!("Basic {encoded}"))?);
+        }
+
+        Ok(headers)
+    }
+}
+
+/// Parses a `Name: value` header.
+pub fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid header '{header}', expected Name: value"))?;
+    let name = HeaderName::try_from(name.trim())
+        .map_err(|e| format!("Invalid header name in '{header}': {e}"))?;
+    let value = HeaderValue::try_from(value.trim())
+        .map_err(|e| format!("Invalid header value in '{header}': {e}"))?;
+    Ok((name, value))
+}
+
+/// Header value that is kept out of debug output.
+fn sensitive_value(value: &str) -> Result<HeaderValue, String> {
+    let mut value =
+        HeaderValue::try_from(value).map_err(|_| "Invalid authorization value".to_string())?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_headers() {
+        let auth = LegacyAuthConfig {
+            headers: vec!["X-Api-Key: secret".to_string(), "X-Tenant:xlayer".to_string()],
+            basic_auth: Some("user:pass".to_string()),
+            ..Default::default()
+        };
+        let headers = auth.default_headers().unwrap();
+
+        assert_eq!(headers["x-api-key"], "secret");
+        assert_eq!(headers["x-tenant"], "xlayer");
+        assert_eq!(headers[AUTHORIZATION], "Basic dXNlcjpwYXNz");
+        assert!(headers[AUTHORIZATION].is_sensitive());
+    }
+
+    #[test]
+    fn test_bearer_token_file_takes_precedence() {
+        let path =
+            std::env::temp_dir().join(format!("xlayer-legacy-bearer-test-{}", std::process::id()));
+        std::fs::write(&path, "token123\n").unwrap();
+
+        let auth = LegacyAuthConfig {
+            bearer_token_file: Some(path.clone()),
+            basic_auth: Some("user:pass".to_string()),
+            ..Default::default()
+        };
+        let headers = auth.default_headers().unwrap();
+        assert_eq!(headers[AUTHORIZATION], "Bearer token123");
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_parse_header_rejects_invalid() {
+        assert!(parse_header("no-colon").is_err());
+        assert!(parse_header("bad name: value").is_err());
+        assert!(parse_header("X-Ok: value").is_ok());
+    }
+}
@@ -0,0 +1,335 @@
+//! Response cache for results served by the legacy endpoint.
+//!
+//! Everything routed to legacy lives below the cutoff block and is therefore
+//! immutable, so successful non-empty results can be cached indefinitely.
+//! Entries are keyed on the method name plus the normalized params.
+//!
+//! The memory tier is a size-aware LRU bounded by total bytes. An optional
+//! disk tier stores one file per entry so the cache survives restarts;
+//! entries loaded from disk are promoted back into memory. The disk tier is
+//! bounded by total bytes too: an index of the entry files, rebuilt from the
+//! directory on startup, evicts the least recently used files.
+use std::{path::PathBuf, sync::Arc};
+
+use alloy_primitives::{hex, keccak256};
+use moka::{notification::RemovalCause, policy::EvictionPolicy, sync::Cache};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use tracing::debug;
+
+use crate::metrics::LegacyCacheMetrics;
+
+/// Entry as persisted by the disk tier. The key is stored alongside the
+/// result so a hash collision can never serve the wrong response.
+#[derive(Serialize, Deserialize)]
+struct DiskEntry {
+    key: String,
+    result: Box<RawValue>,
+}
+
+/// Disk tier of the cache, one file per entry.
+struct DiskTier {
+    dir: PathBuf,
+    /// Size of every entry file by file name. Evicting an entry removes its
+    /// file, so the directory never holds more than the index capacity.
+    index: Cache<String, u64>,
+}
+
+impl DiskTier {
+    fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        if let Err(err) = std::fs::create_dir_all(&dir) {
+            tracing::warn!(
+                target:"xlayer_legacy_rpc",
+                "Failed to create legacy cache dir {}, err = {err:?}",
+                dir.display()
+            );
+        }
+
+        let index = Cache::<String, u64>::builder()
+            .max_capacity(max_bytes)
+            .weigher(|_, size| (*size).try_into().unwrap_or(u32::MAX))
+            .eviction_policy(EvictionPolicy::lru())
+            .eviction_listener({
+                let dir = dir.clone();
+                move |file_name: Arc<String>, _, cause: RemovalCause| {
+                    if cause.was_evicted() {
+                        let _ = std::fs::remove_file(dir.join(file_name.as_str()));
+                    }
+                }
+            })
+            .build();
+
+        // Entries persisted by a previous run count against the budget too
+        if let Ok(entries) = std::fs::read_dir(&dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                if path.extension().is_some_and(|ext| ext == "tmp") {
+                    let _ = std::fs::remove_file(&path);
+                } else if let Ok(metadata) = entry.metadata() {
+                    index.insert(file_name.to_string(), metadata.len());
+                }
+            }
+        }
+
+        Self { dir, index }
+    }
+}
+
+/// Two-tier cache of legacy results.
+pub struct LegacyResponseCache {
+    memory: Cache<String, Box<RawValue>>,
+    disk: Option<DiskTier>,
+    metrics: LegacyCacheMetrics,
+}
+
+impl LegacyResponseCache {
+    /// Creates a cache holding at most `max_bytes` in memory, backed by
+    /// `disk_dir` if given, holding at most `disk_max_bytes` on disk.
+    pub fn new(max_bytes: u64, disk_dir: Option<PathBuf>, disk_max_bytes: u64) -> Self {
+        let memory = Cache::<String, Box<RawValue>>::builder()
+            .max_capacity(max_bytes)
+            .weigher(|key, value| (key.len() + value.get().len()).try_into().unwrap_or(u32::MAX))
+            .build();
+
+        let disk =
+            disk_dir.filter(|_| disk_max_bytes > 0).map(|dir| DiskTier::new(dir, disk_max_bytes));
+
+        Self { memory, disk, metrics: LegacyCacheMetrics::default() }
+    }
+
+    /// Looks up a cached result, falling back to the disk tier.
+    pub async fn get(&self, key: &str) -> Option<Box<RawValue>> {
+        if let Some(result) = self.memory.get(key) {
+            self.metrics.memory_hits.increment(1);
+            return Some(result);
+        }
+
+        if let Some(result) = self.read_disk(key).await {
+            self.metrics.disk_hits.increment(1);
+            self.memory.insert(key.to_string(), result.clone());
+            self.update_size();
+            return Some(result);
+        }
+
+        self.metrics.misses.increment(1);
+        None
+    }
+
+    /// Caches a result if it carries data. Null and empty results are not
+    /// cached since lookups by hash may legitimately miss on legacy.
+    pub fn insert(&self, key: String, result: &RawValue) {
+        if !is_cacheable_result(result) {
+            return;
+        }
+        let raw = result.to_owned();
+
+        if let Some(disk) = &self.disk {
+            let file_name = disk_file_name(&key);
+            let path = disk.dir.join(&file_name);
+            let index = disk.index.clone();
+            let metrics = self.metrics.clone();
+            let entry = DiskEntry { key: key.clone(), result: raw.clone() };
+            if let Ok(bytes) = serde_json::to_vec(&entry) {
+                tokio::spawn(async move {
+                    let size = bytes.len() as u64;
+                    // Write then rename so readers never observe a partial entry
+                    let tmp_path = path.with_extension("tmp");
+                    let res = match tokio::fs::write(&tmp_path, bytes).await {
+                        Ok(()) => tokio::fs::rename(&tmp_path, &path).await,
+                        Err(err) => Err(err),
+                    };
+                    match res {
+                        Ok(()) => {
+                            // Indexed once written, so an eviction never races the write
+                            index.insert(file_name, size);
+                            metrics.disk_size_bytes.set(index.weighted_size() as f64);
+                        }
+                        Err(err) => {
+                            debug!(target:"xlayer_legacy_rpc", "Failed to write legacy cache entry, err = {err:?}");
+                        }
+                    }
+                });
+            }
+        }
+
+        self.memory.insert(key, raw);
+        self.metrics.inserts.increment(1);
+        self.update_size();
+    }
+
+    async fn read_disk(&self, key: &str) -> Option<Box<RawValue>> {
+        let disk = self.disk.as_ref()?;
+        let file_name = disk_file_name(key);
+        // Only indexed files are read, which also marks them recently used
+        disk.index.get(&file_name)?;
+        let bytes = tokio::fs::read(disk.dir.join(file_name)).await.ok()?;
+        let entry: DiskEntry = serde_json::from_slice(&bytes).ok()?;
+        (entry.key == key).then_some(entry.result)
+    }
+
+    fn update_size(&self) {
+        self.metrics.memory_size_bytes.set(self.memory.weighted_size() as f64);
+    }
+}
+
+/// Builds the cache key for a request from its method and params.
+///
+/// Params are normalized so that equivalent requests share an entry: object
+/// keys are sorted and hex strings are lowercased.
+pub fn cache_key(method: &str, params: Option<&str>) -> Option<String> {
+    let params = match params {
+        Some(params) => normalize(serde_json::from_str(params).ok()?),
+        None => serde_json::Value::Null,
+    };
+    Some(format!("{method}:{}", serde_json::to_string(&params).ok()?))
+}
+
+fn normalize(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(s) if s.starts_with("0x") => {
+            serde_json::Value::String(s.to_ascii_lowercase())
+        }
+        serde_json::Value::Array(arr) => {
+            serde_json::Value::Array(arr.into_iter().map(normalize).collect())
+        }
+        serde_json::Value::Object(obj) => {
+            let sorted: std::collections::BTreeMap<_, _> =
+                obj.into_iter().map(|(k, v)| (k, normalize(v))).collect();
+            serde_json::Value::Object(sorted.into_iter().collect())
+        }
+        other => other,
+    }
+}
+
+/// Checks the raw result without parsing it: `null`, `{}` and `[]`, with
+/// any inner whitespace, are empty.
+#[inline]
+fn is_cacheable_result(result: &RawValue) -> bool {
+    let json = result.get().trim();
+    match json.as_bytes().first() {
+        Some(b'{' | b'[') => !json[1..json.len() - 1].trim().is_empty(),
+        _ => json != "null",
+    }
+}
+
+#[inline]
+fn disk_file_name(key: &str) -> String {
+    format!("{}.json", hex::encode(keccak256(key.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(json: &str) -> Box<RawValue> {
+        RawValue::from_string(json.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_cache_key_normalizes_params() {
+        let a = cache_key(
+            "eth_getLogs",
+            Some(r#"[{"toBlock":"0x64","fromBlock":"0x1","address":"0xAbCd"}]"#),
+        );
+        let b = cache_key(
+            "eth_getLogs",
+            Some(r#"[{"address":"0xabcd","fromBlock":"0x1","toBlock":"0x64"}]"#),
+        );
+        assert!(a.is_some());
+        assert_eq!(a, b);
+
+        let other = cache_key("eth_getBlockByNumber", Some(r#"["0x1",false]"#));
+        let other_full = cache_key("eth_getBlockByNumber", Some(r#"["0x1",true]"#));
+        assert_ne!(other, other_full);
+
+        assert!(cache_key("eth_getLogs", Some("not json")).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cache_skips_empty_results() {
+        let cache = LegacyResponseCache::new(1024 * 1024, None, 0);
+        let key = cache_key("eth_getTransactionReceipt", Some(r#"["0x01"]"#)).unwrap();
+
+        cache.insert(key.clone(), &raw("null"));
+        cache.insert(key.clone(), &raw("[ ]"));
+        cache.insert(key.clone(), &raw("{}"));
+        assert!(cache.get(&key).await.is_none());
+
+        cache.insert(key.clone(), &raw(r#"{"status":"0x1"}"#));
+        let cached = cache.get(&key).await.unwrap();
+        assert_eq!(cached.get(), r#"{"status":"0x1"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_cache_disk_tier_survives_restart() {
+        let dir =
+            std::env::temp_dir().join(format!("xlayer-legacy-cache-test-{}", std::process::id()));
+        let key = cache_key("eth_getBlockByNumber", Some(r#"["0x1",false]"#)).unwrap();
+
+        let cache = LegacyResponseCache::new(1024 * 1024, Some(dir.clone()), 1024 * 1024);
+        cache.insert(key.clone(), &raw(r#"{"number":"0x1"}"#));
+
+        // Wait for the background write to land.
+        let path = dir.join(disk_file_name(&key));
+        for _ in 0..100 {
+            if path.exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let restarted = LegacyResponseCache::new(1024 * 1024, Some(dir.clone()), 1024 * 1024);
+        let cached = restarted.get(&key).await.unwrap();
+        assert_eq!(cached.get(), r#"{"number":"0x1"}"#);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_cache_disk_tier_is_bounded() {
+        let dir = std::env::temp_dir()
+            .join(format!("xlayer-legacy-cache-bound-test-{}", std::process::id()));
+        let result = format!(r#"{{"data":"{}"}}"#, "a".repeat(400));
+        // Room for two entries on disk
+        let cache = LegacyResponseCache::new(1024 * 1024, Some(dir.clone()), 1000);
+
+        let keys: Vec<_> = (0..5)
+            .map(|i| {
+                cache_key("eth_getBlockByNumber", Some(&format!(r#"["0x{i}",false]"#))).unwrap()
+            })
+            .collect();
+        for key in &keys {
+            cache.insert(key.clone(), &raw(&result));
+            // Let the background write land before the next one
+            let path = dir.join(disk_file_name(key));
+            for _ in 0..100 {
+                if path.exists()
+                    && cache.disk.as_ref().unwrap().index.contains_key(&disk_file_name(key))
+                {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        }
+        let index = &cache.disk.as_ref().unwrap().index;
+        index.run_pending_tasks();
+        assert!(index.weighted_size() <= 1000);
+
+        let files = std::fs::read_dir(&dir).unwrap().count();
+        assert_eq!(files as u64, index.entry_count());
+        assert!(files < keys.len());
+
+        // A restart picks up the bound from what is left on disk
+        drop(cache);
+        let restarted = LegacyResponseCache::new(1024 * 1024, Some(dir.clone()), 1000);
+        let index = &restarted.disk.as_ref().unwrap().index;
+        index.run_pending_tasks();
+        assert_eq!(index.entry_count() as usize, files);
+        assert!(restarted.get(keys.last().unwrap()).await.is_some());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,388 @@
+//! Handles logic for deciding how to route for `eth_feeHistory`.
+//!
+//! The requested window is `[newest - block_count + 1, newest]`. A window
+//! entirely below the cutoff goes to legacy and one entirely at or above it
+//! stays local. A window crossing the cutoff is split into a legacy part
+//! ending at `cutoff - 1` and a local part starting at `cutoff`, and the two
+//! histories are stitched together. For `latest` and `pending` the local
+//! head is only resolved while the window could still reach below the
+//! cutoff.
+//!
+//! Histories are stitched as follows:
+//!
+//! - `oldestBlock` comes from the legacy part.
+//! - `baseFeePerGas` and `baseFeePerBlobGas` hold one extra entry for the
+//!   block after the window. The legacy trailing entry is the base fee of the
+//!   cutoff block, which the local part already reports, so it is dropped.
+//! - `gasUsedRatio`, `blobGasUsedRatio` and `reward` are concatenated.
+use jsonrpsee::{
+    server::middleware::rpc::RpcServiceT,
+    types::{error::INVALID_PARAMS_CODE, ErrorObject},
+    MethodResponse,
+};
+use jsonrpsee_types::{Id, Request};
+use serde_json::value::RawValue;
+use tracing::debug;
+
+use crate::{
+    metrics::{record_route, RouteDecision},
+    LegacyRpcRouterService,
+};
+
+/// Newest block of an `eth_feeHistory` request.
+#[derive(Debug, Eq, PartialEq)]
+enum NewestBlock {
+    Number(u64),
+    /// `latest` or `pending`, resolved against the local head
+    Latest,
+}
+
+/// Parse a block count, which may be a hex quantity or a plain number.
+#[inline]
+fn parse_block_count(value: &serde_json::Value) -> Option<u64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_u64(),
+        serde_json::Value::String(s) => {
+            u64::from_str_radix(s.strip_prefix("0x").unwrap_or(s), 16).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Parse `eth_feeHistory` params into the block count and newest block.
+#[inline]
+fn parse_fee_history_params(params: &str) -> Option<(u64, NewestBlock)> {
+    let parsed: serde_json::Value = serde_json::from_str(params).ok()?;
+    let arr = parsed.as_array()?;
+
+    let block_count = parse_block_count(arr.first()?)?;
+    if block_count == 0 {
+        return None;
+    }
+
+    let newest = match arr.get(1)?.as_str()? {
+        "latest" | "pending" => NewestBlock::Latest,
+        "earliest" => NewestBlock::Number(0),
+        hex if hex.starts_with("0x") => {
+            NewestBlock::Number(u64::from_str_radix(&hex[2..], 16).ok()?)
+        }
+        // safe/finalized are left to the local node
+        _ => return None,
+    };
+
+    Some((block_count, newest))
+}
+
+/// Returns true if the whole window is below the cutoff. Windows ending at a
+/// tag are never pure legacy since the tag resolves to a local block.
+pub(crate) fn is_pure_legacy_window(params: &str, cutoff_block: u64) -> bool {
+    matches!(
+        parse_fee_history_params(params),
+        Some((_, NewestBlock::Number(newest))) if newest < cutoff_block
+    )
+}
+
+/// Build an `eth_feeHistory` request for `block_count` blocks ending at
+/// `newest_block`, keeping the reward percentiles.
+fn modify_fee_history_params<'a>(
+    original_req: &Request<'a>,
+    block_count: u64,
+    newest_block: u64,
+) -> Option<Request<'a>> {
+    let params_ref = original_req.params();
+    let mut parsed: serde_json::Value = serde_json::from_str(params_ref.as_str()?).ok()?;
+    let arr = parsed.as_array_mut()?;
+
+    *arr.get_mut(0)? = serde_json::Value::String(format!("0x{block_count:x}"));
+    *arr.get_mut(1)? = serde_json::Value::String(format!("0x{newest_block:x}"));
+
+    let params_raw = RawValue::from_string(serde_json::to_string(&parsed).ok()?).ok()?;
+    Some(Request::owned(
+        original_req.method_name().to_string(),
+        Some(params_raw),
+        original_req.id(),
+    ))
+}
+
+/// Concatenate a per-block array of both parts. A side missing the field is
+/// padded with `zero` so the entries stay aligned with `gasUsedRatio`.
+fn merge_fee_history_field(
+    legacy: &serde_json::Map<String, serde_json::Value>,
+    local: &serde_json::Map<String, serde_json::Value>,
+    field: &str,
+    zero: serde_json::Value,
+    drop_legacy_last: bool,
+) -> Option<serde_json::Value> {
+    if !legacy.contains_key(field) && !local.contains_key(field) {
+        return None;
+    }
+
+    let blocks = |part: &serde_json::Map<String, serde_json::Value>| {
+        part.get("gasUsedRatio").and_then(|v| v.as_array()).map_or(0, |v| v.len())
+    };
+    let entries = |part: &serde_json::Map<String, serde_json::Value>, extra: usize| {
+        let values = part.get(field).and_then(|v| v.as_array());
+        values.cloned().unwrap_or_else(|| vec![zero.clone(); blocks(part) + extra])
+    };
+
+    let extra = usize::from(drop_legacy_last);
+    let mut merged = entries(legacy, extra);
+    if drop_legacy_last {
+        merged.pop();
+    }
+    merged.extend(entries(local, extra));
+
+    Some(serde_json::Value::Array(merged))
+}
+
+/// Stitch the legacy and local fee histories into one response.
+fn merge_fee_history_responses(
+    legacy_response: MethodResponse,
+    local_response: MethodResponse,
+    request_id: Id,
+    max_response_size: usize,
+) -> MethodResponse {
+    if legacy_response.is_error() {
+        return legacy_response;
+    }
+    if local_response.is_error() {
+        return local_response;
+    }
+
+    let parse = |response: &MethodResponse| {
+        serde_json::from_str::<serde_json::Value>(response.as_json().get())
+            .ok()
+            .and_then(|v| v.get("result")?.as_object().cloned())
+    };
+    let Some(legacy) = parse(&legacy_response) else {
+        return local_response;
+    };
+    let Some(local) = parse(&local_response) else {
+        return legacy_response;
+    };
+
+    let mut merged = serde_json::Map::new();
+    if let Some(oldest_block) = legacy.get("oldestBlock") {
+        merged.insert("oldestBlock".to_string(), oldest_block.clone());
+    }
+
+    let fee_zero = serde_json::Value::String("0x0".to_string());
+    let ratio_zero = serde_json::json!(0.0);
+    let fields = [
+        ("baseFeePerGas", &fee_zero, true),
+        ("baseFeePerBlobGas", &fee_zero, true),
+        ("gasUsedRatio", &ratio_zero, false),
+        ("blobGasUsedRatio", &ratio_zero, false),
+    ];
+    for (field, zero, drop_legacy_last) in fields {
+        if let Some(value) =
+            merge_fee_history_field(&legacy, &local, field, zero.clone(), drop_legacy_last)
+        {
+            merged.insert(field.to_string(), value);
+        }
+    }
+
+    // Both parts were requested with the same percentiles
+    if let (
+        Some(serde_json::Value::Array(legacy_reward)),
+        Some(serde_json::Value::Array(local_reward)),
+    ) = (legacy.get("reward"), local.get("reward"))
+    {
+        let reward = legacy_reward.iter().chain(local_reward.iter()).cloned().collect();
+        merged.insert("reward".to_string(), serde_json::Value::Array(reward));
+    }
+
+    let merged_result = serde_json::Value::Object(merged);
+    let payload = jsonrpsee_types::ResponsePayload::success(&merged_result).into();
+
+    MethodResponse::response(request_id, payload, max_response_size)
+}
+
+/// Handle eth_feeHistory routing logic.
+pub(crate) async fn handle_eth_fee_history<S>(
+    req: Request<'_>,
+    service: LegacyRpcRouterService<S>,
+) -> MethodResponse
+where
+    S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
+{
+    let params_ref = req.params();
+    let Some(params) = params_ref.as_str() else {
+        return MethodResponse::error(
+            req.id(),
+            ErrorObject::owned(INVALID_PARAMS_CODE, "Missing required params", None::<()>),
+        );
+    };
+
+    let cutoff_block = service.cutoff_block();
+    let Some((block_count, newest)) = parse_fee_history_params(params) else {
+        // If parsing fails, use normal routing
+        record_route("eth_feeHistory", RouteDecision::Local);
+        return service.inner.call(req).await;
+    };
+
+    let newest_block = match newest {
+        NewestBlock::Number(number) => number,
+        NewestBlock::Latest => {
+            // The local head never drops below the cutoff or a head seen
+            // before, so a window already starting at or above the cutoff
+            // from there stays local without resolving the head
+            let known_oldest = service.known_local_head().saturating_sub(block_count - 1);
+            if cutoff_block == 0 || known_oldest >= cutoff_block {
+                record_route("eth_feeHistory", RouteDecision::Local);
+                return service.inner.call(req).await;
+            }
+
+            match crate::get_logs::latest_local_block(&service.inner).await {
+                Some(number) => {
+                    service.observe_local_head(number);
+                    number
+                }
+                None => {
+                    record_route("eth_feeHistory", RouteDecision::Local);
+                    return service.inner.call(req).await;
+                }
+            }
+        }
+    };
+    let oldest_block = newest_block.saturating_sub(block_count - 1);
+
+    if newest_block < cutoff_block {
+        debug!(
+            target:"xlayer_legacy_rpc",
+            "eth_feeHistory pure legacy routing (oldest_block = {}, newest_block = {})",
+            oldest_block, newest_block
+        );
+        record_route("eth_feeHistory", RouteDecision::Legacy);
+        return service.forward_to_legacy(req).await;
+    } else if oldest_block >= cutoff_block {
+        debug!(
+            target:"xlayer_legacy_rpc",
+            "eth_feeHistory pure local routing (oldest_block = {}, newest_block = {})",
+            oldest_block, newest_block
+        );
+        record_route("eth_feeHistory", RouteDecision::Local);
+        return service.inner.call(req).await;
+    }
+
+    let legacy_req = modify_fee_history_params(&req, cutoff_block - oldest_block, cutoff_block - 1);
+    let local_req = modify_fee_history_params(&req, newest_block - cutoff_block + 1, newest_block);
+
+    if let (Some(legacy_req), Some(local_req)) = (legacy_req, local_req) {
+        debug!(
+            target:"xlayer_legacy_rpc",
+            "eth_feeHistory hybrid routing (oldest_block = {}, {}) and ({}, newest_block = {})",
+            oldest_block,
+            cutoff_block - 1,
+            cutoff_block,
+            newest_block
+        );
+
+        record_route("eth_feeHistory", RouteDecision::Hybrid);
+        let (legacy_response, local_response) =
+            tokio::join!(async { service.forward_to_legacy(legacy_req).await }, async {
+                service.inner.call(local_req).await
+            });
+
+        return merge_fee_history_responses(
+            legacy_response,
+            local_response,
+            req.id(),
+            service.max_response_size(),
+        );
+    }
+
+    debug!(target:"xlayer_legacy_rpc", "No legacy routing for method = eth_feeHistory");
+    record_route("eth_feeHistory", RouteDecision::Local);
+    service.inner.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fee_history_response(result: serde_json::Value) -> MethodResponse {
+        let payload = jsonrpsee_types::ResponsePayload::success(&result).into();
+        MethodResponse::response(Id::Number(1), payload, usize::MAX)
+    }
+
+    #[test]
+    fn test_parse_fee_history_params() {
+        let cases = [
+            (r#"["0x4","0x64",[25,75]]"#, Some((4, NewestBlock::Number(100)))),
+            (r#"[4,"latest"]"#, Some((4, NewestBlock::Latest))),
+            (r#"["0x1","earliest"]"#, Some((1, NewestBlock::Number(0)))),
+            (r#"["0x0","0x64"]"#, None),
+            (r#"["0x4","finalized"]"#, None),
+            (r#"["0x4"]"#, None),
+        ];
+
+        for (params, expected) in cases {
+            assert_eq!(parse_fee_history_params(params), expected);
+        }
+
+        assert!(is_pure_legacy_window(r#"["0x4","0x63"]"#, 100));
+        assert!(!is_pure_legacy_window(r#"["0x4","0x64"]"#, 100));
+        assert!(!is_pure_legacy_window(r#"["0x4","latest"]"#, 100));
+    }
+
+    #[test]
+    fn test_modify_fee_history_params_keeps_percentiles() {
+        let params_raw = RawValue::from_string(r#"["0x4","latest",[25,75]]"#.to_string()).unwrap();
+        let request = Request::owned("eth_feeHistory".to_string(), Some(params_raw), Id::Number(1));
+
+        let modified = modify_fee_history_params(&request, 2, 99).unwrap();
+        let modified_params = modified.params();
+        assert_eq!(modified_params.as_str().unwrap(), r#"["0x2","0x63",[25,75]]"#);
+    }
+
+    #[test]
+    fn test_merge_fee_history_responses() {
+        // Legacy covers blocks 98-99, local covers 100-101
+        let legacy = fee_history_response(serde_json::json!({
+            "oldestBlock": "0x62",
+            "baseFeePerGas": ["0x1", "0x2", "0x3"],
+            "gasUsedRatio": [0.1, 0.2],
+            "reward": [["0x10"], ["0x20"]],
+        }));
+        let local = fee_history_response(serde_json::json!({
+            "oldestBlock": "0x64",
+            "baseFeePerGas": ["0x3", "0x4", "0x5"],
+            "baseFeePerBlobGas": ["0x7", "0x8", "0x9"],
+            "gasUsedRatio": [0.3, 0.4],
+            "blobGasUsedRatio": [0.5, 0.6],
+            "reward": [["0x30"], ["0x40"]],
+        }));
+
+        let merged = merge_fee_history_responses(legacy, local, Id::Number(1), usize::MAX);
+        let parsed: serde_json::Value = serde_json::from_str(merged.as_json().get()).unwrap();
+        assert_eq!(
+            parsed["result"],
+            serde_json::json!({
+                "oldestBlock": "0x62",
+                "baseFeePerGas": ["0x1", "0x2", "0x3", "0x4", "0x5"],
+                "baseFeePerBlobGas": ["0x0", "0x0", "0x7", "0x8", "0x9"],
+                "gasUsedRatio": [0.1, 0.2, 0.3, 0.4],
+                "blobGasUsedRatio": [0.0, 0.0, 0.5, 0.6],
+                "reward": [["0x10"], ["0x20"], ["0x30"], ["0x40"]],
+            })
+        );
+    }
+
+    #[test]
+    fn test_merge_fee_history_responses_max_response_size() {
+        let response = |oldest_block: &str| {
+            fee_history_response(serde_json::json!({
+                "oldestBlock": oldest_block,
+                "baseFeePerGas": ["0x1", "0x2"],
+                "gasUsedRatio": [0.1],
+            }))
+        };
+
+        let merged =
+            merge_fee_history_responses(response("0x62"), response("0x63"), Id::Number(1), 64);
+        assert!(merged.is_error());
+        let parsed: serde_json::Value = serde_json::from_str(merged.as_json().get()).unwrap();
+        assert_eq!(parsed["error"]["code"], jsonrpsee_types::error::OVERSIZED_RESPONSE_CODE);
+    }
+}
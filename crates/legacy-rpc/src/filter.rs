@@ -0,0 +1,213 @@
+//! Handles logic for legacy-aware log filters.
+//!
+//! The local node only knows about blocks from the cutoff onwards, so a log
+//! filter starting below the cutoff would silently miss every pre-migration
+//! log. For such filters the router:
+//!
+//! 1. Installs the filter locally with `fromBlock` raised to the cutoff, so
+//!    `eth_getFilterChanges` keeps polling new logs from the local node.
+//! 2. Remembers the original filter under the id returned by the local node.
+//! 3. Serves `eth_getFilterLogs` for that id as an `eth_getLogs` over the
+//!    original filter, which is merged across legacy and local like any
+//!    other `eth_getLogs` (see [`crate::get_logs`]).
+//!
+//! Filters are dropped on `eth_uninstallFilter` or after sitting idle as long
+//! as the local node keeps stale filters around. Polling a filter with
+//! `eth_getFilterChanges` keeps it alive on both sides, so a registered
+//! filter never expires while the local one is still in use.
+use std::time::Duration;
+
+use jsonrpsee::{server::middleware::rpc::RpcServiceT, MethodResponse};
+use jsonrpsee_types::Request;
+use moka::sync::Cache;
+use serde_json::value::RawValue;
+use tracing::debug;
+
+use crate::{
+    metrics::{record_route, RouteDecision},
+    LegacyRpcRouterService,
+};
+
+/// Max number of legacy-aware filters kept at once
+const MAX_LEGACY_FILTERS: u64 = 10_000;
+
+/// Idle time after which a filter is dropped, matching the local node's
+/// stale filter eviction
+const LEGACY_FILTER_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Registry of filters whose range starts below the cutoff, keyed by the
+/// local filter id.
+pub struct LegacyFilterRegistry {
+    filters: Cache<String, String>,
+}
+
+impl LegacyFilterRegistry {
+    pub fn new() -> Self {
+        Self {
+            filters: Cache::builder()
+                .max_capacity(MAX_LEGACY_FILTERS)
+                .time_to_idle(LEGACY_FILTER_TTL)
+                .build(),
+        }
+    }
+
+    /// Records the original `eth_newFilter` params for a filter id.
+    pub fn insert(&self, filter_id: &str, params: String) {
+        self.filters.insert(filter_id.to_ascii_lowercase(), params);
+    }
+
+    /// Returns the original `eth_newFilter` params for a filter id, keeping
+    /// the filter alive.
+    pub fn get(&self, filter_id: &str) -> Option<String> {
+        self.filters.get(&filter_id.to_ascii_lowercase())
+    }
+
+    /// Forgets a filter id.
+    pub fn remove(&self, filter_id: &str) {
+        self.filters.invalidate(&filter_id.to_ascii_lowercase());
+    }
+}
+
+impl Default for LegacyFilterRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parse the filter id, the single param of the filter id based methods.
+#[inline]
+fn parse_filter_id(params: &str) -> Option<String> {
+    let parsed: serde_json::Value = serde_json::from_str(params).ok()?;
+    Some(parsed.as_array()?.first()?.as_str()?.to_string())
+}
+
+/// Handle eth_newFilter routing logic.
+pub(crate) async fn handle_eth_new_filter<S>(
+    req: Request<'_>,
+    service: LegacyRpcRouterService<S>,
+) -> MethodResponse
+where
+    S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
+{
+    let cutoff_block = service.cutoff_block();
+    let params_ref = req.params();
+    let Some(params) = params_ref.as_str() else {
+        return service.inner.call(req).await;
+    };
+    let Some((_, to_block)) = crate::get_logs::legacy_range(params, cutoff_block) else {
+        record_route("eth_newFilter", RouteDecision::Local);
+        return service.inner.call(req).await;
+    };
+    let params = params.to_string();
+
+    // The local part starts at the cutoff, unless the whole range is legacy
+    let local_req = if to_block >= cutoff_block {
+        crate::get_logs::modify_eth_get_logs_params(&req, Some(cutoff_block), None)
+    } else {
+        None
+    };
+    let res = service.inner.call(local_req.unwrap_or(req)).await;
+
+    if res.is_success()
+        && let Ok(json) = serde_json::from_str::<serde_json::Value>(res.as_json().get())
+        && let Some(filter_id) = json.get("result").and_then(|v| v.as_str())
+    {
+        debug!(target:"xlayer_legacy_rpc", "Registered legacy-aware filter, id = {filter_id}");
+        record_route("eth_newFilter", RouteDecision::Hybrid);
+        service.filters.insert(filter_id, params);
+    }
+
+    res
+}
+
+/// Handle eth_getFilterLogs routing logic.
+pub(crate) async fn handle_eth_get_filter_logs<S>(
+    req: Request<'_>,
+    service: LegacyRpcRouterService<S>,
+) -> MethodResponse
+where
+    S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
+{
+    let params_ref = req.params();
+    let filter_params = params_ref
+        .as_str()
+        .and_then(parse_filter_id)
+        .and_then(|filter_id| service.filters.get(&filter_id));
+    let Some(filter_params) = filter_params else {
+        record_route("eth_getFilterLogs", RouteDecision::Local);
+        return service.inner.call(req).await;
+    };
+    let Ok(params_raw) = RawValue::from_string(filter_params) else {
+        return service.inner.call(req).await;
+    };
+
+    debug!(target:"xlayer_legacy_rpc", "method = eth_getFilterLogs, serving legacy-aware filter as eth_getLogs");
+    let logs_req = Request::owned("eth_getLogs".to_string(), Some(params_raw), req.id());
+    crate::get_logs::handle_eth_get_logs(logs_req, service).await
+}
+
+/// Handle eth_getFilterChanges routing logic.
+///
+/// Changes are always served by the local node, this only keeps the
+/// legacy-aware filter alive for as long as the local filter is polled.
+pub(crate) async fn handle_eth_get_filter_changes<S>(
+    req: Request<'_>,
+    service: LegacyRpcRouterService<S>,
+) -> MethodResponse
+where
+    S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
+{
+    let params_ref = req.params();
+    if let Some(filter_id) = params_ref.as_str().and_then(parse_filter_id) {
+        service.filters.get(&filter_id);
+    }
+    record_route("eth_getFilterChanges", RouteDecision::Local);
+    service.inner.call(req).await
+}
+
+/// Handle eth_uninstallFilter routing logic.
+pub(crate) async fn handle_eth_uninstall_filter<S>(
+    req: Request<'_>,
+    service: LegacyRpcRouterService<S>,
+) -> MethodResponse
+where
+    S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
+{
+    let params_ref = req.params();
+    if let Some(filter_id) = params_ref.as_str().and_then(parse_filter_id) {
+        service.filters.remove(&filter_id);
+    }
+    service.inner.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_filter_registry() {
+        let registry = LegacyFilterRegistry::new();
+        registry.insert("0xAbC", r#"[{"fromBlock":"0x1"}]"#.to_string());
+
+        assert_eq!(registry.get("0xabc").as_deref(), Some(r#"[{"fromBlock":"0x1"}]"#));
+        registry.remove("0xABC");
+        assert!(registry.get("0xabc").is_none());
+    }
+
+    #[test]
+    fn test_legacy_filter_registry_expires_idle_filters() {
+        // Expiry is left to moka, reads reset the idle timer of an entry
+        let registry = LegacyFilterRegistry::new();
+        let policy = registry.filters.policy();
+        assert_eq!(policy.time_to_idle(), Some(LEGACY_FILTER_TTL));
+        assert_eq!(policy.time_to_live(), None);
+        assert_eq!(policy.max_capacity(), Some(MAX_LEGACY_FILTERS));
+    }
+
+    #[test]
+    fn test_parse_filter_id() {
+        assert_eq!(parse_filter_id(r#"["0x1f"]"#), Some("0x1f".to_string()));
+        assert_eq!(parse_filter_id(r#"[]"#), None);
+        assert_eq!(parse_filter_id(r#"[31]"#), None);
+    }
+}
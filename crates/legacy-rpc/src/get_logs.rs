@@ -17,15 +17,21 @@
 //!    Condition: from_block < cutoff_block && to_block >= cutoff_block
 //!    Timeline:  [====== Legacy ======][cutoff][====== Local ======]
 //!    Filter:    [from -------------- across -------------- to]
-//!    Results will be sorted (eg. block num, txn index, log index).
+//!    Each side is split into block chunks that run with bounded
+//!    concurrency. Results will be sorted (block num, txn index, log index)
+//!    and capped at a maximum result count.
 //!
 //! Special Cases
 //! from_block: earliest
 //!     These get converted to 0
 //! to_block: latest/pending/finalized/safe
-//!     These get converted to u64::MAX
+//!     These get converted to u64::MAX. The local part of a hybrid range
+//!     is only resolved to the local head for latest, other tags are kept
+//!     as is so the local node resolves them.
 use crate::{service::is_result_empty, LegacyRpcRouterService};
+use futures::{Stream, StreamExt};
 use jsonrpsee::{
+    server::middleware::rpc::RpcServiceT,
     types::{error::INVALID_PARAMS_CODE, ErrorObject},
     MethodResponse,
 };
@@ -33,7 +39,11 @@ use jsonrpsee_types::{Id, Request};
 use serde_json::value::RawValue;
 use tracing::debug;
 
-use crate::is_valid_32_bytes_string;
+use crate::{
+    is_valid_32_bytes_string,
+    metrics::{record_fallback, record_route, LegacyGetLogsMetrics, RouteDecision},
+    LIMIT_EXCEEDED_CODE,
+};
 
 /// Parse a block number string to u64
 /// Returns None for "latest", "pending", "safe", "finalized"
@@ -108,9 +118,44 @@ fn parse_eth_get_logs_params(params: &str) -> Option<GetLogsParams> {
     Some(GetLogsParams::Range(from_block, to_block))
 }
 
+/// Returns true if the filter has no `toBlock` or it is `latest`, i.e. the
+/// range ends at the head of the chain.
+#[inline]
+fn ends_at_latest(params: &str) -> bool {
+    let Ok(parsed) = serde_json::from_str::<serde_json::Value>(params) else {
+        return false;
+    };
+    match parsed.get(0).and_then(|filter| filter.get("toBlock")) {
+        None => true,
+        Some(to_block) => to_block.as_str() == Some("latest"),
+    }
+}
+
+/// Returns true if the `eth_getLogs` params describe a range that ends
+/// below the cutoff block.
+#[inline]
+pub(crate) fn is_pure_legacy_range(params: &str, cutoff_block: u64) -> bool {
+    matches!(
+        parse_eth_get_logs_params(params),
+        Some(GetLogsParams::Range(_, to_block)) if to_block < cutoff_block
+    )
+}
+
+/// Returns the (from_block, to_block) range of filter params that start
+/// below the cutoff block, i.e. that need at least some legacy logs.
+#[inline]
+pub(crate) fn legacy_range(params: &str, cutoff_block: u64) -> Option<(u64, u64)> {
+    match parse_eth_get_logs_params(params)? {
+        GetLogsParams::Range(from_block, to_block) if from_block < cutoff_block => {
+            Some((from_block, to_block))
+        }
+        _ => None,
+    }
+}
+
 /// Modify eth_getLogs request to use custom fromBlock and toBlock
 /// Returns a new Request with modified parameters
-fn modify_eth_get_logs_params<'a>(
+pub(crate) fn modify_eth_get_logs_params<'a>(
     original_req: &Request<'a>,
     from_block: Option<u64>,
     to_block: Option<u64>,
@@ -152,61 +197,217 @@ fn modify_eth_get_logs_params<'a>(
     ))
 }
 
-/// Merge two eth_getLogs responses
-fn merge_eth_get_logs_responses(
-    legacy_response: MethodResponse,
-    local_response: MethodResponse,
-    request_id: Id,
-) -> MethodResponse {
-    // Parse both responses
-    let legacy_json = legacy_response.as_json().get();
-    let local_json = local_response.as_json().get();
+/// Parse a hex quantity field of a log, defaulting to 0.
+#[inline]
+fn log_quantity(log: &serde_json::Value, field: &str) -> u64 {
+    log.get(field)
+        .and_then(|v| v.as_str())
+        .and_then(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok())
+        .unwrap_or(0)
+}
 
-    let legacy_parsed: serde_json::Value = match serde_json::from_str(legacy_json) {
-        Ok(v) => v,
-        Err(_) => return legacy_response, // Fallback to legacy on error
-    };
+/// Merge eth_getLogs chunk responses into a single response.
+///
+/// The first error response is returned as is. Logs are sorted by block
+/// number, then transaction index, then log index. If `max_results` is
+/// non-zero and exceeded, a limit exceeded error is returned instead, and
+/// a merged response above `max_response_size` bytes is rejected as well.
+///
+/// Responses are counted as the stream yields them, and the stream is
+/// dropped on the first error or once `max_results` is exceeded, so no
+/// further chunks are fetched.
+async fn merge_eth_get_logs_responses(
+    responses: impl Stream<Item = MethodResponse>,
+    request_id: Id<'_>,
+    max_results: usize,
+    max_response_size: usize,
+) -> MethodResponse {
+    let mut responses = std::pin::pin!(responses);
+    let mut merged_logs = Vec::new();
 
-    let local_parsed: serde_json::Value = match serde_json::from_str(local_json) {
-        Ok(v) => v,
-        Err(_) => return local_response, // Fallback to local on error
-    };
+    while let Some(response) = responses.next().await {
+        if response.is_error() {
+            return response;
+        }
 
-    // Extract results arrays
-    let legacy_result =
-        legacy_parsed.get("result").and_then(|r| r.as_array()).cloned().unwrap_or_default();
+        let parsed: serde_json::Value = match serde_json::from_str(response.as_json().get()) {
+            Ok(v) => v,
+            Err(_) => return response,
+        };
+        if let Some(serde_json::Value::Array(logs)) = parsed.get("result") {
+            merged_logs.extend(logs.iter().cloned());
+        }
 
-    let local_result =
-        local_parsed.get("result").and_then(|r| r.as_array()).cloned().unwrap_or_default();
+        if max_results > 0 && merged_logs.len() > max_results {
+            LegacyGetLogsMetrics::get().hybrid_limit_exceeded.increment(1);
+            return MethodResponse::error(
+                request_id,
+                ErrorObject::owned(
+                    LIMIT_EXCEEDED_CODE,
+                    format!(
+                        "query returned more than {max_results} results, retry with a smaller block range"
+                    ),
+                    None::<()>,
+                ),
+            );
+        }
+    }
 
-    // Merge the arrays
-    let mut merged_logs = legacy_result;
-    merged_logs.extend(local_result);
+    LegacyGetLogsMetrics::get().hybrid_merged_logs.record(merged_logs.len() as f64);
 
     // Sort by block number, then transaction index, then log index
-    merged_logs.sort_by(|a, b| {
-        // Compare block numbers
-        let block_a = a
-            .get("blockNumber")
-            .and_then(|v| v.as_str())
-            .and_then(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok())
-            .unwrap_or(0);
-
-        let block_b = b
-            .get("blockNumber")
-            .and_then(|v| v.as_str())
-            .and_then(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok())
-            .unwrap_or(0);
-
-        // Separated by cutoff point, won't need to compare equal block numbers
-        block_a.cmp(&block_b)
+    merged_logs.sort_by_key(|log| {
+        (
+            log_quantity(log, "blockNumber"),
+            log_quantity(log, "transactionIndex"),
+            log_quantity(log, "logIndex"),
+        )
     });
 
     // Create merged response
     let merged_result = serde_json::Value::Array(merged_logs);
     let payload = jsonrpsee_types::ResponsePayload::success(&merged_result).into();
 
-    MethodResponse::response(request_id, payload, usize::MAX)
+    MethodResponse::response(request_id, payload, max_response_size)
+}
+
+/// Split the inclusive range `[from_block, to_block]` into chunks of at most
+/// `chunk_size` blocks. A zero chunk size yields the whole range.
+fn split_block_range(
+    from_block: u64,
+    to_block: u64,
+    chunk_size: u64,
+) -> impl Iterator<Item = (u64, u64)> {
+    let span = if chunk_size == 0 { u64::MAX } else { chunk_size } - 1;
+    let chunk = move |start: u64| (start, start.saturating_add(span).min(to_block));
+    std::iter::successors((from_block <= to_block).then(|| chunk(from_block)), move |&(_, end)| {
+        (end < to_block).then(|| chunk(end + 1))
+    })
+}
+
+/// Number of chunks [`split_block_range`] splits the range into.
+fn chunk_count(from_block: u64, to_block: u64, chunk_size: u64) -> u64 {
+    if from_block > to_block {
+        0
+    } else if chunk_size == 0 {
+        1
+    } else {
+        ((to_block - from_block) / chunk_size).saturating_add(1)
+    }
+}
+
+/// Fetch the latest local block number.
+pub(crate) async fn latest_local_block<S>(inner: &S) -> Option<u64>
+where
+    S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
+{
+    let request = Request::owned("eth_blockNumber".to_string(), None, Id::Number(1));
+    let res = inner.call(request).await;
+    let response = serde_json::from_str::<serde_json::Value>(res.as_json().get()).ok()?;
+    let hex = response.get("result")?.as_str()?;
+    u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok()
+}
+
+/// Handle a range crossing the cutoff.
+///
+/// The legacy part `[from_block, cutoff - 1]` and the local part
+/// `[cutoff, to_block]` are split into chunks of `get_logs_chunk_size`
+/// blocks, which run with at most `get_logs_max_concurrency` requests in
+/// flight and are merged in block order. Chunks are built as they are sent,
+/// and ranges needing more than `get_logs_max_chunks` chunks are rejected.
+async fn handle_hybrid_eth_get_logs<S>(
+    req: &Request<'_>,
+    service: &LegacyRpcRouterService<S>,
+    from_block: u64,
+    to_block: u64,
+) -> Option<MethodResponse>
+where
+    S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
+{
+    let config = &service.config;
+    let cutoff_block = service.cutoff_block();
+    let chunk_size = config.get_logs_chunk_size;
+    // Chunks only differ by their range, so params that can't be rewritten
+    // are caught once here
+    modify_eth_get_logs_params(req, None, None)?;
+
+    // Resolve ranges up to latest so the local part can be chunked as well
+    let local_to_block = if to_block != u64::MAX {
+        Some(to_block)
+    } else if req.params().as_str().is_some_and(ends_at_latest) {
+        latest_local_block(&service.inner).await.inspect(|head| service.observe_local_head(*head))
+    } else {
+        None
+    };
+    let chunk_count = chunk_count(from_block, cutoff_block - 1, chunk_size).saturating_add(
+        local_to_block
+            .map_or(1, |local_to_block| chunk_count(cutoff_block, local_to_block, chunk_size)),
+    );
+    let max_chunks = config.get_logs_max_chunks;
+    if max_chunks != 0 && chunk_count > max_chunks as u64 {
+        return Some(MethodResponse::error(
+            req.id(),
+            ErrorObject::owned(
+                INVALID_PARAMS_CODE,
+                format!(
+                    "query spans too many blocks, at most {max_chunks} chunks of {chunk_size} blocks are allowed"
+                ),
+                None::<()>,
+            ),
+        ));
+    }
+
+    debug!(
+        target:"xlayer_legacy_rpc",
+        "eth_getLogs hybrid routing (from_block = {}, {}) and ({}, to_block = {}) in {} chunks",
+        from_block,
+        cutoff_block - 1,
+        cutoff_block,
+        to_block,
+        chunk_count
+    );
+    LegacyGetLogsMetrics::get().hybrid_chunks.record(chunk_count as f64);
+
+    let legacy_chunks = split_block_range(from_block, cutoff_block - 1, chunk_size)
+        .map(|(from, to)| (true, from, Some(to)));
+    let local_chunks = local_to_block
+        .map(|local_to_block| {
+            split_block_range(cutoff_block, local_to_block, chunk_size)
+                .map(|(from, to)| (from, Some(to)))
+        })
+        .into_iter()
+        .flatten()
+        // Keep the original toBlock tag, for safe, finalized and pending or if
+        // the local head is unknown
+        .chain(local_to_block.is_none().then_some((cutoff_block, None)))
+        .map(|(from, to)| (false, from, to));
+
+    let responses = futures::stream::iter(legacy_chunks.chain(local_chunks).map(
+        |(is_legacy, from, to)| async move {
+            let Some(chunk) = modify_eth_get_logs_params(req, Some(from), to) else {
+                return MethodResponse::error(
+                    req.id(),
+                    ErrorObject::owned(INVALID_PARAMS_CODE, "Invalid params", None::<()>),
+                );
+            };
+            if is_legacy {
+                service.forward_to_legacy(chunk).await
+            } else {
+                service.inner.call(chunk).await
+            }
+        },
+    ))
+    .buffered(config.get_logs_max_concurrency.max(1));
+
+    Some(
+        merge_eth_get_logs_responses(
+            responses,
+            req.id(),
+            config.get_logs_max_results,
+            service.max_response_size(),
+        )
+        .await,
+    )
 }
 
 /// Handle eth_getLogs routing logic.
@@ -215,18 +416,12 @@ fn merge_eth_get_logs_responses(
 /// based on the block range in the request.
 pub(crate) async fn handle_eth_get_logs<S>(
     req: Request<'_>,
-    client: reqwest::Client,
-    config: std::sync::Arc<crate::LegacyRpcRouterConfig>,
-    inner: S,
+    service: LegacyRpcRouterService<S>,
 ) -> MethodResponse
 where
-    S: jsonrpsee::server::middleware::rpc::RpcServiceT<MethodResponse = MethodResponse>
-        + Send
-        + Sync
-        + Clone
-        + 'static,
+    S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
 {
-    let service = LegacyRpcRouterService { inner: inner.clone(), config, client };
+    let inner = service.inner.clone();
     let params_ref = req.params();
     let Some(params) = params_ref.as_str() else {
         return MethodResponse::error(
@@ -235,7 +430,7 @@ where
         );
     };
 
-    let cutoff_block = service.config.cutoff_block;
+    let cutoff_block = service.cutoff_block();
 
     match parse_eth_get_logs_params(params) {
         Some(GetLogsParams::Range(from_block, to_block)) => {
@@ -246,6 +441,7 @@ where
                     from_block, to_block
                 );
                 // Pure legacy
+                record_route("eth_getLogs", RouteDecision::Legacy);
                 return service.forward_to_legacy(req).await;
             } else if from_block >= cutoff_block {
                 debug!(
@@ -254,39 +450,19 @@ where
                     from_block, to_block
                 );
                 // Pure local
+                record_route("eth_getLogs", RouteDecision::Local);
                 return inner.call(req).await;
             } else {
-                // Hybrid: split into two requests
-
-                // 1. Legacy request: fromBlock to cutoff-1
-                let legacy_req =
-                    modify_eth_get_logs_params(&req, Some(from_block), Some(cutoff_block - 1));
-
-                // 2. Local request: cutoff to toBlock
-                let local_req =
-                    modify_eth_get_logs_params(&req, Some(cutoff_block), Some(to_block));
-
-                if let (Some(legacy_req), Some(local_req)) = (legacy_req, local_req) {
-                    debug!(
-                        target:"xlayer_legacy_rpc",
-                        "eth_getLogs hybrid routing (from_block = {}, {}) and ({}, to_block = {})",
-                        from_block,
-                        cutoff_block - 1,
-                        cutoff_block,
-                        to_block
-                    );
-
-                    // Call both and merge results
-                    let (legacy_response, local_response) = tokio::join!(
-                        async { service.forward_to_legacy(legacy_req).await },
-                        async { inner.call(local_req).await }
-                    );
-
-                    // Merge the results
-                    return merge_eth_get_logs_responses(legacy_response, local_response, req.id());
+                // Hybrid: split into legacy and local chunks
+                if let Some(response) =
+                    handle_hybrid_eth_get_logs(&req, &service, from_block, to_block).await
+                {
+                    record_route("eth_getLogs", RouteDecision::Hybrid);
+                    return response;
                 }
 
                 debug!(target:"xlayer_legacy_rpc", "No legacy routing for method = eth_getLogs");
+                record_route("eth_getLogs", RouteDecision::Local);
 
                 // Fallback to normal if modification failed
                 return inner.call(req).await;
@@ -297,14 +473,18 @@ where
             let res = inner.call(req.clone()).await;
             if res.is_success() && !is_result_empty(&res) {
                 debug!(target:"xlayer_legacy_rpc", "method = eth_getLogs, success response = {res:?}");
+                record_route("eth_getLogs", RouteDecision::Local);
                 res
             } else {
                 debug!(target:"xlayer_legacy_rpc", "method = eth_getLogs, forward to legacy (empty or error)");
+                record_fallback("eth_getLogs");
+                record_route("eth_getLogs", RouteDecision::Legacy);
                 service.forward_to_legacy(req).await
             }
         }
         _ => {
             // If parsing fails, use normal routing
+            record_route("eth_getLogs", RouteDecision::Local);
             inner.call(req).await
         }
     }
@@ -313,6 +493,7 @@ where
 #[cfg(test)]
 mod tests {
     use crate::get_logs::GetLogsParams;
+    use futures::StreamExt;
     use jsonrpsee::MethodResponse;
     use jsonrpsee_types::{Id, Request};
     use serde_json::value::RawValue;
@@ -352,6 +533,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_ends_at_latest() {
+        assert!(super::ends_at_latest(r#"[{"fromBlock":"0x1"}]"#));
+        assert!(super::ends_at_latest(r#"[{"fromBlock":"0x1","toBlock":"latest"}]"#));
+        for tag in ["safe", "finalized", "pending", "0x64"] {
+            let params = format!(r#"[{{"fromBlock":"0x1","toBlock":"{tag}"}}]"#);
+            assert!(!super::ends_at_latest(&params));
+        }
+    }
+
     #[test]
     fn test_modify_eth_get_logs_params_both_blocks() {
         // Original request
@@ -375,8 +566,8 @@ mod tests {
         assert_eq!(filter.get("toBlock").and_then(|v| v.as_str()), Some("0xc8"));
     }
 
-    #[test]
-    fn test_merge_eth_get_logs_responses_both_have_logs() {
+    #[tokio::test]
+    async fn test_merge_eth_get_logs_responses_both_have_logs() {
         // Create legacy response with 2 logs
         let legacy_json = r#"{
               "jsonrpc": "2.0",
@@ -438,8 +629,13 @@ mod tests {
         let local_response = MethodResponse::response(Id::Number(1), local_payload, usize::MAX);
 
         // Merge
-        let merged =
-            super::merge_eth_get_logs_responses(legacy_response, local_response, Id::Number(1));
+        let merged = super::merge_eth_get_logs_responses(
+            futures::stream::iter(vec![legacy_response, local_response]),
+            Id::Number(1),
+            0,
+            usize::MAX,
+        )
+        .await;
 
         // Parse merged response
         let merged_json = merged.as_json().get();
@@ -455,4 +651,99 @@ mod tests {
         assert_eq!(result[2].get("blockNumber").unwrap().as_str(), Some("0x65"));
         assert_eq!(result[3].get("blockNumber").unwrap().as_str(), Some("0x66"));
     }
+
+    fn logs_response(logs: serde_json::Value) -> MethodResponse {
+        let payload = jsonrpsee_types::ResponsePayload::success(&logs).into();
+        MethodResponse::response(Id::Number(1), payload, usize::MAX)
+    }
+
+    #[test]
+    fn test_split_block_range() {
+        let split = |from, to, chunk_size| {
+            super::split_block_range(from, to, chunk_size).collect::<Vec<_>>()
+        };
+        assert_eq!(split(0, 9, 0), vec![(0, 9)]);
+        assert_eq!(split(0, 9, 5), vec![(0, 4), (5, 9)]);
+        assert_eq!(split(0, 10, 5), vec![(0, 4), (5, 9), (10, 10)]);
+        assert_eq!(split(7, 7, 100), vec![(7, 7)]);
+        assert!(split(8, 7, 100).is_empty());
+        assert_eq!(split(u64::MAX - 1, u64::MAX, 10), vec![(u64::MAX - 1, u64::MAX)]);
+        assert_eq!(split(0, u64::MAX, 0), vec![(0, u64::MAX)]);
+
+        for (from, to, chunk_size) in [(0, 9, 0), (0, 9, 5), (0, 10, 5), (7, 7, 100), (8, 7, 100)] {
+            assert_eq!(
+                super::chunk_count(from, to, chunk_size),
+                split(from, to, chunk_size).len() as u64
+            );
+        }
+        // Counted without generating the chunks
+        assert_eq!(super::chunk_count(0, u64::MAX - 1, 1), u64::MAX);
+    }
+
+    #[tokio::test]
+    async fn test_merge_eth_get_logs_responses_sorts_within_block() {
+        let chunk_a = logs_response(serde_json::json!([
+            {"blockNumber": "0x65", "transactionIndex": "0x1", "logIndex": "0x3"},
+            {"blockNumber": "0x64", "transactionIndex": "0x0", "logIndex": "0x1"}
+        ]));
+        let chunk_b = logs_response(serde_json::json!([
+            {"blockNumber": "0x65", "transactionIndex": "0x0", "logIndex": "0x0"},
+            {"blockNumber": "0x64", "transactionIndex": "0x0", "logIndex": "0x0"}
+        ]));
+
+        let merged = super::merge_eth_get_logs_responses(
+            futures::stream::iter(vec![chunk_a, chunk_b]),
+            Id::Number(1),
+            0,
+            usize::MAX,
+        )
+        .await;
+        let parsed: serde_json::Value = serde_json::from_str(merged.as_json().get()).unwrap();
+        let keys: Vec<_> = parsed["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|log| (log["blockNumber"].as_str().unwrap(), log["logIndex"].as_str().unwrap()))
+            .collect();
+
+        assert_eq!(keys, vec![("0x64", "0x0"), ("0x64", "0x1"), ("0x65", "0x0"), ("0x65", "0x3")]);
+    }
+
+    #[tokio::test]
+    async fn test_merge_eth_get_logs_responses_max_results() {
+        let chunk_a = logs_response(serde_json::json!([{"blockNumber": "0x1"}]));
+        let chunk_b =
+            logs_response(serde_json::json!([{"blockNumber": "0x2"}, {"blockNumber": "0x3"}]));
+        let fetched = std::sync::atomic::AtomicUsize::new(0);
+        let chunk_c = logs_response(serde_json::json!([{"blockNumber": "0x4"}]));
+
+        // The chunk after the cap is exceeded is never fetched
+        let responses = futures::stream::iter(vec![chunk_a, chunk_b, chunk_c]).inspect(|_| {
+            fetched.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        });
+        let merged =
+            super::merge_eth_get_logs_responses(responses, Id::Number(1), 2, usize::MAX).await;
+        assert_eq!(fetched.load(std::sync::atomic::Ordering::Relaxed), 2);
+        assert!(merged.is_error());
+        let parsed: serde_json::Value = serde_json::from_str(merged.as_json().get()).unwrap();
+        assert_eq!(parsed["error"]["code"], super::LIMIT_EXCEEDED_CODE);
+        assert!(parsed["error"]["message"].as_str().unwrap().contains("more than 2 results"));
+    }
+
+    #[tokio::test]
+    async fn test_merge_eth_get_logs_responses_max_response_size() {
+        let chunk_a = logs_response(serde_json::json!([{"blockNumber": "0x1"}]));
+        let chunk_b = logs_response(serde_json::json!([{"blockNumber": "0x2"}]));
+
+        let merged = super::merge_eth_get_logs_responses(
+            futures::stream::iter(vec![chunk_a, chunk_b]),
+            Id::Number(1),
+            0,
+            64,
+        )
+        .await;
+        assert!(merged.is_error());
+        let parsed: serde_json::Value = serde_json::from_str(merged.as_json().get()).unwrap();
+        assert_eq!(parsed["error"]["code"], jsonrpsee_types::error::OVERSIZED_RESPONSE_CODE);
+    }
 }
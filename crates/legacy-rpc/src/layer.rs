@@ -1,30 +1,123 @@
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 
+use jsonrpsee::types::ErrorObjectOwned;
 use reqwest::Client;
+use tokio::sync::Semaphore;
 use tower::Layer;
 use tracing::info;
 
-use crate::{LegacyRpcRouterConfig, LegacyRpcRouterService};
+use crate::{
+    cache::LegacyResponseCache, filter::LegacyFilterRegistry, policy::TokenBucket,
+    pool::LegacyEndpointPool, transport::TransportConfig, LegacyRpcRouterConfig,
+    LegacyRpcRouterService,
+};
 
 /// Layer that creates the routing middleware
 #[derive(Clone)]
 pub struct LegacyRpcRouterLayer {
     config: Arc<LegacyRpcRouterConfig>,
-    client: Client,
+    cutoff_block: Arc<AtomicU64>,
+    local_head: Arc<AtomicU64>,
+    pool: Arc<LegacyEndpointPool>,
+    cache: Option<Arc<LegacyResponseCache>>,
+    filters: Arc<LegacyFilterRegistry>,
+    limiter: Option<Arc<TokenBucket>>,
+    shadow_permits: Arc<Semaphore>,
 }
 
 impl LegacyRpcRouterLayer {
     pub fn new(config: LegacyRpcRouterConfig) -> Self {
-        let client = Client::builder()
-            .timeout(config.timeout)
-            .build()
+        let client = config
+            .auth
+            .apply(Client::builder().timeout(config.timeout))
+            .and_then(|builder| builder.build().map_err(|e| e.to_string()))
             .expect("Failed to create HTTP client");
+        let headers = config.auth.default_headers().expect("Invalid legacy RPC auth config");
+        let transport = TransportConfig {
+            client,
+            headers,
+            timeout: config.timeout,
+            max_response_size: config.max_response_size,
+        };
+        let pool = Arc::new(LegacyEndpointPool::with_transport_config(
+            &config.legacy_endpoints,
+            config.selection,
+            config.circuit_breaker,
+            &transport,
+        ));
 
         if config.enabled {
-            info!(target:"xlayer_legacy_rpc", "xlayer legacy rpc enabled");
+            info!(
+                target:"xlayer_legacy_rpc",
+                "xlayer legacy rpc enabled, endpoints = {:?}, selection = {}",
+                config.legacy_endpoints,
+                config.selection
+            );
+
+            if !config.health_check_interval.is_zero() {
+                pool.clone().spawn_health_check(config.health_check_interval);
+            }
         }
 
-        Self { config: Arc::new(config), client }
+        let cache = (config.enabled && config.cache_max_bytes > 0).then(|| {
+            info!(
+                target:"xlayer_legacy_rpc",
+                "xlayer legacy rpc cache enabled, max_bytes = {}, dir = {:?}, disk_max_bytes = {}",
+                config.cache_max_bytes,
+                config.cache_dir,
+                config.cache_disk_max_bytes
+            );
+            Arc::new(LegacyResponseCache::new(
+                config.cache_max_bytes,
+                config.cache_dir.clone(),
+                config.cache_disk_max_bytes,
+            ))
+        });
+
+        let limiter = (config.rate_limit > 0)
+            .then(|| Arc::new(TokenBucket::new(config.rate_limit, config.rate_limit_burst)));
+
+        let shadow_permits = Arc::new(Semaphore::new(config.shadow.max_in_flight));
+
+        Self {
+            cutoff_block: Arc::new(AtomicU64::new(config.cutoff_block)),
+            local_head: Arc::new(AtomicU64::new(0)),
+            config: Arc::new(config),
+            pool,
+            cache,
+            filters: Arc::new(LegacyFilterRegistry::new()),
+            limiter,
+            shadow_permits,
+        }
+    }
+
+    /// Returns true if legacy routing is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// First block served by the local node.
+    pub fn cutoff_block(&self) -> u64 {
+        self.cutoff_block.load(Ordering::Relaxed)
+    }
+
+    /// Moves the cutoff for every service created by this layer, e.g. once
+    /// the earliest local block is known at startup.
+    pub fn set_cutoff_block(&self, cutoff_block: u64) {
+        info!(target:"xlayer_legacy_rpc", "xlayer legacy rpc cutoff block = {cutoff_block}");
+        self.cutoff_block.store(cutoff_block, Ordering::Relaxed);
+    }
+
+    /// Fetches the logs matching an `eth_getLogs` filter object from the
+    /// legacy endpoints, outside of any RPC request.
+    pub async fn legacy_logs(
+        &self,
+        filter: serde_json::Value,
+    ) -> Result<Vec<serde_json::Value>, ErrorObjectOwned> {
+        self.layer(()).legacy_logs(filter).await
     }
 }
 
@@ -32,6 +125,16 @@ impl<S> Layer<S> for LegacyRpcRouterLayer {
     type Service = LegacyRpcRouterService<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        LegacyRpcRouterService { inner, config: self.config.clone(), client: self.client.clone() }
+        LegacyRpcRouterService {
+            inner,
+            config: self.config.clone(),
+            cutoff_block: self.cutoff_block.clone(),
+            local_head: self.local_head.clone(),
+            pool: self.pool.clone(),
+            cache: self.cache.clone(),
+            filters: self.filters.clone(),
+            limiter: self.limiter.clone(),
+            shadow_permits: self.shadow_permits.clone(),
+        }
     }
 }
@@ -1,28 +1,111 @@
+pub mod auth;
+pub mod cache;
+pub mod fee_history;
+pub mod filter;
 pub mod get_logs;
 pub mod layer;
+pub mod metrics;
+pub mod policy;
+pub mod pool;
+pub mod routes;
 pub mod service;
-
-use std::sync::Arc;
+pub mod shadow;
+pub mod trace;
+pub mod transport;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
 use jsonrpsee::{
     core::middleware::RpcServiceT,
     types::{
-        error::{CALL_EXECUTION_FAILED_CODE, INTERNAL_ERROR_CODE},
-        ErrorObject, Request,
+        error::{
+            CALL_EXECUTION_FAILED_CODE, INTERNAL_ERROR_CODE, OVERSIZED_RESPONSE_CODE,
+            OVERSIZED_RESPONSE_MSG,
+        },
+        ErrorObject, ErrorObjectOwned, Request,
     },
     MethodResponse,
 };
 use jsonrpsee_types::Id;
-use reqwest::Client;
 use serde_json::value::RawValue;
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+use crate::{
+    auth::LegacyAuthConfig,
+    cache::LegacyResponseCache,
+    filter::LegacyFilterRegistry,
+    metrics::LegacyRoutingMetrics,
+    policy::{RetryPolicy, TokenBucket},
+    pool::{CircuitBreakerConfig, EndpointSelection, LegacyEndpointPool},
+    routes::RoutingPolicy,
+    shadow::ShadowConfig,
+    transport::{LegacyResponse, TransportError},
+};
+
+/// EIP-1474 "limit exceeded" error code
+pub(crate) const LIMIT_EXCEEDED_CODE: i32 = -32005;
 
 /// Configuration for legacy RPC routing
 #[derive(Clone, Debug)]
 pub struct LegacyRpcRouterConfig {
     pub enabled: bool,
-    pub legacy_endpoint: String,
+    pub legacy_endpoints: Vec<String>,
+    pub selection: EndpointSelection,
+    /// Interval between endpoint health probes, zero disables probing
+    pub health_check_interval: std::time::Duration,
+    /// First block served locally, see [`layer::LegacyRpcRouterLayer::set_cutoff_block`]
     pub cutoff_block: u64,
     pub timeout: std::time::Duration,
+    /// Max bytes held by the legacy response cache, zero disables caching
+    pub cache_max_bytes: u64,
+    /// Optional directory persisting cached legacy responses across restarts
+    pub cache_dir: Option<std::path::PathBuf>,
+    /// Max bytes held by the disk tier of the cache, zero disables the disk tier
+    pub cache_disk_max_bytes: u64,
+    /// Block span of each chunk of a hybrid `eth_getLogs`, zero disables chunking
+    pub get_logs_chunk_size: u64,
+    /// Max number of `eth_getLogs` chunks in flight at once
+    pub get_logs_max_concurrency: usize,
+    /// Max number of chunks a hybrid `eth_getLogs` is split into, zero is
+    /// unlimited
+    pub get_logs_max_chunks: usize,
+    /// Max number of logs returned by a hybrid `eth_getLogs`, zero is unlimited
+    pub get_logs_max_results: usize,
+    /// Headers and credentials sent to the legacy endpoints
+    pub auth: LegacyAuthConfig,
+    /// Max outbound legacy calls per second, zero is unlimited
+    pub rate_limit: u32,
+    /// Burst capacity of the rate limit, zero uses the rate limit
+    pub rate_limit_burst: u32,
+    /// Retries of failed calls for idempotent methods
+    pub retry: RetryPolicy,
+    /// Per-endpoint circuit breaker
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// Routing table of the methods considered for legacy routing
+    pub routing: RoutingPolicy,
+    /// Methods sent to both legacy and the local node for comparison
+    pub shadow: ShadowConfig,
+    /// Max size of a response in bytes, matching the server's
+    /// `--rpc.max-response-size`, zero is unlimited
+    pub max_response_size: usize,
+}
+
+/// Failure of a single round over the legacy endpoints
+enum LegacyPostError {
+    /// Every attempted endpoint failed
+    Failed(String),
+    /// No endpoint was attempted since every circuit is open
+    CircuitOpen,
+    /// The response exceeds the max response size
+    ResponseTooLarge(usize),
 }
 
 /// XLayer legacy routing service
@@ -30,77 +113,323 @@ pub struct LegacyRpcRouterConfig {
 pub struct LegacyRpcRouterService<S> {
     inner: S,
     config: Arc<LegacyRpcRouterConfig>,
-    client: Client,
+    /// Current cutoff, starting at `config.cutoff_block`
+    cutoff_block: Arc<AtomicU64>,
+    /// Highest local head seen so far, which never goes back
+    local_head: Arc<AtomicU64>,
+    pool: Arc<LegacyEndpointPool>,
+    cache: Option<Arc<LegacyResponseCache>>,
+    filters: Arc<LegacyFilterRegistry>,
+    limiter: Option<Arc<TokenBucket>>,
+    /// Bounds the shadow comparisons in flight
+    shadow_permits: Arc<Semaphore>,
 }
 
 impl<S> LegacyRpcRouterService<S> {
+    /// First block served by the local node.
+    pub(crate) fn cutoff_block(&self) -> u64 {
+        self.cutoff_block.load(Ordering::Relaxed)
+    }
+
+    /// Highest local head seen so far, at least the cutoff.
+    pub(crate) fn known_local_head(&self) -> u64 {
+        self.local_head.load(Ordering::Relaxed).max(self.cutoff_block())
+    }
+
+    /// Records a local head returned by the inner service.
+    pub(crate) fn observe_local_head(&self, head: u64) {
+        self.local_head.fetch_max(head, Ordering::Relaxed);
+    }
+
+    /// Max size of a response in bytes.
+    pub(crate) fn max_response_size(&self) -> usize {
+        match self.config.max_response_size {
+            0 => usize::MAX,
+            max => max,
+        }
+    }
+
     async fn forward_to_legacy(&self, req: Request<'_>) -> MethodResponse {
         let request_id = req.id().clone();
 
+        let cache_key = self.cache_key(&req);
+        if let Some(response) = self.cached_response(&request_id, cache_key.as_deref()).await {
+            return response;
+        }
+
         // Build JSON-RPC request body
+        let body = legacy_request_body(&req, &request_id);
+
+        let idempotent = service::is_idempotent(req.method_name());
+        let start = Instant::now();
+        let result = self.post_to_legacy(&body, idempotent).await;
+        LegacyRoutingMetrics::for_method(req.method_name())
+            .legacy_duration
+            .record(start.elapsed().as_secs_f64());
+
+        match result {
+            Ok(raw) => match LegacyResponse::parse(&raw) {
+                Ok(response) => self.legacy_response(request_id, &response, cache_key),
+                Err(_) => invalid_legacy_response(request_id),
+            },
+            Err(e) => MethodResponse::error(request_id, e),
+        }
+    }
+
+    /// Forwards several requests to legacy as a single JSON-RPC batch.
+    ///
+    /// Responses are returned in the order of `reqs`. Upstream entries are
+    /// correlated by their position rather than the client ids, which may
+    /// repeat within a batch; each response carries its original id.
+    async fn forward_batch_to_legacy(&self, reqs: Vec<Request<'_>>) -> Vec<MethodResponse> {
+        let mut responses: Vec<Option<MethodResponse>> = (0..reqs.len()).map(|_| None).collect();
+        let mut pending = Vec::new();
+        let mut body = Vec::new();
+
+        for (i, req) in reqs.iter().enumerate() {
+            let request_id = req.id().into_owned();
+            let cache_key = self.cache_key(req);
+            if let Some(response) = self.cached_response(&request_id, cache_key.as_deref()).await {
+                responses[i] = Some(response);
+                continue;
+            }
+
+            body.push(legacy_request_body(req, &Id::Number(pending.len() as u64)));
+            pending.push((i, request_id, cache_key));
+        }
+
+        if !pending.is_empty() {
+            debug!(target:"xlayer_legacy_rpc", "Forwarding batch of {} requests to legacy", pending.len());
+            let idempotent = reqs.iter().all(|req| service::is_idempotent(req.method_name()));
+            let start = Instant::now();
+            let result = self.post_to_legacy(&serde_json::Value::Array(body), idempotent).await;
+            LegacyRoutingMetrics::for_method("batch")
+                .legacy_duration
+                .record(start.elapsed().as_secs_f64());
+
+            let raw = match result {
+                Ok(raw) => raw,
+                Err(e) => {
+                    for (i, request_id, _) in pending {
+                        responses[i] = Some(MethodResponse::error(request_id, e.clone()));
+                    }
+                    return responses.into_iter().flatten().collect();
+                }
+            };
+
+            match LegacyResponse::parse_batch(&raw) {
+                Ok(items) => {
+                    let mut by_id: HashMap<u64, LegacyResponse<'_>> = items
+                        .into_iter()
+                        .filter_map(|item| Some((item.id?.get().parse::<u64>().ok()?, item)))
+                        .collect();
+                    for (pos, (i, request_id, cache_key)) in pending.into_iter().enumerate() {
+                        responses[i] = Some(match by_id.remove(&(pos as u64)) {
+                            Some(item) => self.legacy_response(request_id, &item, cache_key),
+                            None => MethodResponse::error(
+                                request_id,
+                                ErrorObject::owned(
+                                    INTERNAL_ERROR_CODE,
+                                    "Missing legacy batch response",
+                                    None::<()>,
+                                ),
+                            ),
+                        });
+                    }
+                }
+                // A single object means the whole batch was rejected upstream
+                Err(_) => {
+                    let response = LegacyResponse::parse(&raw);
+                    for (i, request_id, _) in pending {
+                        responses[i] = Some(match &response {
+                            Ok(response) => self.legacy_response(request_id, response, None),
+                            Err(_) => invalid_legacy_response(request_id),
+                        });
+                    }
+                }
+            }
+        }
+
+        responses.into_iter().flatten().collect()
+    }
+
+    fn cache_key(&self, req: &Request<'_>) -> Option<String> {
+        if self.cache.is_none() {
+            return None;
+        }
+        crate::cache::cache_key(req.method_name(), req.params().as_str())
+    }
+
+    async fn cached_response(
+        &self,
+        request_id: &Id<'_>,
+        cache_key: Option<&str>,
+    ) -> Option<MethodResponse> {
+        let result = self.cache.as_ref()?.get(cache_key?).await?;
+        let payload = jsonrpsee_types::ResponsePayload::success(result.as_ref()).into();
+        Some(MethodResponse::response(request_id.clone(), payload, self.max_response_size()))
+    }
+
+    /// Converts a legacy JSON-RPC response object into a [`MethodResponse`],
+    /// caching successful results under `cache_key`. The raw result is copied
+    /// into the response as is, without being parsed.
+    fn legacy_response(
+        &self,
+        request_id: Id<'_>,
+        response: &LegacyResponse<'_>,
+        cache_key: Option<String>,
+    ) -> MethodResponse {
+        if let Some(result) = response.result {
+            if let (Some(cache), Some(key)) = (&self.cache, cache_key) {
+                cache.insert(key, result);
+            }
+            let payload = jsonrpsee_types::ResponsePayload::success(result).into();
+            MethodResponse::response(request_id, payload, self.max_response_size())
+        } else if let Some(error) = &response.error {
+            MethodResponse::error(request_id, legacy_error_object(error))
+        } else {
+            invalid_legacy_response(request_id)
+        }
+    }
+
+    /// Fetches the logs matching an `eth_getLogs` filter object from legacy,
+    /// for callers outside the RPC server such as subscription replays.
+    pub(crate) async fn legacy_logs(
+        &self,
+        filter: serde_json::Value,
+    ) -> Result<Vec<serde_json::Value>, ErrorObjectOwned> {
         let body = serde_json::json!({
             "jsonrpc": "2.0",
-            "method": req.method_name(),
-            "params": req.params().as_str()
-                .and_then(|s| serde_json::from_str::<serde_json::Value>(s).ok())
-                .unwrap_or(serde_json::Value::Null),
+            "method": "eth_getLogs",
+            "params": [filter],
             "id": 1
         });
 
-        match self.client.post(&self.config.legacy_endpoint).json(&body).send().await {
-            Ok(response) => match response.json::<serde_json::Value>().await {
-                Ok(json) => {
-                    if let Some(result) = json.get("result") {
-                        let payload = jsonrpsee_types::ResponsePayload::success(result).into();
-                        MethodResponse::response(request_id, payload, usize::MAX)
-                    } else if let Some(error) = json.get("error") {
-                        let code = error
-                            .get("code")
-                            .and_then(|c| c.as_i64())
-                            .unwrap_or(CALL_EXECUTION_FAILED_CODE as i64)
-                            as i32;
-                        let message = error
-                            .get("message")
-                            .and_then(|m| m.as_str())
-                            .unwrap_or("Legacy RPC error");
-                        MethodResponse::error(
-                            request_id,
-                            ErrorObject::owned(code, message, None::<()>),
-                        )
-                    } else {
-                        MethodResponse::error(
-                            request_id,
-                            ErrorObject::owned(
-                                INTERNAL_ERROR_CODE,
-                                "Invalid legacy response",
-                                None::<()>,
-                            ),
-                        )
-                    }
+        let start = Instant::now();
+        let result = self.post_to_legacy(&body, true).await;
+        LegacyRoutingMetrics::for_method("eth_getLogs")
+            .legacy_duration
+            .record(start.elapsed().as_secs_f64());
+
+        let mut json = serde_json::from_str::<serde_json::Value>(result?.get())
+            .map_err(|e| ErrorObject::owned(INTERNAL_ERROR_CODE, e.to_string(), None::<()>))?;
+        if let Some(error) = json.get("error") {
+            return Err(legacy_error_object(error));
+        }
+        match json.get_mut("result").map(serde_json::Value::take) {
+            Some(serde_json::Value::Array(logs)) => Ok(logs),
+            _ => {
+                Err(ErrorObject::owned(INTERNAL_ERROR_CODE, "Invalid legacy response", None::<()>))
+            }
+        }
+    }
+
+    /// Posts a JSON-RPC body to the legacy endpoints, subject to the rate
+    /// limit. Calls that fail on every endpoint are retried with backoff
+    /// when `idempotent` is set.
+    async fn post_to_legacy(
+        &self,
+        body: &serde_json::Value,
+        idempotent: bool,
+    ) -> Result<Box<RawValue>, ErrorObjectOwned> {
+        let retry = self.config.retry;
+        let max_retries = if idempotent { retry.max_retries } else { 0 };
+        let mut attempt = 0;
+
+        loop {
+            if let Some(limiter) = &self.limiter
+                && !limiter.try_acquire()
+            {
+                debug!(target:"xlayer_legacy_rpc", "Legacy RPC rate limit exceeded");
+                return Err(ErrorObject::owned(
+                    LIMIT_EXCEEDED_CODE,
+                    "Legacy RPC rate limit exceeded",
+                    None::<()>,
+                ));
+            }
+
+            match self.post_to_endpoints(body).await {
+                Ok(raw) => return Ok(raw),
+                Err(LegacyPostError::Failed(e)) if attempt < max_retries => {
+                    let backoff = retry.backoff(attempt);
+                    attempt += 1;
+                    debug!(
+                        target:"xlayer_legacy_rpc",
+                        "Legacy RPC call failed, retry {attempt}/{max_retries} in {backoff:?}, err = {e}"
+                    );
+                    tokio::time::sleep(backoff).await;
                 }
-                Err(e) => MethodResponse::error(
-                    request_id,
-                    ErrorObject::owned(
-                        INTERNAL_ERROR_CODE,
-                        format!("Legacy parse error: {e}"),
-                        None::<()>,
-                    ),
-                ),
-            },
-            Err(e) => {
-                tracing::error!(target: "rpc::legacy", error = %e, "Legacy RPC request failed");
-                MethodResponse::error(
-                    request_id,
-                    ErrorObject::owned(
+                Err(LegacyPostError::Failed(e)) => {
+                    return Err(ErrorObject::owned(INTERNAL_ERROR_CODE, e, None::<()>));
+                }
+                Err(LegacyPostError::CircuitOpen) => {
+                    return Err(ErrorObject::owned(
                         INTERNAL_ERROR_CODE,
-                        format!("Legacy RPC error: {e}"),
+                        "Legacy RPC unavailable: circuit open on all endpoints",
                         None::<()>,
-                    ),
-                )
+                    ));
+                }
+                Err(LegacyPostError::ResponseTooLarge(max)) => {
+                    return Err(ErrorObject::owned(
+                        OVERSIZED_RESPONSE_CODE,
+                        OVERSIZED_RESPONSE_MSG,
+                        Some(format!("Exceeded max limit of {max}")),
+                    ));
+                }
             }
         }
     }
 
+    /// Posts a JSON-RPC body to the legacy endpoints, failing over to the
+    /// next candidate on transport errors or 5xx responses. Endpoints with an
+    /// open circuit are skipped.
+    async fn post_to_endpoints(
+        &self,
+        body: &serde_json::Value,
+    ) -> Result<Box<RawValue>, LegacyPostError> {
+        let mut last_error = None;
+
+        for endpoint in self.pool.candidates() {
+            if !endpoint.try_acquire() {
+                continue;
+            }
+
+            let start = Instant::now();
+            match endpoint.transport().send(body).await {
+                Ok(response) => {
+                    endpoint.mark_up(start.elapsed());
+                    return Ok(response);
+                }
+                Err(TransportError::InvalidResponse(e)) => {
+                    endpoint.mark_up(start.elapsed());
+                    return Err(LegacyPostError::Failed(format!("Legacy parse error: {e}")));
+                }
+                Err(TransportError::ResponseTooLarge(max)) => {
+                    endpoint.mark_up(start.elapsed());
+                    return Err(LegacyPostError::ResponseTooLarge(max));
+                }
+                Err(TransportError::Unavailable(e)) => {
+                    tracing::warn!(
+                        target: "rpc::legacy",
+                        endpoint = endpoint.label(),
+                        error = %e,
+                        "Legacy RPC request failed, failing over"
+                    );
+                    endpoint.mark_down();
+                    last_error = Some(format!("Legacy RPC error: {e}"));
+                }
+            }
+        }
+
+        match last_error {
+            Some(e) => Err(LegacyPostError::Failed(e)),
+            None if self.pool.is_empty() => {
+                Err(LegacyPostError::Failed("No legacy RPC endpoint configured".to_string()))
+            }
+            None => Err(LegacyPostError::CircuitOpen),
+        }
+    }
+
     pub async fn call_eth_get_block_by_hash(
         &self,
         block_hash: &str,
@@ -172,6 +501,37 @@ impl<S> LegacyRpcRouterService<S> {
     }
 }
 
+/// Builds the JSON-RPC body sent to legacy for `req` under the given id.
+fn legacy_request_body(req: &Request<'_>, id: &Id<'_>) -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": req.method_name(),
+        "params": req.params().as_str()
+            .and_then(|s| serde_json::from_str::<serde_json::Value>(s).ok())
+            .unwrap_or(serde_json::Value::Null),
+        "id": id
+    })
+}
+
+/// Converts a legacy JSON-RPC error object into an owned error, keeping the
+/// upstream `code`, `message` and `data` (e.g. revert data) unchanged.
+pub(crate) fn legacy_error_object(error: &serde_json::Value) -> ErrorObjectOwned {
+    let code = error
+        .get("code")
+        .and_then(|c| c.as_i64())
+        .and_then(|c| i32::try_from(c).ok())
+        .unwrap_or(CALL_EXECUTION_FAILED_CODE);
+    let message = error.get("message").and_then(|m| m.as_str()).unwrap_or("Legacy RPC error");
+    ErrorObject::owned(code, message, error.get("data").cloned())
+}
+
+fn invalid_legacy_response(request_id: Id<'_>) -> MethodResponse {
+    MethodResponse::error(
+        request_id,
+        ErrorObject::owned(INTERNAL_ERROR_CODE, "Invalid legacy response", None::<()>),
+    )
+}
+
 /// Validates that a string is a valid 32-byte hexadecimal string (block hash or similar).
 /// Checks that the string:
 /// - Has the "0x" prefix
@@ -204,6 +564,10 @@ pub fn is_block_hash(hex: &str) -> bool {
 }
 
 /// Handles latest, pending, hash, hex number etc
+///
+/// The EIP-1898 `requireCanonical` flag only has to be a boolean here: it is
+/// not enforced by the router. Requests are forwarded with their original
+/// params, so the node answering them, local or legacy, enforces it.
 #[inline]
 pub(crate) fn parse_block_param(params: &str, index: usize) -> Option<String> {
     let parsed: serde_json::Value = serde_json::from_str(params).ok()?;
@@ -245,8 +609,14 @@ pub(crate) fn parse_block_param(params: &str, index: usize) -> Option<String> {
                 _ => None,
             }
         }
-        // Handle object format: {"blockHash": "0x..."} or {"blockNumber": "0x..."}
+        // Handle EIP-1898 object format: {"blockHash": "0x...", "requireCanonical": bool}
+        // or {"blockNumber": "0x..."}
         serde_json::Value::Object(obj) => {
+            // Malformed requireCanonical is left to the local node to reject
+            if obj.get("requireCanonical").is_some_and(|v| !v.is_boolean()) {
+                return None;
+            }
+
             if let Some(serde_json::Value::String(hash)) = obj.get("blockHash") {
                 // Validate block hash to prevent JSON injection
                 if is_valid_32_bytes_string(hash) {
@@ -274,6 +644,7 @@ pub(crate) fn parse_block_param(params: &str, index: usize) -> Option<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transport::TransportConfig;
     use jsonrpsee::core::middleware::RpcServiceT;
     use jsonrpsee::types::{Id, Request};
     use jsonrpsee::MethodResponse;
@@ -289,7 +660,7 @@ mod tests {
     impl RpcServiceT for MockRpcService {
         type MethodResponse = MethodResponse;
         type NotificationResponse = MethodResponse;
-        type BatchResponse = Vec<MethodResponse>;
+        type BatchResponse = MethodResponse;
 
         fn call<'a>(
             &self,
@@ -323,7 +694,16 @@ mod tests {
             &self,
             _req: jsonrpsee::core::middleware::Batch<'a>,
         ) -> impl Future<Output = Self::BatchResponse> + Send + 'a {
-            Box::pin(async { vec![] })
+            Box::pin(async {
+                MethodResponse::error(
+                    Id::Null,
+                    jsonrpsee::types::ErrorObjectOwned::owned(
+                        -32600,
+                        "Not implemented",
+                        None::<()>,
+                    ),
+                )
+            })
         }
 
         fn notification<'a>(
@@ -344,22 +724,496 @@ mod tests {
     }
 
     fn create_test_service(response: &str) -> LegacyRpcRouterService<MockRpcService> {
+        create_test_service_with_endpoint(response, "https://testrpc.xlayer.tech/terigon")
+    }
+
+    fn create_test_service_with_endpoint(
+        response: &str,
+        endpoint: &str,
+    ) -> LegacyRpcRouterService<MockRpcService> {
         let config = LegacyRpcRouterConfig {
             enabled: true,
-            legacy_endpoint: "https://testrpc.xlayer.tech/terigon".to_string(),
+            legacy_endpoints: vec![endpoint.to_string()],
+            selection: EndpointSelection::RoundRobin,
+            health_check_interval: std::time::Duration::ZERO,
             cutoff_block: 1_000_000,
             timeout: std::time::Duration::from_secs(10),
+            cache_max_bytes: 0,
+            cache_dir: None,
+            cache_disk_max_bytes: 0,
+            get_logs_chunk_size: 0,
+            get_logs_max_concurrency: 1,
+            get_logs_max_chunks: 0,
+            get_logs_max_results: 0,
+            auth: LegacyAuthConfig::default(),
+            rate_limit: 0,
+            rate_limit_burst: 0,
+            retry: RetryPolicy::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            routing: RoutingPolicy::default(),
+            shadow: ShadowConfig::default(),
+            max_response_size: 0,
         };
 
         let mock_service = MockRpcService { response: response.to_string() };
+        let pool = Arc::new(LegacyEndpointPool::new(&config.legacy_endpoints, config.selection));
+        let shadow_permits = Arc::new(Semaphore::new(config.shadow.max_in_flight));
 
         LegacyRpcRouterService {
             inner: mock_service,
+            cutoff_block: Arc::new(AtomicU64::new(config.cutoff_block)),
+            local_head: Arc::new(AtomicU64::new(0)),
             config: Arc::new(config),
-            client: reqwest::Client::new(),
+            pool,
+            cache: None,
+            filters: Arc::new(LegacyFilterRegistry::new()),
+            limiter: None,
+            shadow_permits,
+        }
+    }
+
+    /// Starts a local jsonrpsee server acting as the legacy endpoint.
+    async fn spawn_legacy_server(
+        module: jsonrpsee::RpcModule<()>,
+    ) -> (String, jsonrpsee::server::ServerHandle) {
+        let server = jsonrpsee::server::Server::builder().build("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", server.local_addr().unwrap());
+        (url, server.start(module))
+    }
+
+    #[tokio::test]
+    async fn test_forward_to_legacy_preserves_id_and_error_data() {
+        let mut module = jsonrpsee::RpcModule::new(());
+        module
+            .register_method("eth_call", |_, _, _| {
+                Err::<(), _>(jsonrpsee::types::ErrorObjectOwned::owned(
+                    3,
+                    "execution reverted: not owner",
+                    Some("0x08c379a0deadbeef"),
+                ))
+            })
+            .unwrap();
+        let (url, _handle) = spawn_legacy_server(module).await;
+
+        let service = create_test_service_with_endpoint(r#"{"result":null}"#, &url);
+        let params = RawValue::from_string(r#"[{"to":"0x00"},"0x1"]"#.to_string()).unwrap();
+        let request =
+            Request::owned("eth_call".to_string(), Some(params), Id::Str("req-42".into()));
+
+        let response = service.forward_to_legacy(request).await;
+        let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+
+        assert_eq!(json["id"], "req-42");
+        assert_eq!(json["error"]["code"], 3);
+        assert_eq!(json["error"]["message"], "execution reverted: not owner");
+        assert_eq!(json["error"]["data"], "0x08c379a0deadbeef");
+    }
+
+    #[tokio::test]
+    async fn test_legacy_logs() {
+        let mut module = jsonrpsee::RpcModule::new(());
+        module
+            .register_method("eth_getLogs", |params, _, _| {
+                let filter: serde_json::Value = params.one()?;
+                Ok::<_, jsonrpsee::types::ErrorObjectOwned>(serde_json::json!([
+                    {"blockNumber": filter["fromBlock"], "logIndex": "0x0"}
+                ]))
+            })
+            .unwrap();
+        let (url, _handle) = spawn_legacy_server(module).await;
+
+        let service = create_test_service_with_endpoint(r#"{"result":null}"#, &url);
+        let logs = service
+            .legacy_logs(serde_json::json!({"fromBlock": "0x10", "toBlock": "0x20"}))
+            .await
+            .unwrap();
+
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0]["blockNumber"], "0x10");
+    }
+
+    #[tokio::test]
+    async fn test_forward_batch_to_legacy_keeps_order_and_ids() {
+        let mut module = jsonrpsee::RpcModule::new(());
+        module
+            .register_method("eth_getBlockByNumber", |params, _, _| {
+                params.parse::<serde_json::Value>()
+            })
+            .unwrap();
+        module
+            .register_method("eth_getBalance", |_, _, _| {
+                Err::<(), _>(jsonrpsee::types::ErrorObjectOwned::owned(
+                    -32000,
+                    "missing trie node",
+                    None::<()>,
+                ))
+            })
+            .unwrap();
+        let (url, _handle) = spawn_legacy_server(module).await;
+
+        let service = create_test_service_with_endpoint(r#"{"result":null}"#, &url);
+        let requests = vec![
+            Request::owned(
+                "eth_getBlockByNumber".to_string(),
+                Some(RawValue::from_string(r#"["0x1",false]"#.to_string()).unwrap()),
+                Id::Number(7),
+            ),
+            Request::owned(
+                "eth_getBalance".to_string(),
+                Some(RawValue::from_string(r#"["0x00","0x1"]"#.to_string()).unwrap()),
+                Id::Str("balance".into()),
+            ),
+            Request::owned(
+                "eth_getBlockByNumber".to_string(),
+                Some(RawValue::from_string(r#"["0x2",false]"#.to_string()).unwrap()),
+                Id::Number(7),
+            ),
+        ];
+
+        let responses = service.forward_batch_to_legacy(requests).await;
+        assert_eq!(responses.len(), 3);
+
+        let json: Vec<serde_json::Value> =
+            responses.iter().map(|r| serde_json::from_str(r.as_json().get()).unwrap()).collect();
+        assert_eq!(json[0]["id"], 7);
+        assert_eq!(json[0]["result"], serde_json::json!(["0x1", false]));
+        assert_eq!(json[1]["id"], "balance");
+        assert_eq!(json[1]["error"]["message"], "missing trie node");
+        assert_eq!(json[2]["id"], 7);
+        assert_eq!(json[2]["result"], serde_json::json!(["0x2", false]));
+    }
+
+    #[test]
+    fn test_parse_block_param_eip1898_require_canonical() {
+        let hash = "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef";
+
+        let params = format!(r#"["0x00",{{"blockHash":"{hash}","requireCanonical":true}}]"#);
+        assert_eq!(parse_block_param(&params, 1), Some(hash.to_string()));
+
+        let params = format!(r#"["0x00",{{"blockHash":"{hash}","requireCanonical":false}}]"#);
+        assert_eq!(parse_block_param(&params, 1), Some(hash.to_string()));
+
+        let params = format!(r#"["0x00",{{"blockHash":"{hash}","requireCanonical":"yes"}}]"#);
+        assert_eq!(parse_block_param(&params, 1), None);
+    }
+
+    #[tokio::test]
+    async fn test_block_hash_below_cutoff_routes_to_legacy() {
+        let mut module = jsonrpsee::RpcModule::new(());
+        module.register_method("eth_getBalance", |_, _, _| "0x1234".to_string()).unwrap();
+        let (url, _handle) = spawn_legacy_server(module).await;
+
+        let hash = "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef";
+        let params = format!(r#"["0x00",{{"blockHash":"{hash}","requireCanonical":true}}]"#);
+
+        // Local resolves the hash to block 0x10, below the cutoff
+        let service = create_test_service_with_endpoint(
+            r#"{"jsonrpc":"2.0","id":1,"result":{"number":"0x10"}}"#,
+            &url,
+        );
+        let request = Request::owned(
+            "eth_getBalance".to_string(),
+            Some(RawValue::from_string(params.clone()).unwrap()),
+            Id::Number(1),
+        );
+        let response = service.call(request).await;
+        let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+        assert_eq!(json["result"], "0x1234");
+
+        // Local resolves the hash to the cutoff block, served locally
+        let service = create_test_service_with_endpoint(
+            r#"{"jsonrpc":"2.0","id":1,"result":{"number":"0xf4240"}}"#,
+            &url,
+        );
+        let request = Request::owned(
+            "eth_getBalance".to_string(),
+            Some(RawValue::from_string(params).unwrap()),
+            Id::Number(1),
+        );
+        let response = service.call(request).await;
+        let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+        assert_eq!(json["result"]["number"], "0xf4240");
+    }
+
+    #[tokio::test]
+    async fn test_require_canonical_forwarded_to_legacy() {
+        let mut module = jsonrpsee::RpcModule::new(());
+        module
+            .register_method("eth_getBalance", |params, _, _| {
+                let params: serde_json::Value = params.parse().unwrap_or_default();
+                params[1]["requireCanonical"].clone()
+            })
+            .unwrap();
+        let (url, _handle) = spawn_legacy_server(module).await;
+
+        let hash = "0x1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef";
+        // Local doesn't know the hash
+        let service =
+            create_test_service_with_endpoint(r#"{"jsonrpc":"2.0","id":1,"result":null}"#, &url);
+        for require_canonical in [true, false] {
+            let params = format!(
+                r#"["0x00",{{"blockHash":"{hash}","requireCanonical":{require_canonical}}}]"#
+            );
+            let request = Request::owned(
+                "eth_getBalance".to_string(),
+                Some(RawValue::from_string(params).unwrap()),
+                Id::Number(1),
+            );
+            let response = service.call(request).await;
+            let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+            assert_eq!(json["result"], require_canonical);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_filter_below_cutoff_serves_legacy_logs() {
+        let mut module = jsonrpsee::RpcModule::new(());
+        module
+            .register_method(
+                "eth_getLogs",
+                |_, _, _| serde_json::json!([{"blockNumber": "0x5", "logIndex": "0x0"}]),
+            )
+            .unwrap();
+        let (url, _handle) = spawn_legacy_server(module).await;
+
+        // Local returns the filter id on install
+        let service = create_test_service_with_endpoint(r#"{"result":"0xfilter01"}"#, &url);
+        let request = Request::owned(
+            "eth_newFilter".to_string(),
+            Some(
+                RawValue::from_string(r#"[{"fromBlock":"0x1","toBlock":"0x10"}]"#.into()).unwrap(),
+            ),
+            Id::Number(1),
+        );
+        let response = service.call(request).await;
+        let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+        assert_eq!(json["result"], "0xfilter01");
+
+        let request = Request::owned(
+            "eth_getFilterLogs".to_string(),
+            Some(RawValue::from_string(r#"["0xfilter01"]"#.to_string()).unwrap()),
+            Id::Number(2),
+        );
+        let response = service.call(request).await;
+        let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+        assert_eq!(json["id"], 2);
+        assert_eq!(json["result"], serde_json::json!([{"blockNumber": "0x5", "logIndex": "0x0"}]));
+    }
+
+    #[tokio::test]
+    async fn test_routing_policy_routes_custom_method() {
+        let mut module = jsonrpsee::RpcModule::new(());
+        module.register_method("vendor_getThing", |_, _, _| "legacy".to_string()).unwrap();
+        let (url, _handle) = spawn_legacy_server(module).await;
+
+        let path = std::env::temp_dir()
+            .join(format!("xlayer-legacy-routing-service-test-{}.toml", std::process::id()));
+        std::fs::write(&path, "[methods.vendor_getThing]\nroute = \"block\"\n").unwrap();
+        let routing = RoutingPolicy::from_file(&path).unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let mut service = create_test_service_with_endpoint(r#"{"result":"local"}"#, &url);
+        Arc::make_mut(&mut service.config).routing = routing;
+
+        for (block, expected) in [("0x10", "legacy"), ("0xf4240", "local")] {
+            let request = Request::owned(
+                "vendor_getThing".to_string(),
+                Some(RawValue::from_string(format!(r#"["{block}"]"#)).unwrap()),
+                Id::Number(1),
+            );
+            let response = service.call(request).await;
+            let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+            assert_eq!(json["result"], expected);
         }
     }
 
+    #[tokio::test]
+    async fn test_hybrid_get_logs_rejects_too_many_chunks() {
+        let mut service = create_test_service(r#"{"result":[]}"#);
+        service.config = Arc::new(LegacyRpcRouterConfig {
+            get_logs_chunk_size: 1,
+            get_logs_max_chunks: 100,
+            ..(*service.config).clone()
+        });
+        // Crosses the cutoff at 1_000_000 with a million single block chunks
+        let request = Request::owned(
+            "eth_getLogs".to_string(),
+            Some(
+                RawValue::from_string(r#"[{"fromBlock":"0x0","toBlock":"0xf4245"}]"#.to_string())
+                    .unwrap(),
+            ),
+            Id::Number(1),
+        );
+
+        let response = crate::get_logs::handle_eth_get_logs(request, service).await;
+        let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+        assert_eq!(json["error"]["code"], jsonrpsee::types::error::INVALID_PARAMS_CODE);
+    }
+
+    #[tokio::test]
+    async fn test_fee_history_latest_skips_head_lookup() {
+        let mut module = jsonrpsee::RpcModule::new(());
+        module.register_method("eth_feeHistory", |_, _, _| "legacy").unwrap();
+        let (url, _handle) = spawn_legacy_server(module).await;
+
+        // Local answers every call, including `eth_blockNumber`, with 0x1
+        let service = create_test_service_with_endpoint(r#"{"result":"0x1"}"#, &url);
+        let fee_history = |params: &str| {
+            let request = Request::owned(
+                "eth_feeHistory".to_string(),
+                Some(RawValue::from_string(params.to_string()).unwrap()),
+                Id::Number(1),
+            );
+            let service = service.clone();
+            async move {
+                let response = crate::fee_history::handle_eth_fee_history(request, service).await;
+                serde_json::from_str::<serde_json::Value>(response.as_json().get()).unwrap()
+            }
+        };
+
+        // A single block window can't start below the cutoff
+        assert_eq!(fee_history(r#"[1,"latest"]"#).await["result"], "0x1");
+        // The head is resolved, and being below the cutoff routes to legacy
+        assert_eq!(fee_history(r#"[4,"latest"]"#).await["result"], "legacy");
+
+        // Once a head past the window is known, the lookup is skipped
+        service.observe_local_head(1_000_010);
+        assert_eq!(fee_history(r#"[4,"pending"]"#).await["result"], "0x1");
+        assert_eq!(fee_history(r#"[20,"latest"]"#).await["result"], "legacy");
+    }
+
+    #[tokio::test]
+    async fn test_shadow_returns_routed_response() {
+        let mut module = jsonrpsee::RpcModule::new(());
+        module.register_method("eth_getBalance", |_, _, _| "legacy").unwrap();
+        let (url, _handle) = spawn_legacy_server(module).await;
+
+        let mut service = create_test_service_with_endpoint(r#"{"result":"local"}"#, &url);
+        service.config = Arc::new(LegacyRpcRouterConfig {
+            shadow: ShadowConfig {
+                methods: ["eth_getBalance".to_string()].into(),
+                ..Default::default()
+            },
+            ..(*service.config).clone()
+        });
+        let get_balance = |block: &str| {
+            let params = format!(r#"["0x0000000000000000000000000000000000000001","{block}"]"#);
+            Request::owned(
+                "eth_getBalance".to_string(),
+                Some(RawValue::from_string(params).unwrap()),
+                Id::Number(1),
+            )
+        };
+
+        // Below the cutoff legacy answers, within the window above it local does
+        let response = service.call(get_balance("0x10")).await;
+        assert!(response.as_json().get().contains(r#""result":"legacy""#));
+        let response = service.call(get_balance("0xf4241")).await;
+        assert!(response.as_json().get().contains(r#""result":"local""#));
+    }
+
+    #[tokio::test]
+    async fn test_forward_to_legacy_rate_limited() {
+        let mut module = jsonrpsee::RpcModule::new(());
+        module.register_method("eth_getBalance", |_, _, _| "0x1234".to_string()).unwrap();
+        let (url, _handle) = spawn_legacy_server(module).await;
+
+        let mut service = create_test_service_with_endpoint(r#"{"result":null}"#, &url);
+        service.limiter = Some(Arc::new(TokenBucket::new(1, 1)));
+        let request = || {
+            Request::owned(
+                "eth_getBalance".to_string(),
+                Some(RawValue::from_string(r#"["0x00","0x1"]"#.to_string()).unwrap()),
+                Id::Number(1),
+            )
+        };
+
+        let response = service.forward_to_legacy(request()).await;
+        let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+        assert_eq!(json["result"], "0x1234");
+
+        let response = service.forward_to_legacy(request()).await;
+        let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+        assert_eq!(json["error"]["code"], LIMIT_EXCEEDED_CODE);
+    }
+
+    #[tokio::test]
+    async fn test_forward_to_legacy_fails_fast_when_circuit_open() {
+        let mut service =
+            create_test_service_with_endpoint(r#"{"result":null}"#, "http://127.0.0.1:1");
+        let breaker =
+            CircuitBreakerConfig { threshold: 1, cooldown: std::time::Duration::from_secs(60) };
+        service.pool = Arc::new(LegacyEndpointPool::with_circuit_breaker(
+            &service.config.legacy_endpoints,
+            service.config.selection,
+            breaker,
+        ));
+        let request = || {
+            Request::owned(
+                "eth_getBalance".to_string(),
+                Some(RawValue::from_string(r#"["0x00","0x1"]"#.to_string()).unwrap()),
+                Id::Number(1),
+            )
+        };
+
+        let response = service.forward_to_legacy(request()).await;
+        let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+        assert!(json["error"]["message"].as_str().unwrap().starts_with("Legacy RPC error"));
+
+        let response = service.forward_to_legacy(request()).await;
+        let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+        assert!(json["error"]["message"].as_str().unwrap().contains("circuit open"));
+    }
+
+    #[tokio::test]
+    async fn test_forward_to_legacy_enforces_max_response_size() {
+        let mut module = jsonrpsee::RpcModule::new(());
+        module.register_method("eth_getCode", |_, _, _| format!("0x{}", "ab".repeat(256))).unwrap();
+        module.register_method("eth_getTransactionByHash", |_, _, _| None::<()>).unwrap();
+        let (url, _handle) = spawn_legacy_server(module).await;
+        let request = |method: &str| {
+            Request::owned(
+                method.to_string(),
+                Some(RawValue::from_string(r#"["0x00","0x1"]"#.to_string()).unwrap()),
+                Id::Number(1),
+            )
+        };
+
+        // A null result is passed through as is
+        let mut service = create_test_service_with_endpoint(r#"{"result":null}"#, &url);
+        let response = service.forward_to_legacy(request("eth_getTransactionByHash")).await;
+        let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+        assert_eq!(json.get("result"), Some(&serde_json::Value::Null));
+
+        // Rejected when building the response
+        Arc::make_mut(&mut service.config).max_response_size = 128;
+        let response = service.forward_to_legacy(request("eth_getCode")).await;
+        let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+        assert_eq!(json["error"]["code"], OVERSIZED_RESPONSE_CODE);
+
+        // Rejected by the transport while reading the body
+        let transport = TransportConfig { max_response_size: 128, ..Default::default() };
+        service.pool = Arc::new(LegacyEndpointPool::with_transport_config(
+            &service.config.legacy_endpoints,
+            service.config.selection,
+            service.config.circuit_breaker,
+            &transport,
+        ));
+        Arc::make_mut(&mut service.config).max_response_size = 0;
+        let response = service.forward_to_legacy(request("eth_getCode")).await;
+        let json: serde_json::Value = serde_json::from_str(response.as_json().get()).unwrap();
+        assert_eq!(json["error"]["code"], OVERSIZED_RESPONSE_CODE);
+        assert_eq!(json["error"]["data"], "Exceeded max limit of 128");
+    }
+
+    #[test]
+    fn test_legacy_error_object_defaults() {
+        let error = legacy_error_object(&serde_json::json!({}));
+        assert_eq!(error.code(), CALL_EXECUTION_FAILED_CODE);
+        assert_eq!(error.message(), "Legacy RPC error");
+        assert!(error.data().is_none());
+    }
+
     #[tokio::test]
     async fn test_get_transaction_by_hash_found() {
         let response = r#"{
@@ -0,0 +1,157 @@
+use std::sync::LazyLock;
+
+use dashmap::DashMap;
+use reth_metrics::{
+    metrics::{Counter, Gauge, Histogram},
+    Metrics,
+};
+use tracing::debug;
+
+/// Per-endpoint legacy RPC metrics, labelled by redacted endpoint url
+#[derive(Metrics, Clone)]
+#[metrics(scope = "xlayer_legacy_rpc.endpoint")]
+pub struct LegacyEndpointMetrics {
+    /// Whether the endpoint is considered up (1) or down (0)
+    pub up: Gauge,
+    /// Histogram of successful request latency to the endpoint
+    pub request_duration: Histogram,
+    /// Number of failed requests or health probes to the endpoint
+    pub failures: Counter,
+    /// Whether the endpoint circuit breaker is open (1) or closed (0)
+    pub circuit_open: Gauge,
+}
+
+/// Legacy response cache metrics
+#[derive(Metrics, Clone)]
+#[metrics(scope = "xlayer_legacy_rpc.cache")]
+pub struct LegacyCacheMetrics {
+    /// Number of lookups served from memory
+    pub memory_hits: Counter,
+    /// Number of lookups served from disk
+    pub disk_hits: Counter,
+    /// Number of lookups that missed both tiers
+    pub misses: Counter,
+    /// Number of results inserted into the cache
+    pub inserts: Counter,
+    /// Approximate size of the memory tier in bytes
+    pub memory_size_bytes: Gauge,
+    /// Approximate size of the disk tier in bytes
+    pub disk_size_bytes: Gauge,
+}
+
+/// Per-method routing metrics, labelled by method
+#[derive(Metrics, Clone)]
+#[metrics(scope = "xlayer_legacy_rpc.routing")]
+pub struct LegacyRoutingMetrics {
+    /// Number of requests served by the local node
+    pub local: Counter,
+    /// Number of requests served by legacy
+    pub legacy: Counter,
+    /// Number of requests split across legacy and the local node
+    pub hybrid: Counter,
+    /// Number of try-local requests that fell back to legacy
+    pub fallbacks: Counter,
+    /// Histogram of legacy call latency, including retries
+    pub legacy_duration: Histogram,
+}
+
+/// Routing metrics by method, registered on first use
+static ROUTING_METRICS: LazyLock<DashMap<String, LegacyRoutingMetrics>> =
+    LazyLock::new(DashMap::new);
+
+impl LegacyRoutingMetrics {
+    /// Returns the metrics of `method`, which is a routed method or `batch`.
+    pub fn for_method(method: &str) -> Self {
+        cached_for_method(&ROUTING_METRICS, method, || {
+            Self::new_with_labels(&[("method", method.to_string())])
+        })
+    }
+}
+
+/// Hybrid `eth_getLogs` metrics
+#[derive(Metrics, Clone)]
+#[metrics(scope = "xlayer_legacy_rpc.get_logs")]
+pub struct LegacyGetLogsMetrics {
+    /// Histogram of chunks issued per hybrid query
+    pub hybrid_chunks: Histogram,
+    /// Histogram of logs returned per hybrid query
+    pub hybrid_merged_logs: Histogram,
+    /// Number of hybrid queries rejected for exceeding the result cap
+    pub hybrid_limit_exceeded: Counter,
+}
+
+static GET_LOGS_METRICS: LazyLock<LegacyGetLogsMetrics> =
+    LazyLock::new(LegacyGetLogsMetrics::default);
+
+impl LegacyGetLogsMetrics {
+    /// Returns the metrics shared by every hybrid query.
+    pub fn get() -> &'static Self {
+        &GET_LOGS_METRICS
+    }
+}
+
+/// Per-method shadow mode metrics, labelled by method
+#[derive(Metrics, Clone)]
+#[metrics(scope = "xlayer_legacy_rpc.shadow")]
+pub struct LegacyShadowMetrics {
+    /// Number of requests sent to both legacy and the local node
+    pub requests: Counter,
+    /// Number of requests whose legacy and local results differ
+    pub mismatches: Counter,
+    /// Number of requests that failed on either side
+    pub errors: Counter,
+    /// Number of requests not compared since too many were in flight or the
+    /// legacy rate limit was reached
+    pub dropped: Counter,
+}
+
+/// Shadow metrics by method, registered on first use
+static SHADOW_METRICS: LazyLock<DashMap<String, LegacyShadowMetrics>> = LazyLock::new(DashMap::new);
+
+impl LegacyShadowMetrics {
+    /// Returns the metrics of the shadowed `method`.
+    pub fn for_method(method: &str) -> Self {
+        cached_for_method(&SHADOW_METRICS, method, || {
+            Self::new_with_labels(&[("method", method.to_string())])
+        })
+    }
+}
+
+/// Returns the metrics labelled by `method` from `cache`, so the recorder is
+/// only hit once per method rather than on every request.
+fn cached_for_method<M: Clone>(
+    cache: &DashMap<String, M>,
+    method: &str,
+    new: impl FnOnce() -> M,
+) -> M {
+    if let Some(metrics) = cache.get(method) {
+        return metrics.clone();
+    }
+    cache.entry(method.to_string()).or_insert_with(new).clone()
+}
+
+/// Where a routable request is served from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteDecision {
+    Local,
+    Legacy,
+    Hybrid,
+}
+
+/// Records the routing decision for a request of `method`.
+pub fn record_route(method: &str, decision: RouteDecision) {
+    debug!(target:"xlayer_legacy_rpc", method, ?decision, "Legacy routing decision");
+
+    let metrics = LegacyRoutingMetrics::for_method(method);
+    match decision {
+        RouteDecision::Local => metrics.local.increment(1),
+        RouteDecision::Legacy => metrics.legacy.increment(1),
+        RouteDecision::Hybrid => metrics.hybrid.increment(1),
+    }
+}
+
+/// Records a try-local request of `method` falling back to legacy.
+pub fn record_fallback(method: &str) {
+    debug!(target:"xlayer_legacy_rpc", method, "Legacy routing fallback");
+    LegacyRoutingMetrics::for_method(method).fallbacks.increment(1);
+}
@@ -0,0 +1,98 @@
+//! Outbound call policy for the legacy endpoints.
+//!
+//! A token bucket bounds the rate of upstream calls, shedding load with a
+//! "limit exceeded" error rather than queueing behind a slow endpoint, and
+//! failed calls of idempotent methods are retried with exponential backoff.
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Upper bound of a single retry backoff
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Token bucket refilling at a fixed rate up to a burst capacity.
+pub struct TokenBucket {
+    rate_per_sec: f64,
+    burst: f64,
+    /// Available tokens and the time they were last refilled
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket. A zero burst defaults to one second worth of
+    /// tokens.
+    pub fn new(rate_per_sec: u32, burst: u32) -> Self {
+        let burst = if burst == 0 { rate_per_sec } else { burst }.max(1) as f64;
+        Self {
+            rate_per_sec: rate_per_sec as f64,
+            burst,
+            state: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    /// Takes a token if one is available.
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let (tokens, last_refill) = &mut *state;
+
+        let now = Instant::now();
+        let refill = now.duration_since(*last_refill).as_secs_f64() * self.rate_per_sec;
+        *tokens = (*tokens + refill).min(self.burst);
+        *last_refill = now;
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Retry policy for failed upstream calls.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt, 0 disables retrying
+    pub max_retries: u32,
+    /// Backoff before the first retry, doubled on every further retry
+    pub initial_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Backoff before retry number `attempt`, starting at 0.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff.saturating_mul(1 << attempt.min(16)).min(MAX_RETRY_BACKOFF)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_limits_burst() {
+        let bucket = TokenBucket::new(1, 3);
+        assert!(bucket.try_acquire());
+        assert!(bucket.try_acquire());
+        assert!(bucket.try_acquire());
+        assert!(!bucket.try_acquire());
+    }
+
+    #[test]
+    fn test_token_bucket_refills() {
+        let bucket = TokenBucket::new(1000, 1);
+        assert!(bucket.try_acquire());
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(bucket.try_acquire());
+    }
+
+    #[test]
+    fn test_retry_backoff_doubles_and_caps() {
+        let policy = RetryPolicy { max_retries: 10, initial_backoff: Duration::from_millis(100) };
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(800));
+        assert_eq!(policy.backoff(10), MAX_RETRY_BACKOFF);
+    }
+}
@@ -0,0 +1,223 @@
+//! Per-method routing table of the legacy router.
+//!
+//! The built-in table covers the standard `eth_`, `debug_` and `trace_`
+//! methods. A policy file in TOML or JSON (by its `.json` extension) may
+//! override any of them or add custom methods, without rebuilding the node:
+//!
+//! ```toml
+//! # Routed by the block number or hash at params[1]
+//! [methods.vendor_getBalanceAt]
+//! route = "block"
+//! param = 1
+//! block_hash = true
+//!
+//! # Addressed by a hash, served locally first and by legacy if not found
+//! [methods.vendor_getReceipt]
+//! route = "legacy"
+//! try_local_first = true
+//!
+//! # Never routed to legacy
+//! [methods.eth_feeHistory]
+//! route = "local"
+//! ```
+use std::{collections::HashMap, path::Path};
+
+use serde::Deserialize;
+
+/// How a method is routed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RouteType {
+    /// Routed to legacy when the block param is below the cutoff
+    Block,
+    /// Routed to legacy, after the local node if `try_local_first` is set
+    Legacy,
+    /// Never routed to legacy
+    Local,
+    /// Routed by a dedicated handler, only available to built-in methods
+    #[serde(skip)]
+    Handler,
+}
+
+/// Routing of a single method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MethodRoute {
+    pub route: RouteType,
+    /// Index of the block number or hash in the params, for `block` routes
+    #[serde(default)]
+    pub param: usize,
+    /// Whether the block param may be a block hash, for `block` routes
+    #[serde(default)]
+    pub block_hash: bool,
+    /// Whether the local node is tried first, falling back to legacy on an
+    /// error or empty result, for `legacy` routes
+    #[serde(default)]
+    pub try_local_first: bool,
+}
+
+impl MethodRoute {
+    const fn block(param: usize, block_hash: bool) -> Self {
+        Self { route: RouteType::Block, param, block_hash, try_local_first: false }
+    }
+
+    const fn local_then_legacy() -> Self {
+        Self { route: RouteType::Legacy, param: 0, block_hash: false, try_local_first: true }
+    }
+
+    const fn handler() -> Self {
+        Self { route: RouteType::Handler, param: 0, block_hash: false, try_local_first: false }
+    }
+}
+
+/// Built-in routes of the standard methods.
+const BUILTIN_ROUTES: &[(&str, MethodRoute)] = &[
+    // Addressed by a block number
+    ("eth_getBlockByNumber", MethodRoute::block(0, false)),
+    ("eth_getBlockTransactionCountByNumber", MethodRoute::block(0, false)),
+    ("eth_getHeaderByNumber", MethodRoute::block(0, false)),
+    ("eth_getTransactionByBlockNumberAndIndex", MethodRoute::block(0, false)),
+    ("eth_getRawTransactionByBlockNumberAndIndex", MethodRoute::block(0, false)),
+    ("debug_traceBlockByNumber", MethodRoute::block(0, false)),
+    ("trace_block", MethodRoute::block(0, false)),
+    // Addressed by a block number or hash
+    ("eth_getBlockReceipts", MethodRoute::block(0, true)),
+    ("eth_getBalance", MethodRoute::block(1, true)),
+    ("eth_getCode", MethodRoute::block(1, true)),
+    ("eth_getTransactionCount", MethodRoute::block(1, true)),
+    ("eth_call", MethodRoute::block(1, true)),
+    ("eth_estimateGas", MethodRoute::block(1, true)),
+    ("eth_createAccessList", MethodRoute::block(1, true)),
+    ("debug_traceCall", MethodRoute::block(1, true)),
+    ("eth_getStorageAt", MethodRoute::block(2, true)),
+    // Addressed by a block or transaction hash
+    ("eth_getTransactionByHash", MethodRoute::local_then_legacy()),
+    ("eth_getTransactionReceipt", MethodRoute::local_then_legacy()),
+    ("eth_getRawTransactionByHash", MethodRoute::local_then_legacy()),
+    ("eth_getBlockByHash", MethodRoute::local_then_legacy()),
+    ("eth_getHeaderByHash", MethodRoute::local_then_legacy()),
+    ("eth_getBlockTransactionCountByHash", MethodRoute::local_then_legacy()),
+    ("eth_getTransactionByBlockHashAndIndex", MethodRoute::local_then_legacy()),
+    ("eth_getRawTransactionByBlockHashAndIndex", MethodRoute::local_then_legacy()),
+    ("debug_traceTransaction", MethodRoute::local_then_legacy()),
+    ("debug_traceBlockByHash", MethodRoute::local_then_legacy()),
+    ("trace_transaction", MethodRoute::local_then_legacy()),
+    // Dedicated handlers
+    ("eth_getLogs", MethodRoute::handler()),
+    ("eth_newFilter", MethodRoute::handler()),
+    ("eth_getFilterLogs", MethodRoute::handler()),
+    ("eth_getFilterChanges", MethodRoute::handler()),
+    ("eth_uninstallFilter", MethodRoute::handler()),
+    ("eth_feeHistory", MethodRoute::handler()),
+    ("trace_filter", MethodRoute::handler()),
+];
+
+/// Policy file layout.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RoutingPolicyFile {
+    #[serde(default)]
+    methods: HashMap<String, MethodRoute>,
+}
+
+/// Routing table of all methods considered for legacy routing.
+#[derive(Debug, Clone)]
+pub struct RoutingPolicy {
+    methods: HashMap<String, MethodRoute>,
+}
+
+impl Default for RoutingPolicy {
+    fn default() -> Self {
+        let methods =
+            BUILTIN_ROUTES.iter().map(|(method, route)| (method.to_string(), *route)).collect();
+        Self { methods }
+    }
+}
+
+impl RoutingPolicy {
+    /// Loads a policy file, whose methods override the built-in routes.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read routing policy {}: {e}", path.display()))?;
+        let file: RoutingPolicyFile = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&contents).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&contents).map_err(|e| e.to_string())
+        }
+        .map_err(|e| format!("Invalid routing policy {}: {e}", path.display()))?;
+
+        let mut policy = Self::default();
+        policy.methods.extend(file.methods);
+        Ok(policy)
+    }
+
+    /// Returns the route of a method, `None` if it is never routed to legacy.
+    pub fn route(&self, method: &str) -> Option<&MethodRoute> {
+        self.methods.get(method).filter(|route| route.route != RouteType::Local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_policy(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("xlayer-legacy-routing-test-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_builtin_routes() {
+        let policy = RoutingPolicy::default();
+        assert_eq!(policy.route("eth_getStorageAt"), Some(&MethodRoute::block(2, true)));
+        assert_eq!(policy.route("eth_getLogs").map(|r| r.route), Some(RouteType::Handler));
+        assert_eq!(policy.route("eth_getFilterChanges").map(|r| r.route), Some(RouteType::Handler));
+        assert!(policy.route("eth_getTransactionByHash").is_some_and(|r| r.try_local_first));
+        assert!(policy.route("eth_sendRawTransaction").is_none());
+    }
+
+    #[test]
+    fn test_toml_policy_overrides_builtin_routes() {
+        let path = write_policy(
+            "routing.toml",
+            r#"
+            [methods.vendor_getBalanceAt]
+            route = "block"
+            param = 1
+            block_hash = true
+
+            [methods.eth_feeHistory]
+            route = "local"
+            "#,
+        );
+        let policy = RoutingPolicy::from_file(&path).unwrap();
+        let _ = std::fs::remove_file(path);
+
+        assert_eq!(policy.route("vendor_getBalanceAt"), Some(&MethodRoute::block(1, true)));
+        assert!(policy.route("eth_feeHistory").is_none());
+        assert_eq!(policy.route("eth_getBalance"), Some(&MethodRoute::block(1, true)));
+    }
+
+    #[test]
+    fn test_json_policy() {
+        let path = write_policy(
+            "routing.json",
+            r#"{"methods": {"vendor_getReceipt": {"route": "legacy", "try_local_first": true}}}"#,
+        );
+        let policy = RoutingPolicy::from_file(&path).unwrap();
+        let _ = std::fs::remove_file(path);
+
+        assert_eq!(policy.route("vendor_getReceipt"), Some(&MethodRoute::local_then_legacy()));
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        let path = write_policy("invalid.toml", "[methods.vendor_x]\nroute = \"handler\"\n");
+        let result = RoutingPolicy::from_file(&path);
+        let _ = std::fs::remove_file(path);
+
+        assert!(result.unwrap_err().contains("Invalid routing policy"));
+    }
+}
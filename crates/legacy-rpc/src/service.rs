@@ -1,6 +1,6 @@
 use std::future::Future;
 
-use futures::{future::Either, stream::FuturesOrdered, StreamExt};
+use futures::{future::Either, stream::FuturesUnordered, StreamExt};
 use jsonrpsee::{
     core::middleware::{Batch, BatchEntry, Notification},
     server::middleware::rpc::RpcServiceT,
@@ -9,14 +9,21 @@ use jsonrpsee::{
 };
 use tracing::debug;
 
-use crate::LegacyRpcRouterService;
+use crate::{
+    metrics::{record_fallback, record_route, RouteDecision},
+    routes::{MethodRoute, RouteType},
+    LegacyRpcRouterService,
+};
 
-/// Only these methods should be considered for legacy routing.
+/// Methods that are safe to retry against legacy. Only reads are retried,
+/// so methods added by a routing policy aren't retried unless listed here.
 #[inline]
-pub fn is_legacy_routable(method: &str) -> bool {
+pub(crate) fn is_idempotent(method: &str) -> bool {
     matches!(
         method,
-        "eth_getBlockByNumber"
+        "eth_blockNumber"
+            | "eth_chainId"
+            | "eth_getBlockByNumber"
             | "eth_getBlockByHash"
             | "eth_getBlockTransactionCountByNumber"
             | "eth_getBlockTransactionCountByHash"
@@ -24,74 +31,29 @@ pub fn is_legacy_routable(method: &str) -> bool {
             | "eth_getHeaderByNumber"
             | "eth_getHeaderByHash"
             | "eth_getTransactionByHash"
-            | "eth_getTransactionReceipt"
-            | "eth_getTransactionByBlockHashAndIndex"
             | "eth_getTransactionByBlockNumberAndIndex"
+            | "eth_getTransactionByBlockHashAndIndex"
             | "eth_getRawTransactionByHash"
-            | "eth_getRawTransactionByBlockHashAndIndex"
             | "eth_getRawTransactionByBlockNumberAndIndex"
+            | "eth_getRawTransactionByBlockHashAndIndex"
+            | "eth_getTransactionReceipt"
             | "eth_getBalance"
             | "eth_getCode"
-            | "eth_getStorageAt"
             | "eth_getTransactionCount"
-            | "eth_call"
-            | "eth_estimateGas"
-            | "eth_createAccessList"
-            | "eth_getLogs"
-            | "debug_traceTransaction"
-    )
-}
-
-/// Takes block number/hash as param
-#[inline]
-fn need_parse_block(method: &str) -> bool {
-    matches!(
-        method,
-        "eth_getBlockByNumber"
-            | "eth_getBlockTransactionCountByNumber"
-            | "eth_getHeaderByNumber"
-            | "eth_getTransactionByBlockNumberAndIndex"
-            | "eth_getRawTransactionByBlockNumberAndIndex"
-            | "eth_getBlockReceipts"
-            | "eth_getBalance"
-            | "eth_getCode"
             | "eth_getStorageAt"
-            | "eth_getTransactionCount"
+            | "eth_getProof"
             | "eth_call"
             | "eth_estimateGas"
             | "eth_createAccessList"
-    )
-}
-
-/// Need to fetch block num from DB/API
-#[inline]
-fn can_use_block_hash_as_param(method: &str) -> bool {
-    matches!(
-        method,
-        "eth_getBlockReceipts"
-            | "eth_getBalance"
-            | "eth_getCode"
-            | "eth_getStorageAt"
-            | "eth_getTransactionCount"
-            | "eth_call"
-            | "eth_estimateGas"
-            | "eth_createAccessList"
-    )
-}
-
-#[inline]
-fn need_try_local_then_legacy(method: &str) -> bool {
-    matches!(
-        method,
-        "eth_getTransactionByHash"
-            | "eth_getTransactionReceipt"
-            | "eth_getRawTransactionByHash"
-            | "eth_getBlockByHash"
-            | "eth_getHeaderByHash"
-            | "eth_getBlockTransactionCountByHash"
-            | "eth_getTransactionByBlockHashAndIndex"
-            | "eth_getRawTransactionByBlockHashAndIndex"
+            | "eth_getLogs"
+            | "eth_feeHistory"
+            | "debug_traceCall"
             | "debug_traceTransaction"
+            | "debug_traceBlockByNumber"
+            | "debug_traceBlockByHash"
+            | "trace_block"
+            | "trace_transaction"
+            | "trace_filter"
     )
 }
 
@@ -114,32 +76,31 @@ pub(crate) fn is_result_empty(response: &MethodResponse) -> bool {
     false
 }
 
-/// Returns the block param index.
-///
-/// In eth requests, there is params list: [...].
-/// Looks at each method and decides block num/hash
-/// param position in that argument list.
-#[inline]
-fn block_param_pos(method: &str) -> usize {
-    // 2nd position (index 1)
-    if matches!(
-        method,
-        "eth_getBalance"
-            | "eth_getCode"
-            | "eth_getTransactionCount"
-            | "eth_call"
-            | "eth_estimateGas"
-            | "eth_createAccessList"
-    ) {
-        return 1;
-    }
+/// Returns true if the request can be routed to legacy without consulting
+/// local state, i.e. it is addressed by a block number below the cutoff.
+pub(crate) fn is_pure_legacy(req: &Request<'_>, route: &MethodRoute, cutoff_block: u64) -> bool {
+    let method = req.method_name();
+    let params_ref = req.params();
 
-    // 3rd position (index 2)
-    if matches!(method, "eth_getStorageAt") {
-        return 2;
+    match route.route {
+        RouteType::Legacy => !route.try_local_first,
+        RouteType::Block => params_ref
+            .as_str()
+            .and_then(|params| crate::parse_block_param(params, route.param))
+            .and_then(|block_param| block_param.parse::<u64>().ok())
+            .is_some_and(|block_num| block_num < cutoff_block),
+        RouteType::Handler => {
+            let Some(params) = params_ref.as_str() else {
+                return false;
+            };
+            match method {
+                "eth_getLogs" => crate::get_logs::is_pure_legacy_range(params, cutoff_block),
+                "eth_feeHistory" => crate::fee_history::is_pure_legacy_window(params, cutoff_block),
+                _ => false,
+            }
+        }
+        RouteType::Local => false,
     }
-
-    0
 }
 
 impl<S> RpcServiceT for LegacyRpcRouterService<S>
@@ -158,28 +119,29 @@ where
         let method = req.method_name();
 
         // Early return - no boxing, direct passthrough
-        if !self.config.enabled || !is_legacy_routable(method) {
-            return Either::Left(self.inner.call(req));
-        }
+        let route = match self.config.routing.route(method) {
+            Some(route) if self.config.enabled => *route,
+            _ => return Either::Left(self.inner.call(req)),
+        };
 
-        let client = self.client.clone();
-        let config = self.config.clone();
-        let inner = self.inner.clone();
+        let service = self.clone();
 
         Either::Right(Box::pin(async move {
             let method = req.method_name();
 
-            if method == "eth_getLogs" {
-                return crate::get_logs::handle_eth_get_logs(req, client, config, inner).await;
-            } else if need_try_local_then_legacy(method) {
-                return handle_try_local_then_legacy(req, client, config, inner).await;
-            } else if need_parse_block(method) {
-                return handle_block_param_methods(req, client, config, inner).await;
+            let shadow = &service.config.shadow;
+            if shadow.is_shadowed(method)
+                && crate::shadow::should_shadow(
+                    &req,
+                    &route,
+                    service.cutoff_block(),
+                    shadow.cutoff_window,
+                )
+            {
+                return crate::shadow::handle_shadow(req, service, route).await;
             }
 
-            debug!(target:"xlayer_legacy_rpc", "No legacy routing for method = {}", method);
-            // Default resorts to normal rpc calls.
-            inner.call(req).await
+            route_request(req, service, route).await
         }))
     }
 
@@ -192,34 +154,97 @@ where
         let service = self.clone();
 
         Either::Right(Box::pin(async move {
-            // Collect all entries first to avoid lifetime issues
-            let entries: Vec<_> = req.into_iter().collect();
+            let cutoff_block = service.cutoff_block();
+            let service_ref = &service;
 
-            // Process all requests concurrently using FuturesOrdered
-            // This significantly improves latency for batch requests with multiple calls
-            let mut futures: FuturesOrdered<_> = entries
-                .into_iter()
-                .filter_map(|entry| match entry {
-                    Ok(BatchEntry::Call(request)) => Some(Either::Right(service.call(request))),
+            // Slot per call entry so responses can be put back in the original order
+            let mut responses: Vec<Option<MethodResponse>> = Vec::new();
+            // Entries to send upstream together as a single legacy batch
+            let mut legacy = Vec::new();
+            let mut futures = FuturesUnordered::new();
+
+            for entry in req.into_iter() {
+                match entry {
+                    Ok(BatchEntry::Call(request)) => {
+                        let idx = responses.len();
+                        responses.push(None);
+
+                        // Shadowed requests go through `call` like local ones
+                        let method = request.method_name();
+                        let shadow = &service_ref.config.shadow;
+                        let route = service_ref.config.routing.route(method).filter(|route| {
+                            !(shadow.is_shadowed(method)
+                                && crate::shadow::should_shadow(
+                                    &request,
+                                    route,
+                                    cutoff_block,
+                                    shadow.cutoff_window,
+                                ))
+                        });
+                        if route.is_some_and(|route| is_pure_legacy(&request, route, cutoff_block))
+                        {
+                            record_route(request.method_name(), RouteDecision::Legacy);
+                            legacy.push((idx, request));
+                        } else if route.is_some_and(|route| {
+                            route.route == RouteType::Legacy && route.try_local_first
+                        }) {
+                            // Local first, falls back to the legacy batch on error or empty
+                            futures.push(Either::Left(async move {
+                                let res = service_ref.inner.call(request.clone()).await;
+                                (idx, Some(request), res)
+                            }));
+                        } else {
+                            futures.push(Either::Right(async move {
+                                (idx, None, service_ref.call(request).await)
+                            }));
+                        }
+                    }
                     Ok(BatchEntry::Notification(_notif)) => {
                         // Notifications should not be answered
                         // Note: we don't process notifications in batch context
-                        None
                     }
                     Err(_) => {
                         // Return error response for malformed entries
-                        Some(Either::Left(async {
-                            MethodResponse::error(
-                                Id::Null,
-                                ErrorObject::from(ErrorCode::InvalidRequest),
-                            )
-                        }))
+                        responses.push(Some(MethodResponse::error(
+                            Id::Null,
+                            ErrorObject::from(ErrorCode::InvalidRequest),
+                        )));
+                    }
+                }
+            }
+
+            // Process local requests concurrently
+            while let Some((idx, request, response)) = futures.next().await {
+                match request {
+                    Some(request) if response.is_error() || is_result_empty(&response) => {
+                        record_fallback(request.method_name());
+                        record_route(request.method_name(), RouteDecision::Legacy);
+                        legacy.push((idx, request));
                     }
-                })
-                .collect();
+                    Some(request) => {
+                        record_route(request.method_name(), RouteDecision::Local);
+                        responses[idx] = Some(response);
+                    }
+                    None => responses[idx] = Some(response),
+                }
+            }
+
+            if !legacy.is_empty() {
+                debug!(
+                    target:"xlayer_legacy_rpc",
+                    "Route {} batch entries to legacy in a single request",
+                    legacy.len()
+                );
+                let (indices, requests): (Vec<_>, Vec<_>) = legacy.into_iter().unzip();
+                let legacy_responses = service.forward_batch_to_legacy(requests).await;
+                for (idx, response) in indices.into_iter().zip(legacy_responses) {
+                    responses[idx] = Some(response);
+                }
+            }
 
-            let mut batch_response = BatchResponseBuilder::new_with_limit(usize::MAX);
-            while let Some(response) = futures.next().await {
+            let mut batch_response =
+                BatchResponseBuilder::new_with_limit(service.max_response_size());
+            for response in responses.into_iter().flatten() {
                 if let Err(err) = batch_response.append(response) {
                     return err;
                 }
@@ -237,37 +262,88 @@ where
     }
 }
 
+/// Routes a request of a routed method, without shadowing it.
+pub(crate) async fn route_request<S>(
+    req: Request<'_>,
+    service: LegacyRpcRouterService<S>,
+    route: MethodRoute,
+) -> MethodResponse
+where
+    S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
+{
+    let method = req.method_name();
+    match (route.route, method) {
+        (RouteType::Handler, "eth_getLogs") => {
+            return crate::get_logs::handle_eth_get_logs(req, service).await;
+        }
+        (RouteType::Handler, "eth_newFilter") => {
+            return crate::filter::handle_eth_new_filter(req, service).await;
+        }
+        (RouteType::Handler, "eth_getFilterLogs") => {
+            return crate::filter::handle_eth_get_filter_logs(req, service).await;
+        }
+        (RouteType::Handler, "eth_getFilterChanges") => {
+            return crate::filter::handle_eth_get_filter_changes(req, service).await;
+        }
+        (RouteType::Handler, "eth_uninstallFilter") => {
+            return crate::filter::handle_eth_uninstall_filter(req, service).await;
+        }
+        (RouteType::Handler, "eth_feeHistory") => {
+            return crate::fee_history::handle_eth_fee_history(req, service).await;
+        }
+        (RouteType::Handler, "trace_filter") => {
+            return crate::trace::handle_trace_filter(req, service).await;
+        }
+        (RouteType::Legacy, _) if route.try_local_first => {
+            return handle_try_local_then_legacy(req, service).await;
+        }
+        (RouteType::Legacy, _) => {
+            debug!(target:"xlayer_legacy_rpc", "Route to legacy for method = {}", method);
+            record_route(method, RouteDecision::Legacy);
+            return service.forward_to_legacy(req).await;
+        }
+        (RouteType::Block, _) => {
+            return handle_block_param_methods(req, service, route).await;
+        }
+        _ => {}
+    }
+
+    debug!(target:"xlayer_legacy_rpc", "No legacy routing for method = {}", method);
+    record_route(method, RouteDecision::Local);
+    // Default resorts to normal rpc calls.
+    service.inner.call(req).await
+}
+
 async fn handle_try_local_then_legacy<S>(
     req: Request<'_>,
-    client: reqwest::Client,
-    config: std::sync::Arc<crate::LegacyRpcRouterConfig>,
-    inner: S,
+    service: LegacyRpcRouterService<S>,
 ) -> MethodResponse
 where
     S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
 {
     let method = req.method_name();
-    let res = inner.call(req.clone()).await;
+    let res = service.inner.call(req.clone()).await;
     if res.is_error() || (res.is_success() && is_result_empty(&res)) {
-        let service = LegacyRpcRouterService { inner: inner.clone(), config, client };
         debug!(
             target:"xlayer_legacy_rpc",
             "Route to legacy for method = {method}. is_error = {}, is_empty_result = {}",
             res.is_error(),
             res.is_success()
         );
+        record_fallback(method);
+        record_route(method, RouteDecision::Legacy);
         service.forward_to_legacy(req).await
     } else {
         debug!(target:"xlayer_legacy_rpc", "No legacy routing(local success with data) for method = {method}");
+        record_route(method, RouteDecision::Local);
         res
     }
 }
 
 async fn handle_block_param_methods<S>(
     req: Request<'_>,
-    client: reqwest::Client,
-    config: std::sync::Arc<crate::LegacyRpcRouterConfig>,
-    inner: S,
+    service: LegacyRpcRouterService<S>,
+    route: MethodRoute,
 ) -> MethodResponse
 where
     S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
@@ -280,30 +356,40 @@ where
         );
     };
     let method = req.method_name();
-    let block_param = crate::parse_block_param(params, block_param_pos(method));
+    let block_param = crate::parse_block_param(params, route.param);
 
-    let cutoff_block = config.cutoff_block;
+    let cutoff_block = service.cutoff_block();
     if let Some(block_param) = block_param {
-        let service = LegacyRpcRouterService { inner: inner.clone(), config, client };
-        if can_use_block_hash_as_param(method) && crate::is_valid_32_bytes_string(&block_param) {
+        if route.block_hash && crate::is_valid_32_bytes_string(&block_param) {
             let res = service.call_eth_get_block_by_hash(&block_param, false).await;
             match res {
-                Ok(n) => {
-                    if n.is_none() {
-                        debug!(target:"xlayer_legacy_rpc", "Route to legacy for method (block by hash not found) = {}", method);
-                        return service.forward_to_legacy(req).await;
-                    } else {
-                        // TODO: if block_num parsed from blk hash is smaller than
-                        // cutoff, route to legacy as well?
-                        debug!(
-                            target:"xlayer_legacy_rpc",
-                            "No route to legacy since got block num from block hash. block = {:?}",
-                            n
-                        );
-                    }
+                Ok(None) => {
+                    debug!(target:"xlayer_legacy_rpc", "Route to legacy for method (block by hash not found) = {}", method);
+                    record_route(method, RouteDecision::Legacy);
+                    return service.forward_to_legacy(req).await;
+                }
+                Ok(Some(block_num)) if block_num < cutoff_block => {
+                    // The original params (including any EIP-1898 `requireCanonical`)
+                    // are forwarded unchanged, so legacy enforces canonicality itself.
+                    debug!(
+                        target:"xlayer_legacy_rpc",
+                        "Route to legacy for method (block hash below cutoff) = {}, block = {}",
+                        method,
+                        block_num
+                    );
+                    record_route(method, RouteDecision::Legacy);
+                    return service.forward_to_legacy(req).await;
+                }
+                Ok(Some(block_num)) => {
+                    debug!(
+                        target:"xlayer_legacy_rpc",
+                        "No route to legacy since block hash resolves above cutoff. block = {}",
+                        block_num
+                    );
                 }
                 Err(err) => {
                     debug!(target:"xlayer_legacy_rpc", "Error getting block by hash = {err:?}, forwarding to legacy");
+                    record_route(method, RouteDecision::Legacy);
                     return service.forward_to_legacy(req).await;
                 }
             }
@@ -313,6 +399,7 @@ where
                     debug!(target:"xlayer_legacy_rpc", "block_num = {}", block_num);
                     if block_num < cutoff_block {
                         debug!(target:"xlayer_legacy_rpc", "Route to legacy for method (below cuttoff) = {}", method);
+                        record_route(method, RouteDecision::Legacy);
                         return service.forward_to_legacy(req).await;
                     }
                 }
@@ -326,5 +413,6 @@ where
     }
 
     debug!(target:"xlayer_legacy_rpc", "No legacy routing for method = {}", method);
-    inner.call(req).await
+    record_route(method, RouteDecision::Local);
+    service.inner.call(req).await
 }
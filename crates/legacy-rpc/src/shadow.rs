@@ -0,0 +1,265 @@
+//! Shadow mode comparing legacy and local responses.
+//!
+//! Requests of the shadowed methods are sent to both legacy and the local
+//! node. Only requests the router may send to legacy are shadowed, along
+//! with blocks just above the cutoff, since the other side has nothing to
+//! compare against. The caller gets the response the router would have
+//! returned, while the call to the other side and the comparison run in a
+//! background task, so shadowing never adds latency. Differing results are
+//! recorded as mismatch metrics and logged with both responses, which allows
+//! checking a migrated database against the old chain.
+//!
+//! Comparisons in flight are bounded, and requests arriving while the bound
+//! is reached skip the comparison. A shadow call to legacy takes a token from
+//! the rate limit of the routed traffic without waiting for it, and skips the
+//! comparison when none is left. Shadow results are never cached.
+use std::collections::HashSet;
+
+use jsonrpsee::{server::middleware::rpc::RpcServiceT, MethodResponse};
+use jsonrpsee_types::Request;
+use tracing::{debug, warn};
+
+use crate::{
+    metrics::LegacyShadowMetrics,
+    routes::{MethodRoute, RouteType},
+    service::{is_pure_legacy, route_request},
+    LegacyRpcRouterService,
+};
+
+/// Max length of the params and responses logged on a mismatch
+const MAX_LOGGED_LEN: usize = 512;
+
+/// Side a shadowed request is sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShadowSide {
+    Local,
+    Legacy,
+}
+
+/// Shadow mode settings.
+#[derive(Debug, Clone)]
+pub struct ShadowConfig {
+    /// Methods sent to both sides, empty disables shadow mode
+    pub methods: HashSet<String>,
+    /// Blocks at or above the cutoff that are still shadowed
+    pub cutoff_window: u64,
+    /// Max comparisons in flight, further requests skip the comparison
+    pub max_in_flight: usize,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self { methods: HashSet::new(), cutoff_window: 1000, max_in_flight: 64 }
+    }
+}
+
+impl ShadowConfig {
+    pub fn is_shadowed(&self, method: &str) -> bool {
+        self.methods.contains(method)
+    }
+}
+
+/// Returns true if a request of a shadowed method is worth comparing: the
+/// router may send it to legacy, or it addresses blocks just above the cutoff.
+pub(crate) fn should_shadow(
+    req: &Request<'_>,
+    route: &MethodRoute,
+    cutoff_block: u64,
+    cutoff_window: u64,
+) -> bool {
+    match route.route {
+        RouteType::Legacy => true,
+        _ => is_pure_legacy(req, route, cutoff_block.saturating_add(cutoff_window)),
+    }
+}
+
+/// Result of comparing the two responses of a shadowed request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShadowOutcome {
+    Match,
+    Mismatch,
+    /// Either side failed, so the results can't be compared
+    Error,
+}
+
+/// Compares the results of two JSON-RPC responses, ignoring their ids.
+fn compare_responses(local: &str, legacy: &str) -> ShadowOutcome {
+    let result = |json: &str| {
+        serde_json::from_str::<serde_json::Value>(json)
+            .ok()
+            .and_then(|mut json| json.get_mut("result").map(serde_json::Value::take))
+    };
+
+    match (result(local), result(legacy)) {
+        (Some(local), Some(legacy)) if local == legacy => ShadowOutcome::Match,
+        (Some(_), Some(_)) => ShadowOutcome::Mismatch,
+        _ => ShadowOutcome::Error,
+    }
+}
+
+fn truncate(s: &str) -> &str {
+    match s.char_indices().nth(MAX_LOGGED_LEN) {
+        Some((idx, _)) => &s[..idx],
+        None => s,
+    }
+}
+
+/// Handle a shadowed request.
+pub(crate) async fn handle_shadow<S>(
+    req: Request<'_>,
+    service: LegacyRpcRouterService<S>,
+    route: MethodRoute,
+) -> MethodResponse
+where
+    S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
+{
+    // Requests the router sends to legacy are compared against local, and
+    // local or hybrid ones against legacy
+    let other = if is_pure_legacy(&req, &route, service.cutoff_block()) {
+        ShadowSide::Local
+    } else {
+        ShadowSide::Legacy
+    };
+
+    let Ok(permit) = service.shadow_permits.clone().try_acquire_owned() else {
+        return skip_comparison(req, service, route, "Too many shadow requests in flight").await;
+    };
+    if other == ShadowSide::Legacy
+        && let Some(limiter) = &service.limiter
+        && !limiter.try_acquire()
+    {
+        return skip_comparison(req, service, route, "Legacy rate limit reached").await;
+    }
+    let shadow_req = req.clone().into_owned();
+
+    let res = route_request(req, service.clone(), route).await;
+    let routed_json = res.as_json().get().to_string();
+
+    // The rate limit token is already taken
+    let service = LegacyRpcRouterService { limiter: None, cache: None, ..service };
+    tokio::spawn(async move {
+        let _permit = permit;
+        let method = shadow_req.method_name().to_string();
+        let method = method.as_str();
+        let params = shadow_req.params().as_str().map(|params| truncate(params).to_string());
+        let params = params.as_deref();
+
+        let other_res = call_side(&service, other, shadow_req).await;
+        let other_json = other_res.as_json().get();
+        let (local, legacy) = match other {
+            ShadowSide::Legacy => (routed_json.as_str(), other_json),
+            ShadowSide::Local => (other_json, routed_json.as_str()),
+        };
+
+        let metrics = LegacyShadowMetrics::for_method(method);
+        metrics.requests.increment(1);
+        match compare_responses(local, legacy) {
+            ShadowOutcome::Match => {
+                debug!(target:"xlayer_legacy_rpc", method, "Shadow responses match");
+            }
+            ShadowOutcome::Mismatch => {
+                metrics.mismatches.increment(1);
+                warn!(
+                    target:"xlayer_legacy_rpc",
+                    method,
+                    params,
+                    local = truncate(local),
+                    legacy = truncate(legacy),
+                    "Shadow response mismatch"
+                );
+            }
+            ShadowOutcome::Error => {
+                metrics.errors.increment(1);
+                debug!(
+                    target:"xlayer_legacy_rpc",
+                    method,
+                    params,
+                    local = truncate(local),
+                    legacy = truncate(legacy),
+                    "Shadow responses not comparable"
+                );
+            }
+        }
+    });
+
+    res
+}
+
+/// Routes a shadowed request without comparing it.
+async fn skip_comparison<S>(
+    req: Request<'_>,
+    service: LegacyRpcRouterService<S>,
+    route: MethodRoute,
+    reason: &'static str,
+) -> MethodResponse
+where
+    S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
+{
+    let method = req.method_name();
+    LegacyShadowMetrics::for_method(method).dropped.increment(1);
+    debug!(target:"xlayer_legacy_rpc", method, "{reason}, skipping shadow comparison");
+    route_request(req, service, route).await
+}
+
+/// Sends the request to one side.
+async fn call_side<S>(
+    service: &LegacyRpcRouterService<S>,
+    side: ShadowSide,
+    req: Request<'_>,
+) -> MethodResponse
+where
+    S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
+{
+    match side {
+        ShadowSide::Local => service.inner.call(req).await,
+        ShadowSide::Legacy => service.forward_to_legacy(req).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::RoutingPolicy;
+    use jsonrpsee_types::Id;
+    use serde_json::value::RawValue;
+
+    #[test]
+    fn test_compare_responses() {
+        let local = r#"{"jsonrpc":"2.0","id":1,"result":{"number":"0x10","hash":"0xab"}}"#;
+        let legacy = r#"{"jsonrpc":"2.0","id":"x","result":{"hash":"0xab","number":"0x10"}}"#;
+        assert_eq!(compare_responses(local, legacy), ShadowOutcome::Match);
+
+        let legacy = r#"{"jsonrpc":"2.0","id":1,"result":{"number":"0x10","hash":"0xcd"}}"#;
+        assert_eq!(compare_responses(local, legacy), ShadowOutcome::Mismatch);
+
+        let legacy = r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"boom"}}"#;
+        assert_eq!(compare_responses(local, legacy), ShadowOutcome::Error);
+    }
+
+    #[test]
+    fn test_should_shadow_near_cutoff() {
+        let routing = RoutingPolicy::default();
+        let route = routing.route("eth_getBlockByNumber").unwrap();
+        let request = |block: &str| {
+            let params = RawValue::from_string(format!(r#"["{block}",false]"#)).unwrap();
+            Request::owned("eth_getBlockByNumber".to_string(), Some(params), Id::Number(1))
+        };
+
+        // Below the cutoff and within the window above it
+        assert!(should_shadow(&request("0x3e7"), route, 1000, 100));
+        assert!(should_shadow(&request("0x44b"), route, 1000, 100));
+        // Past the window, or resolved locally
+        assert!(!should_shadow(&request("0x44c"), route, 1000, 100));
+        assert!(!should_shadow(&request("latest"), route, 1000, 100));
+        // Legacy routed methods are always shadowed
+        let route = routing.route("eth_getTransactionByHash").unwrap();
+        assert!(should_shadow(&request("0x1"), route, 1000, 0));
+    }
+
+    #[test]
+    fn test_truncate() {
+        let long = "a".repeat(MAX_LOGGED_LEN + 10);
+        assert_eq!(truncate(&long).len(), MAX_LOGGED_LEN);
+        assert_eq!(truncate("short"), "short");
+    }
+}
@@ -0,0 +1,311 @@
+//! Handles logic for deciding how to route for `trace_filter`.
+//!
+//! Routing follows `eth_getLogs` (see [`crate::get_logs`]) with a few
+//! differences in the filter semantics:
+//!
+//! from_block: missing
+//!     Defaults to 0 (genesis), not latest
+//! to_block: missing
+//!     Defaults to latest, converted to u64::MAX
+//!
+//! For a hybrid range the legacy part `[from, cutoff - 1]` and the local part
+//! `[cutoff, to]` are fetched without `after`, and with `after + count` as
+//! their `count` since neither side can contribute more to the page. Since
+//! every legacy trace precedes every local trace, the results are
+//! concatenated in order and the pagination is applied to the merged list,
+//! which skips the legacy traces before paging into the local ones.
+use jsonrpsee::{
+    server::middleware::rpc::RpcServiceT,
+    types::{error::INVALID_PARAMS_CODE, ErrorObject},
+    MethodResponse,
+};
+use jsonrpsee_types::{Id, Request};
+use serde_json::value::RawValue;
+use tracing::debug;
+
+use crate::{
+    metrics::{record_route, RouteDecision},
+    LegacyRpcRouterService,
+};
+
+/// Parsed block range and pagination of a `trace_filter` request.
+#[derive(Debug, Eq, PartialEq)]
+struct TraceFilterParams {
+    from_block: u64,
+    to_block: u64,
+    after: usize,
+    count: Option<usize>,
+}
+
+/// Parse a `trace_filter` block number, which is a hex quantity or a tag.
+#[inline]
+fn parse_trace_block(value: &serde_json::Value) -> Option<u64> {
+    match value.as_str()? {
+        "latest" | "pending" | "safe" | "finalized" => Some(u64::MAX),
+        "earliest" => Some(0),
+        hex if hex.starts_with("0x") => u64::from_str_radix(&hex[2..], 16).ok(),
+        _ => None,
+    }
+}
+
+/// Parse `trace_filter` params into a block range and pagination.
+#[inline]
+fn parse_trace_filter_params(params: &str) -> Option<TraceFilterParams> {
+    let parsed: serde_json::Value = serde_json::from_str(params).ok()?;
+    let filter = parsed.as_array()?.first()?.as_object()?;
+
+    let from_block = match filter.get("fromBlock") {
+        Some(value) => parse_trace_block(value)?,
+        None => 0,
+    };
+    let to_block = match filter.get("toBlock") {
+        Some(value) => parse_trace_block(value)?,
+        None => u64::MAX,
+    };
+    if from_block > to_block {
+        return None;
+    }
+
+    let after = filter.get("after").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+    let count = filter.get("count").and_then(|v| v.as_u64()).map(|c| c as usize);
+
+    Some(TraceFilterParams { from_block, to_block, after, count })
+}
+
+/// Build a `trace_filter` request for `[from_block, to_block]` returning at
+/// most `count` traces, without `after`. A `None` to_block keeps the original
+/// `toBlock`.
+fn modify_trace_filter_params<'a>(
+    original_req: &Request<'a>,
+    from_block: u64,
+    to_block: Option<u64>,
+    count: Option<usize>,
+) -> Option<Request<'a>> {
+    let params_ref = original_req.params();
+    let mut parsed: serde_json::Value = serde_json::from_str(params_ref.as_str()?).ok()?;
+    let filter = parsed.as_array_mut()?.get_mut(0)?.as_object_mut()?;
+
+    filter.insert("fromBlock".to_string(), serde_json::Value::String(format!("0x{from_block:x}")));
+    if let Some(to) = to_block {
+        filter.insert("toBlock".to_string(), serde_json::Value::String(format!("0x{to:x}")));
+    }
+    filter.remove("after");
+    match count {
+        Some(count) => filter.insert("count".to_string(), count.into()),
+        None => filter.remove("count"),
+    };
+
+    let params_raw = RawValue::from_string(serde_json::to_string(&parsed).ok()?).ok()?;
+    Some(Request::owned(
+        original_req.method_name().to_string(),
+        Some(params_raw),
+        original_req.id(),
+    ))
+}
+
+/// Concatenate legacy and local traces and apply `after`/`count`.
+fn merge_trace_filter_responses(
+    legacy_response: MethodResponse,
+    local_response: MethodResponse,
+    request_id: Id,
+    after: usize,
+    count: Option<usize>,
+    max_response_size: usize,
+) -> MethodResponse {
+    let mut traces = Vec::new();
+    for response in [legacy_response, local_response] {
+        if response.is_error() {
+            return response;
+        }
+        let parsed: serde_json::Value = match serde_json::from_str(response.as_json().get()) {
+            Ok(v) => v,
+            Err(_) => return response,
+        };
+        if let Some(serde_json::Value::Array(result)) = parsed.get("result") {
+            traces.extend(result.iter().cloned());
+        }
+    }
+
+    let traces: Vec<_> = traces.into_iter().skip(after).take(count.unwrap_or(usize::MAX)).collect();
+    let merged_result = serde_json::Value::Array(traces);
+    let payload = jsonrpsee_types::ResponsePayload::success(&merged_result).into();
+
+    MethodResponse::response(request_id, payload, max_response_size)
+}
+
+/// Handle trace_filter routing logic.
+pub(crate) async fn handle_trace_filter<S>(
+    req: Request<'_>,
+    service: LegacyRpcRouterService<S>,
+) -> MethodResponse
+where
+    S: RpcServiceT<MethodResponse = MethodResponse> + Send + Sync + Clone + 'static,
+{
+    let params_ref = req.params();
+    let Some(params) = params_ref.as_str() else {
+        return MethodResponse::error(
+            req.id(),
+            ErrorObject::owned(INVALID_PARAMS_CODE, "Missing required params", None::<()>),
+        );
+    };
+
+    let cutoff_block = service.cutoff_block();
+    let Some(filter) = parse_trace_filter_params(params) else {
+        // If parsing fails, use normal routing
+        record_route("trace_filter", RouteDecision::Local);
+        return service.inner.call(req).await;
+    };
+
+    if filter.to_block < cutoff_block {
+        debug!(
+            target:"xlayer_legacy_rpc",
+            "trace_filter pure legacy routing (from_block = {}, to_block = {})",
+            filter.from_block, filter.to_block
+        );
+        record_route("trace_filter", RouteDecision::Legacy);
+        return service.forward_to_legacy(req).await;
+    } else if filter.from_block >= cutoff_block {
+        debug!(
+            target:"xlayer_legacy_rpc",
+            "trace_filter pure local routing (from_block = {}, to_block = {})",
+            filter.from_block, filter.to_block
+        );
+        record_route("trace_filter", RouteDecision::Local);
+        return service.inner.call(req).await;
+    }
+
+    // Neither side contributes more than the first `after + count` traces
+    let window = filter.count.map(|count| filter.after.saturating_add(count));
+    let legacy_req =
+        modify_trace_filter_params(&req, filter.from_block, Some(cutoff_block - 1), window);
+    let local_to_block = (filter.to_block != u64::MAX).then_some(filter.to_block);
+    let local_req = modify_trace_filter_params(&req, cutoff_block, local_to_block, window);
+
+    if let (Some(legacy_req), Some(local_req)) = (legacy_req, local_req) {
+        debug!(
+            target:"xlayer_legacy_rpc",
+            "trace_filter hybrid routing (from_block = {}, {}) and ({}, to_block = {})",
+            filter.from_block,
+            cutoff_block - 1,
+            cutoff_block,
+            filter.to_block
+        );
+
+        record_route("trace_filter", RouteDecision::Hybrid);
+        let (legacy_response, local_response) =
+            tokio::join!(async { service.forward_to_legacy(legacy_req).await }, async {
+                service.inner.call(local_req).await
+            });
+
+        return merge_trace_filter_responses(
+            legacy_response,
+            local_response,
+            req.id(),
+            filter.after,
+            filter.count,
+            service.max_response_size(),
+        );
+    }
+
+    debug!(target:"xlayer_legacy_rpc", "No legacy routing for method = trace_filter");
+    record_route("trace_filter", RouteDecision::Local);
+    service.inner.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn traces_response(traces: serde_json::Value) -> MethodResponse {
+        let payload = jsonrpsee_types::ResponsePayload::success(&traces).into();
+        MethodResponse::response(Id::Number(1), payload, usize::MAX)
+    }
+
+    #[test]
+    fn test_parse_trace_filter_params() {
+        let cases = [
+            (
+                r#"[{"fromBlock":"0x1","toBlock":"0x64"}]"#,
+                Some(TraceFilterParams { from_block: 1, to_block: 100, after: 0, count: None }),
+            ),
+            (
+                r#"[{"toBlock":"0x64","after":10,"count":5}]"#,
+                Some(TraceFilterParams { from_block: 0, to_block: 100, after: 10, count: Some(5) }),
+            ),
+            (
+                r#"[{"fromBlock":"0x64"}]"#,
+                Some(TraceFilterParams {
+                    from_block: 100,
+                    to_block: u64::MAX,
+                    after: 0,
+                    count: None,
+                }),
+            ),
+            (r#"[{"fromBlock":"0x64","toBlock":"0x1"}]"#, None),
+            (r#"[{"fromBlock":"bogus"}]"#, None),
+            (r#"[]"#, None),
+        ];
+
+        for (params, expected) in cases {
+            assert_eq!(parse_trace_filter_params(params), expected);
+        }
+    }
+
+    #[test]
+    fn test_modify_trace_filter_params_strips_pagination() {
+        let params = r#"[{"fromBlock":"0x1","toBlock":"0x64","after":3,"count":2}]"#;
+        let params_raw = RawValue::from_string(params.to_string()).unwrap();
+        let request = Request::owned("trace_filter".to_string(), Some(params_raw), Id::Number(1));
+
+        let modified = modify_trace_filter_params(&request, 10, Some(20), None).unwrap();
+        let modified_params = modified.params();
+        let parsed: serde_json::Value =
+            serde_json::from_str(modified_params.as_str().unwrap()).unwrap();
+        let filter = &parsed[0];
+
+        assert_eq!(filter["fromBlock"], "0xa");
+        assert_eq!(filter["toBlock"], "0x14");
+        assert!(filter.get("after").is_none());
+        assert!(filter.get("count").is_none());
+
+        // The page window is forwarded as the count of each side
+        let modified = modify_trace_filter_params(&request, 10, None, Some(5)).unwrap();
+        let modified_params = modified.params();
+        let parsed: serde_json::Value =
+            serde_json::from_str(modified_params.as_str().unwrap()).unwrap();
+        let filter = &parsed[0];
+
+        assert_eq!(filter["toBlock"], "0x64");
+        assert!(filter.get("after").is_none());
+        assert_eq!(filter["count"], 5);
+    }
+
+    #[test]
+    fn test_merge_trace_filter_responses_paginates() {
+        let legacy = traces_response(serde_json::json!([{"blockNumber": 1}, {"blockNumber": 2}]));
+        let local = traces_response(serde_json::json!([{"blockNumber": 3}, {"blockNumber": 4}]));
+
+        let merged =
+            merge_trace_filter_responses(legacy, local, Id::Number(1), 1, Some(2), usize::MAX);
+        let parsed: serde_json::Value = serde_json::from_str(merged.as_json().get()).unwrap();
+        assert_eq!(parsed["result"], serde_json::json!([{"blockNumber": 2}, {"blockNumber": 3}]));
+    }
+
+    #[test]
+    fn test_merge_trace_filter_responses_pages_into_local() {
+        // after = 3, count = 2: each side returns at most 5 traces
+        let legacy = traces_response(serde_json::json!([{"blockNumber": 1}, {"blockNumber": 2}]));
+        let local = traces_response(serde_json::json!([
+            {"blockNumber": 3},
+            {"blockNumber": 4},
+            {"blockNumber": 5},
+            {"blockNumber": 6},
+            {"blockNumber": 7}
+        ]));
+
+        let merged =
+            merge_trace_filter_responses(legacy, local, Id::Number(1), 3, Some(2), usize::MAX);
+        let parsed: serde_json::Value = serde_json::from_str(merged.as_json().get()).unwrap();
+        assert_eq!(parsed["result"], serde_json::json!([{"blockNumber": 4}, {"blockNumber": 5}]));
+    }
+}
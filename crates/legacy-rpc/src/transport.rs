@@ -0,0 +1,977 @@
+//! Transports used to reach the legacy endpoints.
+//!
+//! The transport is chosen by the endpoint URL scheme:
+//!
+//! - `http://` and `https://` post every request with [`reqwest`].
+//! - `ws://` and `wss://` keep a persistent WebSocket connection.
+//! - `ipc://<path>` keeps a persistent Unix socket connection.
+//!
+//! Auth headers are sent on every HTTP request and on the WebSocket
+//! handshake. TLS client certificates only apply to HTTP.
+//!
+//! HTTP 429 and 5xx statuses, and other error statuses without a JSON body,
+//! make the endpoint unavailable so the next one is tried.
+//!
+//! Responses are returned as raw JSON and are rejected once they exceed the
+//! configured max response size. HTTP bodies are read in chunks, so an
+//! oversized response is dropped without being buffered in full. Messages on
+//! persistent connections are never parsed in full: only their ids are read,
+//! to hand them to the waiting request or to fail the request an oversized
+//! message answers. An oversized message without an id closes the
+//! connection. IPC messages are not framed, so the stream is split by an
+//! incremental scanner which also skips oversized messages without
+//! buffering them.
+//!
+//! Persistent connections are opened lazily and reopened on the next request
+//! after they drop. Many requests share one connection: request ids are
+//! rewritten to connection-unique ids on the way out and restored on the
+//! way back, so concurrent callers never see each other's responses. Only
+//! the bytes of the ids are rewritten in responses.
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use reqwest::{header::HeaderMap, Client, StatusCode};
+use serde::{Deserialize, Deserializer};
+use serde_json::value::RawValue;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message};
+use tracing::debug;
+
+/// Failure to get a response from a legacy endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportError {
+    /// The endpoint is unreachable or failed, the next endpoint may be tried
+    Unavailable(String),
+    /// The endpoint answered with something that is not JSON
+    InvalidResponse(String),
+    /// The response exceeds the max response size, carried in bytes
+    ResponseTooLarge(usize),
+}
+
+/// JSON-RPC response object borrowing its fields from the raw response, so
+/// the result is passed on without being parsed.
+#[derive(Debug, Deserialize)]
+pub(crate) struct LegacyResponse<'a> {
+    #[serde(borrow, default, deserialize_with = "present")]
+    pub id: Option<&'a RawValue>,
+    /// Set if the field is present, including a `null` result
+    #[serde(borrow, default, deserialize_with = "present")]
+    pub result: Option<&'a RawValue>,
+    #[serde(default)]
+    pub error: Option<serde_json::Value>,
+}
+
+impl<'a> LegacyResponse<'a> {
+    /// Parses a single response object.
+    pub fn parse(raw: &'a RawValue) -> serde_json::Result<Self> {
+        serde_json::from_str(raw.get())
+    }
+
+    /// Parses a batch of response objects.
+    pub fn parse_batch(raw: &'a RawValue) -> serde_json::Result<Vec<Self>> {
+        serde_json::from_str(raw.get())
+    }
+}
+
+/// Deserializes a field that is present as `Some`, unlike the `Option`
+/// impl which maps `null` to `None`.
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<&'de RawValue>, D::Error> {
+    <&RawValue>::deserialize(deserializer).map(Some)
+}
+
+/// Id of a response entry, borrowed from the message while the rest of the
+/// entry is skipped.
+#[derive(Debug, Deserialize)]
+struct ResponseId<'a> {
+    #[serde(borrow, default)]
+    id: Option<&'a RawValue>,
+}
+
+/// Borrows the ids of a response or batch without building the whole value.
+fn response_ids(message: &str) -> serde_json::Result<Vec<&RawValue>> {
+    let entries = if message.trim_start().starts_with('[') {
+        serde_json::from_str::<Vec<ResponseId<'_>>>(message)?
+    } else {
+        vec![serde_json::from_str::<ResponseId<'_>>(message)?]
+    };
+    Ok(entries.into_iter().filter_map(|entry| entry.id).collect())
+}
+
+/// Reads the connection-unique ids of a response or batch.
+fn connection_ids(message: &str) -> serde_json::Result<Vec<u64>> {
+    Ok(response_ids(message)?.into_iter().filter_map(|id| id.get().parse().ok()).collect())
+}
+
+/// A connection to a single legacy endpoint.
+#[async_trait]
+pub trait LegacyTransport: Send + Sync {
+    /// Sends a JSON-RPC request or batch and returns the raw response.
+    async fn send(&self, body: &serde_json::Value) -> Result<Box<RawValue>, TransportError>;
+}
+
+/// Settings shared by the transports of all endpoints.
+#[derive(Clone, Debug, Default)]
+pub struct TransportConfig {
+    /// Client used by the HTTP transport, carrying auth and timeout
+    pub client: Client,
+    /// Headers sent on the WebSocket handshake
+    pub headers: HeaderMap,
+    /// Timeout of a single request on persistent connections, zero is none
+    pub timeout: Duration,
+    /// Max size of a response in bytes, zero is unlimited
+    pub max_response_size: usize,
+}
+
+impl TransportConfig {
+    fn max_response_size(&self) -> usize {
+        if self.max_response_size == 0 {
+            usize::MAX
+        } else {
+            self.max_response_size
+        }
+    }
+}
+
+/// Creates the transport matching the scheme of `url`. Unknown schemes use
+/// HTTP, which reports the error on the first request.
+pub fn new_transport(url: &str, config: &TransportConfig) -> Box<dyn LegacyTransport> {
+    match url.split_once("://") {
+        Some(("ws" | "wss", _)) => Box::new(PersistentTransport::new(
+            Endpoint::Ws { url: url.to_string(), headers: config.headers.clone() },
+            config,
+        )),
+        Some(("ipc", path)) => {
+            Box::new(PersistentTransport::new(Endpoint::Ipc { path: path.to_string() }, config))
+        }
+        _ => Box::new(HttpTransport {
+            client: config.client.clone(),
+            url: url.to_string(),
+            max_response_size: config.max_response_size(),
+        }),
+    }
+}
+
+/// Posts each request over HTTP.
+struct HttpTransport {
+    client: Client,
+    url: String,
+    max_response_size: usize,
+}
+
+#[async_trait]
+impl LegacyTransport for HttpTransport {
+    async fn send(&self, body: &serde_json::Value) -> Result<Box<RawValue>, TransportError> {
+        let mut response = self
+            .client
+            .post(&self.url)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| TransportError::Unavailable(e.without_url().to_string()))?;
+
+        // Rate limited and failing endpoints are skipped rather than answered
+        let status = response.status();
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(TransportError::Unavailable(format!("status {status}")));
+        }
+
+        let too_large = TransportError::ResponseTooLarge(self.max_response_size);
+        if response.content_length().is_some_and(|len| len > self.max_response_size as u64) {
+            return Err(too_large);
+        }
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| TransportError::Unavailable(e.without_url().to_string()))?
+        {
+            if bytes.len() + chunk.len() > self.max_response_size {
+                return Err(too_large);
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        // Other error statuses are only passed on with a JSON-RPC body
+        let invalid = |e: String| match status.is_success() {
+            true => TransportError::InvalidResponse(e),
+            false => TransportError::Unavailable(format!("status {status}")),
+        };
+        let json = String::from_utf8(bytes).map_err(|e| invalid(e.to_string()))?;
+        RawValue::from_string(json).map_err(|e| invalid(e.to_string()))
+    }
+}
+
+/// Address of a persistent connection.
+enum Endpoint {
+    Ws { url: String, headers: HeaderMap },
+    Ipc { path: String },
+}
+
+/// Multiplexes requests over a lazily opened persistent connection.
+struct PersistentTransport {
+    endpoint: Endpoint,
+    timeout: Duration,
+    max_response_size: usize,
+    connection: tokio::sync::Mutex<Option<Arc<Connection>>>,
+}
+
+impl PersistentTransport {
+    fn new(endpoint: Endpoint, config: &TransportConfig) -> Self {
+        Self {
+            endpoint,
+            timeout: config.timeout,
+            max_response_size: config.max_response_size(),
+            connection: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Returns the open connection, reconnecting if it dropped.
+    async fn connection(&self) -> Result<Arc<Connection>, TransportError> {
+        let mut connection = self.connection.lock().await;
+        if let Some(conn) = connection.as_ref()
+            && !conn.is_closed()
+        {
+            return Ok(conn.clone());
+        }
+
+        let connect = self.connect();
+        let conn = if self.timeout.is_zero() {
+            connect.await?
+        } else {
+            tokio::time::timeout(self.timeout, connect)
+                .await
+                .map_err(|_| TransportError::Unavailable("connect timed out".to_string()))??
+        };
+        *connection = Some(conn.clone());
+        Ok(conn)
+    }
+
+    async fn connect(&self) -> Result<Arc<Connection>, TransportError> {
+        match &self.endpoint {
+            Endpoint::Ws { url, headers } => connect_ws(url, headers, self.max_response_size).await,
+            Endpoint::Ipc { path } => connect_ipc(path, self.max_response_size).await,
+        }
+    }
+}
+
+impl Drop for PersistentTransport {
+    fn drop(&mut self) {
+        if let Some(conn) = self.connection.get_mut().take() {
+            conn.close();
+        }
+    }
+}
+
+#[async_trait]
+impl LegacyTransport for PersistentTransport {
+    async fn send(&self, body: &serde_json::Value) -> Result<Box<RawValue>, TransportError> {
+        let raw = self.connection().await?.request(body, self.timeout).await?;
+        if raw.get().len() > self.max_response_size {
+            return Err(TransportError::ResponseTooLarge(self.max_response_size));
+        }
+        Ok(raw)
+    }
+}
+
+/// Raw response handed to a waiting request
+type PendingResponse = Result<String, TransportError>;
+
+/// Requests awaiting a response, keyed by connection-unique id
+type Pending = Mutex<HashMap<u64, oneshot::Sender<PendingResponse>>>;
+
+/// Shared state of a persistent connection. Outgoing messages are written by
+/// a writer task and incoming messages are dispatched by a reader task. Both
+/// tasks stop once the connection is closed by either of them, dropping
+/// their half of the socket.
+struct Connection {
+    outgoing: mpsc::UnboundedSender<String>,
+    pending: Pending,
+    next_id: AtomicU64,
+    closed: watch::Sender<bool>,
+    max_response_size: usize,
+}
+
+impl Connection {
+    fn new(outgoing: mpsc::UnboundedSender<String>, max_response_size: usize) -> Self {
+        Self {
+            outgoing,
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            closed: watch::Sender::new(false),
+            max_response_size,
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        *self.closed.borrow() || self.outgoing.is_closed()
+    }
+
+    /// Resolves once the connection is closed.
+    fn closed(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut closed = self.closed.subscribe();
+        async move {
+            let _ = closed.wait_for(|closed| *closed).await;
+        }
+    }
+
+    /// Marks the connection closed and fails every pending request.
+    fn close(&self) {
+        self.closed.send_replace(true);
+        self.pending.lock().unwrap().clear();
+    }
+
+    /// Marks the connection closed and fails every pending request with
+    /// `error`.
+    fn close_with(&self, error: TransportError) {
+        self.closed.send_replace(true);
+        for (_, sender) in self.pending.lock().unwrap().drain() {
+            let _ = sender.send(Err(error.clone()));
+        }
+    }
+
+    async fn request(
+        &self,
+        body: &serde_json::Value,
+        timeout: Duration,
+    ) -> Result<Box<RawValue>, TransportError> {
+        let mut body = body.clone();
+        let original_ids = self.tag_ids(&mut body);
+        let Some(&key) = original_ids.keys().min() else {
+            return Err(TransportError::InvalidResponse("request without an id".to_string()));
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(key, tx);
+
+        let unavailable = || TransportError::Unavailable("connection closed".to_string());
+        if self.outgoing.send(body.to_string()).is_err() {
+            self.pending.lock().unwrap().remove(&key);
+            return Err(unavailable());
+        }
+
+        let response = if timeout.is_zero() {
+            rx.await.map_err(|_| unavailable())??
+        } else {
+            match tokio::time::timeout(timeout, rx).await {
+                Ok(response) => response.map_err(|_| unavailable())??,
+                Err(_) => {
+                    self.pending.lock().unwrap().remove(&key);
+                    return Err(TransportError::Unavailable("request timed out".to_string()));
+                }
+            }
+        };
+
+        restore_ids(response, &original_ids)
+            .map_err(|e| TransportError::InvalidResponse(e.to_string()))
+    }
+
+    /// Replaces the ids of the request (or of every batch entry) with
+    /// connection-unique ids, returning the originals by new id.
+    fn tag_ids(&self, body: &mut serde_json::Value) -> HashMap<u64, serde_json::Value> {
+        let mut original_ids = HashMap::new();
+        let mut tag = |entry: &mut serde_json::Value| {
+            if let Some(obj) = entry.as_object_mut() {
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                let original = obj.insert("id".to_string(), id.into());
+                original_ids.insert(id, original.unwrap_or(serde_json::Value::Null));
+            }
+        };
+
+        match body {
+            serde_json::Value::Array(entries) => entries.iter_mut().for_each(&mut tag),
+            entry => tag(entry),
+        }
+        original_ids
+    }
+
+    /// Handles an incoming message, which is handed on as is. Batch
+    /// responses are matched by any of their entry ids. A message above the
+    /// max response size fails the request it answers.
+    fn receive(&self, message: &[u8]) -> serde_json::Result<()> {
+        let message = std::str::from_utf8(message).map_err(serde::de::Error::custom)?;
+        if message.len() > self.max_response_size {
+            self.fail_oversized(&connection_ids(message).unwrap_or_default());
+            return Ok(());
+        }
+        self.resolve(&connection_ids(message)?, Ok(message.to_string()));
+        Ok(())
+    }
+
+    /// Fails the request answered by an oversized message. Without an id the
+    /// request can't be told, so the connection is closed instead.
+    fn fail_oversized(&self, ids: &[u64]) {
+        debug!(target:"xlayer_legacy_rpc", "Legacy message exceeds the max response size");
+        if ids.is_empty() {
+            self.close_with(TransportError::Unavailable(
+                "oversized legacy message without an id".to_string(),
+            ));
+        } else {
+            self.resolve(ids, Err(TransportError::ResponseTooLarge(self.max_response_size)));
+        }
+    }
+
+    /// Hands a response to the request waiting for any of `ids`.
+    fn resolve(&self, ids: &[u64], response: PendingResponse) {
+        let sender = {
+            let mut pending = self.pending.lock().unwrap();
+            ids.iter().find_map(|id| pending.remove(id))
+        };
+        match sender {
+            Some(sender) => {
+                let _ = sender.send(response);
+            }
+            // Subscription notifications or responses to timed out requests
+            None => debug!(target:"xlayer_legacy_rpc", "Dropping unmatched legacy message"),
+        }
+    }
+}
+
+/// Puts the original ids back into a response, rewriting only the bytes of
+/// the ids.
+fn restore_ids(
+    response: String,
+    original_ids: &HashMap<u64, serde_json::Value>,
+) -> serde_json::Result<Box<RawValue>> {
+    let mut restored = String::with_capacity(response.len());
+    let mut copied = 0;
+    for id in response_ids(&response)? {
+        let Some(original) = id.get().parse::<u64>().ok().and_then(|id| original_ids.get(&id))
+        else {
+            continue;
+        };
+        // The id is borrowed from the response, entries come in order
+        let start = id.get().as_ptr() as usize - response.as_ptr() as usize;
+        restored.push_str(&response[copied..start]);
+        restored.push_str(&original.to_string());
+        copied = start + id.get().len();
+    }
+    if copied == 0 {
+        return RawValue::from_string(response);
+    }
+    restored.push_str(&response[copied..]);
+    RawValue::from_string(restored)
+}
+
+/// Splits the unframed IPC stream into messages, scanning every byte once.
+/// The top-level ids of a message are picked up on the way, so an oversized
+/// message can be skipped without being buffered and still fail the request
+/// it answers.
+#[derive(Debug, Default)]
+struct IpcScanner {
+    depth: usize,
+    batch: bool,
+    in_string: bool,
+    escaped: bool,
+    /// Last string at entry level, which may be a key
+    token: Vec<u8>,
+    /// Set after a string at entry level until the next token
+    after_string: bool,
+    /// Digits of an `id` value being read, if any
+    id: Option<Vec<u8>>,
+    ids: Vec<u64>,
+}
+
+impl IpcScanner {
+    /// Max length of the tokens kept, enough for `id` and any u64
+    const MAX_TOKEN_LEN: usize = 20;
+
+    /// Scans one byte, returning true if it ends a message.
+    fn push(&mut self, byte: u8) -> bool {
+        // Nesting level of the objects carrying the ids
+        let entry_depth = if self.batch { 2 } else { 1 };
+        let at_entry = self.depth == entry_depth;
+
+        if self.in_string {
+            if self.escaped {
+                self.escaped = false;
+            } else if byte == b'\\' {
+                self.escaped = true;
+            } else if byte == b'"' {
+                self.in_string = false;
+                self.after_string = at_entry;
+                return false;
+            }
+            if at_entry && self.token.len() < Self::MAX_TOKEN_LEN {
+                self.token.push(byte);
+            }
+            return false;
+        }
+
+        if byte.is_ascii_whitespace() {
+            return false;
+        }
+        if at_entry && let Some(id) = &mut self.id {
+            if byte.is_ascii_digit() && id.len() < Self::MAX_TOKEN_LEN {
+                id.push(byte);
+                return false;
+            }
+            // Ids of other requests, like strings or null, are not ours
+            if let Some(id) = std::str::from_utf8(id).ok().and_then(|id| id.parse().ok()) {
+                self.ids.push(id);
+            }
+            self.id = None;
+        }
+
+        let is_id_key = std::mem::take(&mut self.after_string) && self.token == b"id";
+        match byte {
+            b'"' => {
+                self.in_string = true;
+                self.token.clear();
+            }
+            b':' if at_entry && is_id_key => self.id = Some(Vec::new()),
+            b'{' | b'[' => {
+                if self.depth == 0 {
+                    self.batch = byte == b'[';
+                }
+                self.depth += 1;
+            }
+            b'}' | b']' if self.depth > 0 => {
+                self.depth -= 1;
+                return self.depth == 0;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// Returns the ids read since the last call.
+    fn take_ids(&mut self) -> Vec<u64> {
+        std::mem::take(&mut self.ids)
+    }
+}
+
+async fn connect_ws(
+    url: &str,
+    headers: &HeaderMap,
+    max_response_size: usize,
+) -> Result<Arc<Connection>, TransportError> {
+    let mut request = url
+        .into_client_request()
+        .map_err(|e| TransportError::Unavailable(format!("invalid ws url: {e}")))?;
+    request.headers_mut().extend(headers.clone());
+
+    let (ws, _) = tokio_tungstenite::connect_async(request)
+        .await
+        .map_err(|e| TransportError::Unavailable(e.to_string()))?;
+    let (mut sink, mut stream) = ws.split();
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let conn = Arc::new(Connection::new(tx, max_response_size));
+
+    let writer = conn.clone();
+    let closed = conn.closed();
+    tokio::spawn(async move {
+        tokio::pin!(closed);
+        while let Some(message) = tokio::select! {
+            message = rx.recv() => message,
+            _ = &mut closed => None,
+        } {
+            if sink.send(Message::text(message)).await.is_err() {
+                break;
+            }
+        }
+        writer.close();
+    });
+
+    let reader = conn.clone();
+    let closed = conn.closed();
+    tokio::spawn(async move {
+        tokio::pin!(closed);
+        while let Some(Ok(message)) = tokio::select! {
+            message = stream.next() => message,
+            _ = &mut closed => None,
+        } {
+            let received = match message {
+                Message::Text(text) => reader.receive(text.as_str().as_bytes()),
+                Message::Binary(bytes) => reader.receive(&bytes),
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            if let Err(e) = received {
+                debug!(target:"xlayer_legacy_rpc", "Invalid legacy ws message, err = {e}")
+            }
+        }
+        debug!(target:"xlayer_legacy_rpc", "Legacy ws connection closed");
+        reader.close();
+    });
+
+    Ok(conn)
+}
+
+#[cfg(unix)]
+async fn connect_ipc(
+    path: &str,
+    max_response_size: usize,
+) -> Result<Arc<Connection>, TransportError> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let socket = tokio::net::UnixStream::connect(path)
+        .await
+        .map_err(|e| TransportError::Unavailable(e.to_string()))?;
+    let (mut read_half, mut write_half) = socket.into_split();
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let conn = Arc::new(Connection::new(tx, max_response_size));
+
+    let writer = conn.clone();
+    let closed = conn.closed();
+    tokio::spawn(async move {
+        tokio::pin!(closed);
+        while let Some(message) = tokio::select! {
+            message = rx.recv() => message,
+            _ = &mut closed => None,
+        } {
+            if write_half.write_all(message.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+        writer.close();
+    });
+
+    let reader = conn.clone();
+    let closed = conn.closed();
+    tokio::spawn(async move {
+        tokio::pin!(closed);
+        // Messages are concatenated JSON values without framing. Once the
+        // message being read outgrows the limit, the rest of it is skipped
+        let mut buf = Vec::new();
+        let mut chunk = vec![0u8; 64 * 1024];
+        let mut scanner = IpcScanner::default();
+        let mut skipping = false;
+        let mut failed = false;
+        loop {
+            let read = tokio::select! {
+                read = read_half.read(&mut chunk) => read,
+                _ = &mut closed => break,
+            };
+            let chunk = match read {
+                Ok(0) | Err(_) => break,
+                Ok(n) => &chunk[..n],
+            };
+
+            let mut start = 0;
+            for (idx, &byte) in chunk.iter().enumerate() {
+                if !scanner.push(byte) {
+                    continue;
+                }
+                let ids = scanner.take_ids();
+                if skipping {
+                    if !failed || !ids.is_empty() {
+                        reader.fail_oversized(&ids);
+                    }
+                    skipping = false;
+                } else {
+                    buf.extend_from_slice(&chunk[start..=idx]);
+                    if let Err(e) = reader.receive(&buf) {
+                        debug!(target:"xlayer_legacy_rpc", "Invalid legacy ipc message, err = {e}");
+                    }
+                    buf.clear();
+                }
+                start = idx + 1;
+            }
+
+            if !skipping {
+                buf.extend_from_slice(&chunk[start..]);
+                skipping = buf.len() > max_response_size;
+                failed = false;
+                if skipping {
+                    buf = Vec::new();
+                }
+            }
+            // Fail the request as soon as its id is read
+            if skipping {
+                let ids = scanner.take_ids();
+                if !ids.is_empty() {
+                    reader.fail_oversized(&ids);
+                    failed = true;
+                }
+            }
+        }
+        debug!(target:"xlayer_legacy_rpc", "Legacy ipc connection closed");
+        reader.close();
+    });
+
+    Ok(conn)
+}
+
+#[cfg(not(unix))]
+async fn connect_ipc(
+    _path: &str,
+    _max_response_size: usize,
+) -> Result<Arc<Connection>, TransportError> {
+    Err(TransportError::Unavailable("ipc is only supported on unix".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_and_restore_ids() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let conn = Connection::new(tx, usize::MAX);
+
+        let mut body = serde_json::json!([
+            {"jsonrpc": "2.0", "method": "eth_chainId", "id": "a"},
+            {"jsonrpc": "2.0", "method": "eth_chainId", "id": 7},
+        ]);
+        let original_ids = conn.tag_ids(&mut body);
+        assert_eq!(body[0]["id"], 1);
+        assert_eq!(body[1]["id"], 2);
+
+        let response = r#"[{"jsonrpc":"2.0","result":{"id":1},"id":2},{"id":1,"result":"0xc4"}]"#;
+        let restored = restore_ids(response.to_string(), &original_ids).unwrap();
+        assert_eq!(
+            restored.get(),
+            r#"[{"jsonrpc":"2.0","result":{"id":1},"id":7},{"id":"a","result":"0xc4"}]"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_matches_batch_by_any_id() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let conn = Arc::new(Connection::new(tx, usize::MAX));
+
+        let body = serde_json::json!([
+            {"jsonrpc": "2.0", "method": "eth_chainId", "id": 1},
+            {"jsonrpc": "2.0", "method": "eth_blockNumber", "id": 2},
+        ]);
+        let request = {
+            let conn = conn.clone();
+            tokio::spawn(async move { conn.request(&body, Duration::from_secs(5)).await })
+        };
+
+        // Wait until the request is registered, then answer out of order
+        while conn.pending.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        conn.receive(br#"[{"jsonrpc":"2.0","result":"0x10","id":2},{"jsonrpc":"2.0","result":"0xc4","id":1}]"#)
+            .unwrap();
+
+        let response = request.await.unwrap().unwrap();
+        let response: serde_json::Value = serde_json::from_str(response.get()).unwrap();
+        assert_eq!(response[0]["id"], 2);
+        assert_eq!(response[1]["id"], 1);
+    }
+
+    #[test]
+    fn test_connection_ids() {
+        let batch = r#" [{"id":2,"result":[1,2]},{"id":1,"error":{"code":1}}]"#;
+        assert_eq!(connection_ids(batch).unwrap(), vec![2, 1]);
+        // Nested ids are skipped with the rest of the result
+        assert_eq!(connection_ids(r#"{"result":{"id":"a"},"id":7}"#).unwrap(), vec![7]);
+        assert!(connection_ids(r#"{"method":"eth_subscription"}"#).unwrap().is_empty());
+        assert!(connection_ids(r#"{"id":"x","result":null}"#).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ipc_scanner() {
+        let stream = concat!(
+            r#"{"id":1,"result":"}{\"}"} "#,
+            r#"[{"result":{"id":9},"id":2},{"id":3,"error":{"code":1}}]"#,
+            "\n",
+            r#"{"result":"0x1","id":"x"}{"result":[],"id": 18446744073709551615 }"#,
+        );
+        let mut scanner = IpcScanner::default();
+        let mut messages = Vec::new();
+        let mut start = 0;
+        // Bytes are fed one at a time, as if every read returned one byte
+        for (idx, &byte) in stream.as_bytes().iter().enumerate() {
+            if scanner.push(byte) {
+                messages.push((stream[start..=idx].trim().to_string(), scanner.take_ids()));
+                start = idx + 1;
+            }
+        }
+
+        let ids: Vec<_> = messages.iter().map(|(_, ids)| ids.clone()).collect();
+        assert_eq!(ids, vec![vec![1], vec![2, 3], vec![], vec![u64::MAX]]);
+        for (message, _) in &messages {
+            assert!(serde_json::from_str::<serde_json::Value>(message).is_ok(), "{message}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oversized_message_fails_its_request() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let conn = Arc::new(Connection::new(tx, 32));
+
+        let body = serde_json::json!({"jsonrpc": "2.0", "method": "eth_chainId", "id": "x"});
+        let request = {
+            let conn = conn.clone();
+            tokio::spawn(async move { conn.request(&body, Duration::from_secs(5)).await })
+        };
+        while conn.pending.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        conn.receive(br#"{"jsonrpc":"2.0","id":1,"result":"0x000000000000000000000000"}"#).unwrap();
+        assert_eq!(request.await.unwrap().unwrap_err(), TransportError::ResponseTooLarge(32));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_ipc_transport_caps_read_buffer() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let path =
+            std::env::temp_dir().join(format!("xlayer-legacy-ipc-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = socket.read(&mut request).await;
+            // A message larger than the limit, which never completes
+            let response = format!(r#"{{"jsonrpc":"2.0","id":1,"result":"{}"#, "0".repeat(1024));
+            let _ = socket.write_all(response.as_bytes()).await;
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+
+        let config = TransportConfig {
+            timeout: Duration::from_secs(5),
+            max_response_size: 256,
+            ..Default::default()
+        };
+        let transport = new_transport(&format!("ipc://{}", path.display()), &config);
+        let body =
+            serde_json::json!({"jsonrpc": "2.0", "method": "eth_chainId", "params": [], "id": "x"});
+        assert_eq!(transport.send(&body).await.unwrap_err(), TransportError::ResponseTooLarge(256));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_ipc_transport_drops_closed_connections() {
+        use tokio::io::AsyncReadExt;
+
+        let path = std::env::temp_dir()
+            .join(format!("xlayer-legacy-ipc-reconnect-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let (eof_tx, mut eof_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let (mut read_half, write_half) = socket.into_split();
+                let eof_tx = eof_tx.clone();
+                tokio::spawn(async move {
+                    let mut request = [0u8; 1024];
+                    let _ = read_half.read(&mut request).await;
+                    // Hang up, the client has to drop its write half in turn
+                    drop(write_half);
+                    while read_half.read(&mut request).await.is_ok_and(|n| n > 0) {}
+                    let _ = eof_tx.send(());
+                });
+            }
+        });
+
+        let config = TransportConfig { timeout: Duration::from_secs(5), ..Default::default() };
+        let transport = new_transport(&format!("ipc://{}", path.display()), &config);
+        let body =
+            serde_json::json!({"jsonrpc": "2.0", "method": "eth_chainId", "params": [], "id": "x"});
+        for _ in 0..3 {
+            assert!(matches!(transport.send(&body).await, Err(TransportError::Unavailable(_))));
+        }
+        for _ in 0..3 {
+            tokio::time::timeout(Duration::from_secs(5), eof_rx.recv()).await.unwrap().unwrap();
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_legacy_response_borrows_raw_result() {
+        let raw = RawValue::from_string(
+            r#"{"jsonrpc":"2.0","id":7,"result":{"number": "0x1"}}"#.to_string(),
+        )
+        .unwrap();
+        let response = LegacyResponse::parse(&raw).unwrap();
+        assert_eq!(response.id.unwrap().get(), "7");
+        assert_eq!(response.result.unwrap().get(), r#"{"number": "0x1"}"#);
+
+        // A null result is present, unlike a missing one
+        let raw = RawValue::from_string(r#"{"id":1,"result":null}"#.to_string()).unwrap();
+        assert_eq!(LegacyResponse::parse(&raw).unwrap().result.unwrap().get(), "null");
+        let raw = RawValue::from_string(r#"{"id":1,"error":{"code":1}}"#.to_string()).unwrap();
+        let response = LegacyResponse::parse(&raw).unwrap();
+        assert!(response.result.is_none());
+        assert_eq!(response.error.unwrap()["code"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_http_transport_error_statuses() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        async fn send_with_response(status: &str, body: &str) -> Result<String, TransportError> {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let response = format!(
+                "HTTP/1.1 {status}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            tokio::spawn(async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 1024];
+                let _ = socket.read(&mut request).await;
+                let _ = socket.write_all(response.as_bytes()).await;
+            });
+
+            let config = TransportConfig { timeout: Duration::from_secs(5), ..Default::default() };
+            let body = serde_json::json!({"jsonrpc": "2.0", "method": "eth_chainId", "id": 1});
+            new_transport(&url, &config).send(&body).await.map(|raw| raw.get().to_string())
+        }
+
+        let error = r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32600,"message":"bad"}}"#;
+        assert_eq!(send_with_response("400 Bad Request", error).await.unwrap(), error);
+        assert!(matches!(
+            send_with_response("429 Too Many Requests", "slow down").await,
+            Err(TransportError::Unavailable(_))
+        ));
+        assert!(matches!(
+            send_with_response("429 Too Many Requests", error).await,
+            Err(TransportError::Unavailable(_))
+        ));
+        assert!(matches!(
+            send_with_response("403 Forbidden", "forbidden").await,
+            Err(TransportError::Unavailable(_))
+        ));
+        assert!(matches!(
+            send_with_response("200 OK", "not json").await,
+            Err(TransportError::InvalidResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ws_transport_round_trip() {
+        let mut module = jsonrpsee::RpcModule::new(());
+        module.register_method("eth_chainId", |_, _, _| "0xc4".to_string()).unwrap();
+        let server = jsonrpsee::server::Server::builder().build("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", server.local_addr().unwrap());
+        let _handle = server.start(module);
+
+        let transport = new_transport(&url, &TransportConfig::default());
+        let body =
+            serde_json::json!({"jsonrpc": "2.0", "method": "eth_chainId", "params": [], "id": "x"});
+        let response = transport.send(&body).await.unwrap();
+        let response: serde_json::Value = serde_json::from_str(response.get()).unwrap();
+        assert_eq!(response["id"], "x");
+        assert_eq!(response["result"], "0xc4");
+
+        // The connection is reused for the next request
+        let response = transport.send(&body).await.unwrap();
+        assert!(response.get().contains(r#""result":"0xc4""#));
+
+        // Responses above the limit are rejected
+        let config = TransportConfig { max_response_size: 16, ..Default::default() };
+        let transport = new_transport(&url, &config);
+        assert_eq!(transport.send(&body).await.unwrap_err(), TransportError::ResponseTooLarge(16));
+    }
+}
@@ -4,7 +4,9 @@
 //! Run all tests (including ignored): `cargo test -p xlayer-e2e-test --test flashblocks_tests -- --include-ignored --nocapture --test-threads=1`
 //! Run a specific test: `cargo test -p xlayer-e2e-test --test flashblocks_tests -- <test_case_name> --include-ignored --nocapture --test-threads=1`
 
+use alloy_network::TransactionBuilder;
 use alloy_primitives::{hex, Address, U256};
+use alloy_rpc_types_eth::TransactionRequest;
 use alloy_sol_types::{sol, SolCall};
 use eyre::Result;
 use futures_util::StreamExt;
@@ -1110,6 +1112,427 @@ async fn fb_eth_subscribe_empty_params_test() -> Result<()> {
     Ok(())
 }
 
+#[ignore = "Requires flashblocks WebSocket server with flashblocks subscription support"]
+#[tokio::test]
+async fn fb_eth_subscribe_logs_test() -> Result<()> {
+    let ws_url = operations::manager::DEFAULT_WEBSOCKET_URL;
+    let test_address = operations::DEFAULT_L2_NEW_ACC1_ADDRESS;
+
+    let contracts = operations::try_deploy_contracts().await?;
+    println!("ERC20 contract at: {:#x}", contracts.erc20);
+
+    let ws_client = operations::websocket::EthWebSocketClient::connect(ws_url).await?;
+    println!("Connected successfully");
+
+    // ERC-20 `Transfer` events to the test address
+    let transfer_topic = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+    let recipient_topic = format!("0x{:0>64}", test_address.trim_start_matches("0x"));
+    let subscription_params = json!({
+        "logFilter": {
+            "address": format!("{:#x}", contracts.erc20),
+            "topics": [transfer_topic, null, [recipient_topic]]
+        },
+        "logInfo": true
+    });
+
+    let mut subscription: jsonrpsee::core::client::Subscription<Value> =
+        ws_client.subscribe("flashblocks", Some(subscription_params)).await?;
+    println!("Subscription created successfully");
+
+    let tx_hash = operations::erc20_balance_transfer(
+        operations::DEFAULT_L2_NETWORK_URL_FB,
+        U256::from(operations::GWEI),
+        None,
+        test_address,
+        contracts.erc20,
+        None,
+    )
+    .await?;
+    println!("Sent erc20 tx: {tx_hash}");
+
+    let found = tokio::time::timeout(WEB_SOCKET_TIMEOUT, async {
+        while let Some(Ok(notification)) = subscription.next().await {
+            match notification["type"].as_str() {
+                Some("transaction") => continue,
+                Some("logs") => {}
+                other => panic!("Unexpected event type {other:?}"),
+            }
+
+            let logs = notification["logs"].as_array().expect("logs should be an array");
+            for log in logs {
+                assert_eq!(log["topics"][0], transfer_topic);
+                assert_eq!(log["removed"], false);
+                if log["transactionHash"].as_str() == Some(tx_hash.as_str()) {
+                    return true;
+                }
+            }
+        }
+        false
+    })
+    .await
+    .unwrap_or(false);
+
+    assert!(found, "Expected the Transfer log of {tx_hash} in a logs event");
+    println!("Transfer log received via flashblocks subscription");
+
+    Ok(())
+}
+
+#[ignore = "Requires flashblocks WebSocket server with flashblocks subscription support"]
+#[tokio::test]
+async fn fb_pending_logs_subscription_test() -> Result<()> {
+    let ws_url = operations::manager::DEFAULT_WEBSOCKET_URL;
+    let test_address = operations::DEFAULT_L2_NEW_ACC1_ADDRESS;
+
+    let contracts = operations::try_deploy_contracts().await?;
+    println!("ERC20 contract at: {:#x}", contracts.erc20);
+
+    let ws_client = operations::websocket::EthWebSocketClient::connect(ws_url).await?;
+    println!("Connected successfully");
+
+    let filter = json!({ "address": format!("{:#x}", contracts.erc20) });
+    let mut subscription: jsonrpsee::core::client::Subscription<Value> =
+        ws_client.subscribe("pendingLogs", Some(filter)).await?;
+    println!("Subscription created successfully");
+
+    let tx_hash = operations::erc20_balance_transfer(
+        operations::DEFAULT_L2_NETWORK_URL_FB,
+        U256::from(operations::GWEI),
+        None,
+        test_address,
+        contracts.erc20,
+        None,
+    )
+    .await?;
+    println!("Sent erc20 tx: {tx_hash}");
+
+    let log = tokio::time::timeout(WEB_SOCKET_TIMEOUT, async {
+        while let Some(Ok(log)) = subscription.next().await {
+            if log["transactionHash"].as_str() == Some(tx_hash.as_str()) {
+                return Some(log);
+            }
+        }
+        None
+    })
+    .await
+    .ok()
+    .flatten()
+    .expect("Expected the pending log of the erc20 transfer");
+
+    assert_eq!(log["removed"], false);
+    assert!(log["flashblockIndex"].is_u64(), "pending log should carry the flashblock index");
+    println!("Pending log received in flashblock {}", log["flashblockIndex"]);
+
+    Ok(())
+}
+
+#[ignore = "Requires flashblocks WebSocket server with flashblocks subscription support"]
+#[tokio::test]
+async fn fb_resume_subscription_test() -> Result<()> {
+    let ws_url = operations::manager::DEFAULT_WEBSOCKET_URL;
+    let test_address = operations::DEFAULT_L2_NEW_ACC1_ADDRESS;
+
+    let subscription_params = json!({
+        "subTxFilter": { "subscribeAddresses": [test_address] }
+    });
+
+    // Wait for the transaction event of `tx_hash`, returning its cursor
+    async fn wait_for_tx(
+        subscription: &mut jsonrpsee::core::client::Subscription<Value>,
+        tx_hash: &str,
+    ) -> Option<Value> {
+        tokio::time::timeout(WEB_SOCKET_TIMEOUT, async {
+            while let Some(Ok(notification)) = subscription.next().await {
+                if notification["type"] == "transaction"
+                    && notification["transaction"]["txHash"].as_str() == Some(tx_hash)
+                {
+                    return Some(notification["cursor"].clone());
+                }
+            }
+            None
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+
+    let ws_client = operations::websocket::EthWebSocketClient::connect(ws_url).await?;
+    let mut subscription: jsonrpsee::core::client::Subscription<Value> =
+        ws_client.subscribe("flashblocks", Some(subscription_params.clone())).await?;
+    println!("Subscription created successfully");
+
+    let first_tx = operations::native_balance_transfer(
+        operations::DEFAULT_L2_NETWORK_URL_FB,
+        U256::from(operations::GWEI),
+        test_address,
+        true,
+    )
+    .await?;
+    let cursor = wait_for_tx(&mut subscription, &first_tx)
+        .await
+        .expect("Expected the first tx in the flashblocks subscription");
+    println!("Received {first_tx} at cursor {cursor}");
+    drop(subscription);
+    drop(ws_client);
+
+    // Sent while disconnected
+    let second_tx = operations::native_balance_transfer(
+        operations::DEFAULT_L2_NETWORK_URL_FB,
+        U256::from(operations::GWEI),
+        test_address,
+        true,
+    )
+    .await?;
+    println!("Sent {second_tx} while disconnected");
+
+    let mut resume_params = subscription_params;
+    resume_params["resumeFrom"] = cursor.clone();
+    let ws_client = operations::websocket::EthWebSocketClient::connect(ws_url).await?;
+    let mut subscription: jsonrpsee::core::client::Subscription<Value> =
+        ws_client.subscribe("flashblocks", Some(resume_params)).await?;
+    println!("Subscription resumed from {cursor}");
+
+    let resumed_cursor = wait_for_tx(&mut subscription, &second_tx)
+        .await
+        .expect("Expected the tx sent while disconnected to be replayed");
+    assert!(
+        resumed_cursor["blockNumber"].as_u64() >= cursor["blockNumber"].as_u64(),
+        "Replayed cursor {resumed_cursor} should be after {cursor}"
+    );
+    println!("Replayed {second_tx} at cursor {resumed_cursor}");
+
+    Ok(())
+}
+
+#[ignore = "Requires flashblocks WebSocket server with flashblocks subscription support"]
+#[tokio::test]
+async fn fb_subscription_quota_test() -> Result<()> {
+    // Default of --xlayer.flashblocks-subscription-max-per-connection
+    const MAX_PER_CONNECTION: usize = 128;
+
+    let ws_url = operations::manager::DEFAULT_WEBSOCKET_URL;
+    let ws_client = operations::websocket::EthWebSocketClient::connect(ws_url).await?;
+    println!("Connected successfully");
+
+    let subscription_params = json!({ "headerInfo": true });
+    let mut subscriptions = Vec::with_capacity(MAX_PER_CONNECTION);
+    for _ in 0..MAX_PER_CONNECTION {
+        let subscription: jsonrpsee::core::client::Subscription<Value> =
+            ws_client.subscribe("flashblocks", Some(subscription_params.clone())).await?;
+        subscriptions.push(subscription);
+    }
+    println!("Created {} subscriptions", subscriptions.len());
+
+    let _ = ws_client
+        .subscribe("flashblocks", Some(subscription_params.clone()))
+        .await
+        .expect_err("Expected subscription beyond the connection quota to be rejected");
+
+    // Unsubscribing frees a slot
+    subscriptions.pop().expect("Expected a subscription").unsubscribe().await?;
+    let mut subscription: jsonrpsee::core::client::Subscription<Value> =
+        ws_client.subscribe("flashblocks", Some(subscription_params)).await?;
+    let _ = tokio::time::timeout(WEB_SOCKET_TIMEOUT, subscription.next())
+        .await?
+        .expect("Expected an event on the new subscription")?;
+
+    Ok(())
+}
+
+#[ignore = "Requires flashblocks WebSocket server with flashblocks subscription support"]
+#[tokio::test]
+async fn fb_state_diff_subscription_test() -> Result<()> {
+    let ws_url = operations::manager::DEFAULT_WEBSOCKET_URL;
+    let test_address = operations::DEFAULT_L2_NEW_ACC1_ADDRESS;
+
+    let ws_client = operations::websocket::EthWebSocketClient::connect(ws_url).await?;
+    println!("Connected successfully");
+
+    let subscription_params = json!({
+        "subTxFilter": {
+            "subscribeAddresses": [test_address],
+            "stateDiff": true
+        }
+    });
+    let mut subscription: jsonrpsee::core::client::Subscription<Value> =
+        ws_client.subscribe("flashblocks", Some(subscription_params)).await?;
+    println!("Subscription created successfully");
+
+    let tx_hash = operations::native_balance_transfer(
+        operations::DEFAULT_L2_NETWORK_URL_FB,
+        U256::from(operations::GWEI),
+        test_address,
+        true,
+    )
+    .await?;
+    println!("Sent tx: {tx_hash}");
+
+    let state_diff = tokio::time::timeout(WEB_SOCKET_TIMEOUT, async {
+        while let Some(Ok(notification)) = subscription.next().await {
+            let tx = &notification["transaction"];
+            if notification["type"] == "transaction"
+                && tx["txHash"].as_str() == Some(tx_hash.as_str())
+            {
+                return Some(tx["stateDiff"].clone());
+            }
+        }
+        None
+    })
+    .await
+    .ok()
+    .flatten()
+    .expect("Expected the transfer in the flashblocks subscription");
+
+    let account = state_diff
+        .as_object()
+        .and_then(|diff| {
+            diff.iter().find(|(address, _)| address.eq_ignore_ascii_case(test_address))
+        })
+        .map(|(_, account)| account)
+        .expect("state diff should contain the recipient");
+    let from: U256 = serde_json::from_value(account["balance"]["from"].clone())?;
+    let to: U256 = serde_json::from_value(account["balance"]["to"].clone())?;
+    assert_eq!(
+        to - from,
+        U256::from(operations::GWEI),
+        "recipient balance should grow by the value"
+    );
+    println!("Recipient balance {from} -> {to}");
+
+    Ok(())
+}
+
+#[ignore = "Requires flashblocks WebSocket server with flashblocks subscription support"]
+#[tokio::test]
+async fn fb_call_trace_subscription_test() -> Result<()> {
+    let ws_url = operations::manager::DEFAULT_WEBSOCKET_URL;
+    let test_address = operations::DEFAULT_L2_NEW_ACC1_ADDRESS;
+
+    let ws_client = operations::websocket::EthWebSocketClient::connect(ws_url).await?;
+    println!("Connected successfully");
+
+    let subscription_params = json!({
+        "subTxFilter": {
+            "subscribeAddresses": [test_address],
+            "callTrace": true
+        }
+    });
+    let mut subscription: jsonrpsee::core::client::Subscription<Value> =
+        ws_client.subscribe("flashblocks", Some(subscription_params)).await?;
+    println!("Subscription created successfully");
+
+    let tx_hash = operations::native_balance_transfer(
+        operations::DEFAULT_L2_NETWORK_URL_FB,
+        U256::from(operations::GWEI),
+        test_address,
+        true,
+    )
+    .await?;
+    println!("Sent tx: {tx_hash}");
+
+    let call_trace = tokio::time::timeout(WEB_SOCKET_TIMEOUT, async {
+        while let Some(Ok(notification)) = subscription.next().await {
+            let tx = &notification["transaction"];
+            if notification["type"] == "transaction"
+                && tx["txHash"].as_str() == Some(tx_hash.as_str())
+            {
+                return Some(tx["callTrace"].clone());
+            }
+        }
+        None
+    })
+    .await
+    .ok()
+    .flatten()
+    .expect("Expected the transfer in the flashblocks subscription");
+
+    assert_eq!(call_trace["type"], "CALL");
+    let to = call_trace["to"].as_str().expect("call trace should have a target");
+    assert!(to.eq_ignore_ascii_case(test_address), "call target {to} should be the recipient");
+    let value: U256 = serde_json::from_value(call_trace["value"].clone())?;
+    assert_eq!(value, U256::from(operations::GWEI));
+    println!("Call trace received: {call_trace}");
+
+    Ok(())
+}
+
+#[ignore = "Requires flashblocks WebSocket server with flashblocks subscription support"]
+#[tokio::test]
+async fn fb_internal_call_trace_subscription_test() -> Result<()> {
+    let ws_url = operations::manager::DEFAULT_WEBSOCKET_URL;
+
+    // ContractA.triggerCall() calls into ContractB, so ContractB only appears as an inner frame
+    let contracts = operations::try_deploy_contracts().await?;
+    let contract_a = format!("{:#x}", contracts.contract_a);
+    let contract_b = format!("{:#x}", contracts.contract_b);
+    println!("ContractA at: {contract_a}, ContractB at: {contract_b}");
+
+    let ws_client = operations::websocket::EthWebSocketClient::connect(ws_url).await?;
+    println!("Connected successfully");
+
+    let subscription_params = json!({
+        "subTxFilter": {
+            "subscribeAddresses": [contract_b],
+            "callTrace": true
+        }
+    });
+    let mut subscription: jsonrpsee::core::client::Subscription<Value> =
+        ws_client.subscribe("flashblocks", Some(subscription_params)).await?;
+    println!("Subscription created successfully");
+
+    sol! {
+        function triggerCall() external;
+    }
+    let tx_request = TransactionRequest::default()
+        .to(contracts.contract_a)
+        .with_gas_limit(200_000)
+        .with_max_fee_per_gas(20_000_000_000u128)
+        .with_max_priority_fee_per_gas(2_000_000_000u128)
+        .with_input(triggerCallCall {}.abi_encode());
+
+    let (tx_hash, receipt) = operations::sign_and_send_transaction(
+        operations::DEFAULT_L2_NETWORK_URL_FB,
+        operations::DEFAULT_RICH_PRIVATE_KEY,
+        tx_request,
+    )
+    .await?;
+    assert_eq!(receipt["status"], "0x1", "Contract call should succeed");
+    println!("Sent contract call tx: {tx_hash}");
+
+    let call_trace = tokio::time::timeout(WEB_SOCKET_TIMEOUT, async {
+        while let Some(Ok(notification)) = subscription.next().await {
+            let tx = &notification["transaction"];
+            if notification["type"] == "transaction"
+                && tx["txHash"].as_str().is_some_and(|hash| hash.eq_ignore_ascii_case(&tx_hash))
+            {
+                return Some(tx["callTrace"].clone());
+            }
+        }
+        None
+    })
+    .await
+    .ok()
+    .flatten()
+    .expect("Expected the contract call in the flashblocks subscription");
+
+    let to = call_trace["to"].as_str().expect("call trace should have a target");
+    assert!(to.eq_ignore_ascii_case(&contract_a), "top-level call target {to} should be ContractA");
+
+    let calls = call_trace["calls"].as_array().expect("call trace should have inner calls");
+    let inner = calls
+        .iter()
+        .find(|call| call["to"].as_str().is_some_and(|to| to.eq_ignore_ascii_case(&contract_b)))
+        .expect("call trace should contain the internal call into ContractB");
+    assert_eq!(inner["type"], "CALL");
+    assert!(
+        inner["from"].as_str().is_some_and(|from| from.eq_ignore_ascii_case(&contract_a)),
+        "internal call should originate from ContractA"
+    );
+    println!("Call trace received: {call_trace}");
+
+    Ok(())
+}
+
 #[ignore = "Requires flashblocks WebSocket server with flashblocks subscription support"]
 #[tokio::test]
 async fn fb_benchmark_new_heads_subscription_test() -> Result<()> {